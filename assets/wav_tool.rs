@@ -25,7 +25,7 @@ fn help() -> process::ExitCode {
 fn trim(rc_clip: stream::Clip, duration: f32) -> stream::Clip {
     {
         let mut clip: std::cell::RefMut<'_, Vec<f32>> = rc_clip.borrow_mut();
-        clip.truncate((duration * stream::SAMPLE_RATE as f32) as usize);
+        clip.truncate((duration * stream::DEFAULT_SAMPLE_RATE as f32) as usize);
     }
     rc_clip
 }
@@ -41,7 +41,7 @@ fn scale(rc_clip: stream::Clip, volume: f32) -> stream::Clip {
 }
 
 fn fade(rc_clip: stream::Clip, duration: f32) -> stream::Clip {
-    let fade_len = (duration * stream::SAMPLE_RATE as f32) as usize;
+    let fade_len = (duration * stream::DEFAULT_SAMPLE_RATE as f32) as usize;
     {
         let mut clip = rc_clip.borrow_mut();
         assert!(fade_len < clip.len(), "The specified fade duration is longer than the clip");
@@ -65,7 +65,7 @@ fn main() -> process::ExitCode {
     let output_file = &args[2];
     let cmd = &args[3];
 
-    let input_samples = wav::read_wav_file(&input_file).unwrap();
+    let input_samples = wav::read_wav_file(&input_file, stream::DEFAULT_SAMPLE_RATE, 1).unwrap();
 
     let transformed: stream::Clip = match cmd.as_str() {
         "trim" => {
@@ -86,7 +86,8 @@ fn main() -> process::ExitCode {
         }
     };
 
-    wav::write_wav_file(&transformed, &output_file).expect("Failed to write to output file");
+    wav::write_wav_file(&transformed, &output_file, stream::DEFAULT_SAMPLE_RATE, 1)
+        .expect("Failed to write to output file");
 
     process::ExitCode::SUCCESS
 }
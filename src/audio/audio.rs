@@ -7,49 +7,266 @@
 //!
 //! An AudioSink writes audio output to computer speakers or external sources over bluetooth/USB.
 //!
+//! Audio blocks don't have to use hardware. See the backend module for devices which produce
+//! silence or read and write wav files instead.
+//!
 //! AudioSource \[Source\]:
 //!     Required parameters:
 //!         name: Anything
 //!         type: "AudioSource"
 //!         device: The name of the audio device on your computer. Leave empty and the resulting
-//!                 error message will print a list of available devices.
+//!                 error message will print a list of available devices. "null", "null:paced"
+//!                 and "file:<path.wav>" select the null and file backends instead.
 //!         output_channel: The output channel.
+//!     Optional parameters:
+//!         render_file: A wav file to play in place of the device when rendering offline or when
+//!                      the file backend is selected with `--audio-backend file`.
+//!         num_channels: The number of audio channels to read from the device. Defaults to the
+//!                       project's num_channels. The output stream keeps this many channels.
+//!         latency: The round-trip latency of the device, as a number of samples or milliseconds
+//!                  like "12ms". Defaults to the project's latency. Only applies to hardware
+//!                  devices. Run `looper calibrate` to measure it.
 //!
 //! AudioSink \[Sink\]:
 //!     Required parameters:
 //!         name: Anything
 //!         type: "AudioSink"
 //!         device: The name of the audio device on your computer. Leave empty and the resulting
-//!                 error message will print a list of available devices.
+//!                 error message will print a list of available devices. "null", "null:paced"
+//!                 and "file:<path.wav>" select the null and file backends instead.
 //!         input_channel: The output channel.
+//!     Optional parameters:
+//!         render_file: The wav file to write when the file backend is selected with
+//!                      `--audio-backend file`. Defaults to "<name>.wav".
+//!         num_channels: The number of audio channels to write to the device. Defaults to the
+//!                       project's num_channels. The input stream is converted to match.
 
+extern crate error;
 extern crate portaudio;
 
+extern crate backend;
 extern crate block;
+extern crate calibrate;
 extern crate config;
+extern crate registry;
 extern crate stream;
 extern crate tempo;
 
+use std::cell::OnceCell;
+use std::rc::Rc;
+
 use portaudio::{stream_flags::Flags, Blocking, DeviceIndex, DeviceInfo, Flow, PortAudio};
 
 type PaStreamInput = portaudio::Input<stream::Sample>;
 type PaStreamOutput = portaudio::Output<stream::Sample>;
 
 /// Get a new PortAudio instance.
-pub fn pa_get() -> Result<PortAudio, ()> {
+pub fn pa_get() -> Result<PortAudio, error::LooperError> {
     let pa = log::unwrap_abort!(PortAudio::new());
     println!("PortAudio initialized.");
 
     Ok(pa)
 }
 
+/// Get the shared PortAudio instance, initializing it on first use.
+fn pa_get_shared(pa: &OnceCell<PortAudio>) -> Result<&PortAudio, error::LooperError> {
+    if pa.get().is_none() {
+        let _ = pa.set(pa_get()?);
+    }
+    Ok(pa.get().unwrap())
+}
+
+/// Register the AudioSource and AudioSink blocks. PortAudio is only initialized once one of them
+/// is created, so projects without audio blocks never touch the audio hardware.
+pub fn register(registry: &mut registry::Registry) {
+    register_backend(registry, None);
+}
+
+/// Register the AudioSource and AudioSink blocks, with every device using the given backend
+/// regardless of its device name.
+///
+/// # Arguments
+///
+/// * backend - The backend to use for every device, or None to select it by device name.
+pub fn register_backend(registry: &mut registry::Registry, backend: Option<backend::Backend>) {
+    let pa = Rc::new(OnceCell::<PortAudio>::new());
+
+    let source_pa = pa.clone();
+    registry.register_source("AudioSource", move |config, stream_catalog| {
+        let num_channels = device_channels(config, stream_catalog)?;
+        let device = open_input(config, stream_catalog, backend, num_channels, &source_pa)?;
+        AudioSource::new(config, stream_catalog, device, num_channels)
+    });
+    registry.register_sink("AudioSink", move |config, stream_catalog| {
+        let num_channels = device_channels(config, stream_catalog)?;
+        let device = open_output(config, stream_catalog, backend, num_channels, &pa)?;
+        AudioSink::new(config, stream_catalog, device, num_channels)
+    });
+}
+
+/// Register the AudioSource and AudioSink blocks for offline rendering. Sources play their
+/// render_file, or silence, and every sink is mixed into a single clip with the project's number
+/// of audio channels.
+///
+/// # Arguments
+///
+/// * mix - The clip to mix every AudioSink into.
+pub fn register_render(registry: &mut registry::Registry, mix: &stream::Clip) {
+    let mix = mix.clone();
+    registry.register_source("AudioSource", |config, stream_catalog| {
+        let num_channels = device_channels(config, stream_catalog)?;
+        let backend = Some(backend::Backend::File);
+        let device = open_input(config, stream_catalog, backend, num_channels, &OnceCell::new())?;
+        AudioSource::new(config, stream_catalog, device, num_channels)
+    });
+    registry.register_sink("AudioSink", move |config, stream_catalog| {
+        let device = Box::new(backend::MixOutput::new(&mix));
+        AudioSink::new(config, stream_catalog, device, stream_catalog.num_channels())
+    });
+}
+
+/// Measure the round-trip latency of the audio hardware by playing clicks through the project's
+/// first AudioSink and listening for them on its first AudioSource [samples].
+///
+/// # Arguments
+///
+/// * project - The project containing the audio blocks.
+/// * backend - The backend to use for both devices, or None to select it by device name.
+pub fn calibrate(
+    project: &config::ProjectConfig,
+    backend: Option<backend::Backend>,
+) -> Result<usize, error::LooperError> {
+    let find = |block_type: &str| project.blocks.iter().find(|b| b.block_type == block_type);
+    let source = log::opt_abort_msg!(find("AudioSource"), "Calibration requires an AudioSource");
+    let sink = log::opt_abort_msg!(find("AudioSink"), "Calibration requires an AudioSink");
+
+    // Clicks are mono, so there's no need to open every channel.
+    let stream_catalog =
+        stream::StreamCatalog::with_format(project.sample_rate, project.buffer_size, 1);
+    let pa = OnceCell::<PortAudio>::new();
+    let mut input = open_input(source, &stream_catalog, backend, 1, &pa)?;
+    let mut output = open_output(sink, &stream_catalog, backend, 1, &pa)?;
+
+    println!("Listening for clicks from \"{}\" on \"{}\"...", sink.name, source.name);
+    let latency = calibrate::measure(
+        input.as_mut(),
+        output.as_mut(),
+        project.sample_rate,
+        project.buffer_size,
+    );
+    input.cleanup();
+    output.cleanup();
+
+    let latency = latency?;
+    println!(
+        "Measured latency: {} samples ({:.1}ms)",
+        latency,
+        latency as f32 * 1000.0 / project.sample_rate as f32
+    );
+    Ok(latency)
+}
+
+/// Get the number of audio channels a block's device should be opened with.
+fn device_channels(
+    config: &config::BlockConfig,
+    stream_catalog: &stream::StreamCatalog,
+) -> Result<usize, error::LooperError> {
+    let num_channels =
+        config.get_i32_opt("num_channels", &(stream_catalog.num_channels() as i32))?;
+    log::abort_if_msg!(num_channels < 1, "\"num_channels\" must be positive");
+    Ok(num_channels as usize)
+}
+
+/// Select the backend and backend-specific device name for a block.
+fn select_backend(
+    config: &config::BlockConfig,
+    backend: Option<backend::Backend>,
+) -> Result<(backend::Backend, String), error::LooperError> {
+    let (device_backend, name) = backend::Backend::from_device(config.get_str("device")?);
+    match backend {
+        Some(backend) if backend != device_backend => Ok((backend, String::new())),
+        _ => Ok((device_backend, name.to_owned())),
+    }
+}
+
+/// Open the input device for an AudioSource.
+fn open_input(
+    config: &config::BlockConfig,
+    stream_catalog: &stream::StreamCatalog,
+    backend: Option<backend::Backend>,
+    num_channels: usize,
+    pa: &OnceCell<PortAudio>,
+) -> Result<Box<dyn backend::InputDevice>, error::LooperError> {
+    let (backend, name) = select_backend(config, backend)?;
+    let sample_rate = stream_catalog.sample_rate();
+    Ok(match backend {
+        backend::Backend::PortAudio => Box::new(PaInput::new(
+            &config.name,
+            &name,
+            pa_get_shared(pa)?,
+            sample_rate,
+            stream_catalog.buffer_size(),
+            num_channels,
+        )?),
+        backend::Backend::Null => {
+            Box::new(backend::NullInput::new(false, sample_rate, num_channels))
+        }
+        backend::Backend::NullPaced => {
+            Box::new(backend::NullInput::new(true, sample_rate, num_channels))
+        }
+        backend::Backend::File => {
+            let filename = match name.as_str() {
+                "" => config.get_str_opt("render_file", "")?,
+                _ => &name,
+            };
+            Box::new(backend::FileInput::new(filename, sample_rate, num_channels)?)
+        }
+    })
+}
+
+/// Open the output device for an AudioSink.
+fn open_output(
+    config: &config::BlockConfig,
+    stream_catalog: &stream::StreamCatalog,
+    backend: Option<backend::Backend>,
+    num_channels: usize,
+    pa: &OnceCell<PortAudio>,
+) -> Result<Box<dyn backend::OutputDevice>, error::LooperError> {
+    let (backend, name) = select_backend(config, backend)?;
+    let sample_rate = stream_catalog.sample_rate();
+    Ok(match backend {
+        backend::Backend::PortAudio => Box::new(PaOutput::new(
+            &config.name,
+            &name,
+            pa_get_shared(pa)?,
+            sample_rate,
+            stream_catalog.buffer_size(),
+            num_channels,
+        )?),
+        backend::Backend::Null => {
+            Box::new(backend::NullOutput::new(false, sample_rate, num_channels))
+        }
+        backend::Backend::NullPaced => {
+            Box::new(backend::NullOutput::new(true, sample_rate, num_channels))
+        }
+        backend::Backend::File => {
+            let default_filename = format!("{}.wav", config.name);
+            let filename = match name.as_str() {
+                "" => config.get_str_opt("render_file", &default_filename)?,
+                _ => &name,
+            };
+            Box::new(backend::FileOutput::new(filename, sample_rate, num_channels))
+        }
+    })
+}
+
 /// Get the audio device with the associated name.
 fn get_device_index<'a>(
     pa: &'a PortAudio,
     name: &str,
     is_input: bool,
     is_output: bool,
-) -> Result<(DeviceIndex, DeviceInfo<'a>), ()> {
+) -> Result<(DeviceIndex, DeviceInfo<'a>), error::LooperError> {
     for device in log::unwrap_abort!(pa.devices()) {
         let (device_id, device_info) = log::unwrap_abort!(device);
 
@@ -70,57 +287,59 @@ fn get_device_index<'a>(
     }
 
     // If we reach this point, we didn't find a device.
-    println!("Could not find audio device: \"{}\"", name);
+    let msg = format!("Could not find audio device: \"{}\"", name);
+    println!("{}", msg);
     println!("Available devices:");
     for device in log::unwrap_abort!(pa.devices()) {
         let (_, device_info) = log::unwrap_abort!(device);
         println!("  - \"{}\"", device_info.name);
     }
 
-    Err(())
+    Err(error::LooperError::Abort { cause: msg })
 }
 
-// Audio Blocks
+// PortAudio Devices
 
-/// A valid Source Block which derives input from system audio hardware. This could be an external
-/// USB microphone, an audio jack, or a built-in microphone.
-pub struct AudioSource {
+/// An input device which reads from system audio hardware.
+struct PaInput {
     /// The block name.
     name: String,
 
+    /// The number of interleaved audio channels.
+    num_channels: usize,
+
+    /// The number of times the stream has overflowed.
+    overflows: usize,
+
     /// The input [portaudio] stream.
     pa_stream: portaudio::Stream<Blocking<<PaStreamInput as Flow>::Buffer>, PaStreamInput>,
-
-    /// The output [looper] stream buffer.
-    stream: stream::Stream,
 }
 
-/// A valid Sink Block which writes a stream to a hardware speaker.
-pub struct AudioSink {
+/// An output device which writes to system audio hardware.
+struct PaOutput {
     /// The block name.
     name: String,
 
+    /// The number of interleaved audio channels.
+    num_channels: usize,
+
+    /// The number of times the stream has underflowed.
+    underflows: usize,
+
     /// The output [portaudio] stream.
     pa_stream: portaudio::Stream<Blocking<<PaStreamOutput as Flow>::Buffer>, PaStreamOutput>,
-
-    /// The input [looper] stream buffer.
-    stream: stream::Stream,
 }
 
-impl AudioSource {
-    /// Initialize a PortAudio AudioSource block.
-    pub fn new(
-        config: &config::BlockConfig,
-        stream_catalog: &mut stream::StreamCatalog,
+impl PaInput {
+    /// Open a PortAudio input device.
+    fn new(
+        name: &str,
+        device_name: &str,
         pa: &PortAudio,
-    ) -> Result<Self, ()> {
-        // Read in parameters.
-        let output_stream = config.get_str("output_channel")?;
-        let device_name = config.get_str("device")?;
-
-        // Load streams.
-        let stream = stream_catalog.create_source(output_stream)?;
-
+        sample_rate: i32,
+        buffer_size: usize,
+        num_channels: usize,
+    ) -> Result<Self, error::LooperError> {
         // PortAudio wizardry follows...
 
         // Get the audio device.
@@ -130,7 +349,7 @@ impl AudioSource {
         // PortAudio stream parameters.
         let input_params = portaudio::StreamParameters::<stream::Sample>::new(
             device_index,
-            1,    /* channel count */
+            num_channels as i32,
             true, /* interleaved */
             device_info.default_low_output_latency,
         );
@@ -138,8 +357,8 @@ impl AudioSource {
         // Open the audio stream.
         let settings = portaudio::InputStreamSettings {
             params: input_params,
-            sample_rate: 44100.0,
-            frames_per_buffer: stream::SAMPLES_PER_BUFFER as u32,
+            sample_rate: sample_rate as f64,
+            frames_per_buffer: buffer_size as u32,
             flags: Flags::NO_FLAG,
         };
         let mut pa_stream = log::unwrap_abort_msg!(
@@ -148,19 +367,21 @@ impl AudioSource {
         );
         log::unwrap_abort!(pa_stream.start());
 
-        Ok(AudioSource {
-            name: config.name.to_owned(),
+        Ok(PaInput {
+            name: name.to_owned(),
+            num_channels: num_channels,
+            overflows: 0,
             pa_stream: pa_stream,
-            stream: stream,
         })
     }
 
-    /// Block until some number of samples is available to read.
-    fn get_available_samples(&self) -> usize {
+    /// Block until some number of frames is available to read.
+    fn get_available_frames(&mut self) -> usize {
         match self.pa_stream.read_available() {
             Ok(available) => match available {
                 portaudio::StreamAvailable::Frames(frames) => return frames as usize,
                 _ => {
+                    self.overflows += 1;
                     println!("Stream {} has overflowed", self.name);
                     return 0;
                 }
@@ -173,20 +394,16 @@ impl AudioSource {
     }
 }
 
-impl AudioSink {
-    /// Initialize a PortAudio AudioSink block.
-    pub fn new(
-        config: &config::BlockConfig,
-        stream_catalog: &stream::StreamCatalog,
+impl PaOutput {
+    /// Open a PortAudio output device.
+    fn new(
+        name: &str,
+        device_name: &str,
         pa: &PortAudio,
-    ) -> Result<Self, ()> {
-        // Read in parameters.
-        let input_stream = config.get_str("input_channel")?;
-        let device_name = config.get_str("device")?;
-
-        // Load streams.
-        let stream = stream_catalog.bind_sink(input_stream)?;
-
+        sample_rate: i32,
+        buffer_size: usize,
+        num_channels: usize,
+    ) -> Result<Self, error::LooperError> {
         // PortAudio wizardry follows...
 
         // Get the audio device.
@@ -196,7 +413,7 @@ impl AudioSink {
         // PortAudio stream parameters.
         let output_params = portaudio::StreamParameters::<stream::Sample>::new(
             device_index,
-            1,    /* channel count */
+            num_channels as i32,
             true, /* interleaved */
             device_info.default_low_output_latency,
         );
@@ -204,8 +421,8 @@ impl AudioSink {
         // Open the audio stream.
         let settings = portaudio::OutputStreamSettings {
             params: output_params,
-            sample_rate: 44100.0,
-            frames_per_buffer: stream::SAMPLES_PER_BUFFER as u32,
+            sample_rate: sample_rate as f64,
+            frames_per_buffer: buffer_size as u32,
             flags: Flags::NO_FLAG,
         };
         let mut pa_stream = log::unwrap_abort_msg!(
@@ -214,19 +431,21 @@ impl AudioSink {
         );
         log::unwrap_abort!(pa_stream.start());
 
-        Ok(AudioSink {
-            name: config.name.to_owned(),
+        Ok(PaOutput {
+            name: name.to_owned(),
+            num_channels: num_channels,
+            underflows: 0,
             pa_stream: pa_stream,
-            stream: stream,
         })
     }
 
-    /// Block until some number of samples is available to write.
-    fn get_available_samples(&self) -> usize {
+    /// Block until some number of frames is available to write.
+    fn get_available_frames(&mut self) -> usize {
         match self.pa_stream.write_available() {
             Ok(available) => match available {
                 portaudio::StreamAvailable::Frames(frames) => return frames as usize,
                 _ => {
+                    self.underflows += 1;
                     println!("Stream {} has underflowed", self.name);
                     return 0;
                 }
@@ -239,21 +458,19 @@ impl AudioSink {
     }
 }
 
-impl block::Source for AudioSource {
-    fn read(&mut self, _: &block::PlaybackState) {
-        let mut stream = (*self.stream).borrow_mut();
-
+impl backend::InputDevice for PaInput {
+    fn read(&mut self, buffer: &mut stream::RawStream) {
         let mut index: usize = 0;
-        while index < stream::SAMPLES_PER_BUFFER {
-            // Get the number of available samples and read them in from PA.
-            let samples_remaining = stream::SAMPLES_PER_BUFFER - index;
-            let num_samples = std::cmp::min(self.get_available_samples(), samples_remaining);
+        while index < buffer.len() {
+            // Get the number of available frames and read them in from PA.
+            let frames_remaining = (buffer.len() - index) / self.num_channels;
+            let num_frames = std::cmp::min(self.get_available_frames(), frames_remaining);
 
-            // Read samples from the buffer.
-            match self.pa_stream.read(num_samples as u32) {
+            // Read samples from the buffer. Each frame holds one sample per channel.
+            match self.pa_stream.read(num_frames as u32) {
                 Ok(samples) => {
                     for i in 0..samples.len() {
-                        stream[i + index] = samples[i];
+                        buffer[i + index] = samples[i];
                     }
                 }
                 Err(e) => {
@@ -262,29 +479,36 @@ impl block::Source for AudioSource {
                 }
             };
 
-            index += num_samples;
+            index += num_frames * self.num_channels;
         }
     }
 
     fn is_blocking_io(&self) -> bool {
         return true;
     }
-}
 
-impl block::Sink for AudioSink {
-    fn write(&mut self, _: &block::PlaybackState) {
-        let stream = (*self.stream).borrow();
+    fn overflows(&self) -> usize {
+        self.overflows
+    }
 
-        let mut index: usize = 0;
-        while index < stream::SAMPLES_PER_BUFFER {
-            // Get the number of available samples and read them in from PA.
-            let samples_remaining = stream::SAMPLES_PER_BUFFER - index;
-            let num_samples = std::cmp::min(self.get_available_samples(), samples_remaining);
+    fn has_latency(&self) -> bool {
+        return true;
+    }
+}
 
-            // Write samples out to the buffer.
-            match self.pa_stream.write(num_samples as u32, |output| {
+impl backend::OutputDevice for PaOutput {
+    fn write(&mut self, buffer: &stream::RawStream) {
+        let mut index: usize = 0;
+        while index < buffer.len() {
+            // Get the number of available frames and write them out to PA.
+            let frames_remaining = (buffer.len() - index) / self.num_channels;
+            let num_frames = std::cmp::min(self.get_available_frames(), frames_remaining);
+            let num_samples = num_frames * self.num_channels;
+
+            // Write samples out to the buffer. Each frame holds one sample per channel.
+            match self.pa_stream.write(num_frames as u32, |output| {
                 for i in 0..num_samples {
-                    output[i] = stream[i + index];
+                    output[i] = buffer[i + index];
                 }
             }) {
                 Ok(_) => (),
@@ -301,4 +525,144 @@ impl block::Sink for AudioSink {
     fn is_blocking_io(&self) -> bool {
         return true;
     }
+
+    fn underflows(&self) -> usize {
+        self.underflows
+    }
+}
+
+// Audio Blocks
+
+/// A valid Source Block which derives input from an audio device. This could be an external USB
+/// microphone, an audio jack, a built-in microphone, or one of the non-hardware backends.
+pub struct AudioSource {
+    /// The input device.
+    device: Box<dyn backend::InputDevice>,
+
+    /// The output [looper] stream buffer.
+    stream: stream::Stream,
+}
+
+/// A valid Sink Block which writes a stream to an audio device.
+pub struct AudioSink {
+    /// The output device.
+    device: Box<dyn backend::OutputDevice>,
+
+    /// The input [looper] stream buffer.
+    stream: stream::Stream,
+
+    /// The number of audio channels written to the device.
+    num_channels: usize,
+
+    /// The input stream converted to the device's number of audio channels.
+    buffer: stream::RawStream,
+}
+
+impl AudioSource {
+    /// Initialize an AudioSource block.
+    pub fn new(
+        config: &config::BlockConfig,
+        stream_catalog: &mut stream::StreamCatalog,
+        device: Box<dyn backend::InputDevice>,
+        num_channels: usize,
+    ) -> Result<Self, error::LooperError> {
+        // Read in parameters.
+        let output_stream = config.get_str("output_channel")?;
+
+        // Load streams.
+        let stream = stream_catalog.create_source_with_channels(output_stream, num_channels)?;
+
+        // Hardware input arrives late, so let the blocks recording it know by how much.
+        if device.has_latency() {
+            let latency = config.get_latency_opt("latency", stream_catalog.sample_rate())?;
+            stream_catalog.set_captured(output_stream, latency);
+        }
+
+        Ok(AudioSource {
+            device: device,
+            stream: stream,
+        })
+    }
+}
+
+impl AudioSink {
+    /// Initialize an AudioSink block.
+    pub fn new(
+        config: &config::BlockConfig,
+        stream_catalog: &stream::StreamCatalog,
+        device: Box<dyn backend::OutputDevice>,
+        num_channels: usize,
+    ) -> Result<Self, error::LooperError> {
+        // Read in parameters.
+        let input_stream = config.get_str("input_channel")?;
+
+        // Load streams.
+        let stream = stream_catalog.bind_sink(input_stream)?;
+
+        Ok(AudioSink {
+            device: device,
+            stream: stream,
+            num_channels: num_channels,
+            buffer: vec![stream::ZERO; stream_catalog.buffer_size() * num_channels],
+        })
+    }
+}
+
+impl block::Source for AudioSource {
+    fn read(&mut self, _: &block::PlaybackState) {
+        let mut stream = (*self.stream).borrow_mut();
+        self.device.read(&mut stream);
+    }
+
+    fn is_blocking_io(&self) -> bool {
+        self.device.is_blocking_io()
+    }
+
+    fn overflows(&self) -> usize {
+        self.device.overflows()
+    }
+
+    fn count_in(&mut self) {
+        // Nothing listens during the count-in, but the device still has to be read.
+        let mut stream = (*self.stream).borrow_mut();
+        self.device.read(&mut stream);
+    }
+
+    fn cleanup(&mut self) {
+        self.device.cleanup();
+    }
+}
+
+impl block::Sink for AudioSink {
+    fn write(&mut self, state: &block::PlaybackState) {
+        let stream = (*self.stream).borrow();
+        self.buffer.fill(stream::ZERO);
+        stream::mix(
+            &stream,
+            stream.len() / state.tempo.buffer_size,
+            &mut self.buffer,
+            self.num_channels,
+            &[],
+        );
+        self.device.write(&self.buffer);
+    }
+
+    fn is_blocking_io(&self) -> bool {
+        self.device.is_blocking_io()
+    }
+
+    fn underflows(&self) -> usize {
+        self.device.underflows()
+    }
+
+    fn count_in(&mut self, clicks: &stream::RawStream) {
+        self.buffer.fill(stream::ZERO);
+        let clicks_channels = clicks.len() / (self.buffer.len() / self.num_channels);
+        stream::mix(clicks, clicks_channels, &mut self.buffer, self.num_channels, &[]);
+        self.device.write(&self.buffer);
+    }
+
+    fn cleanup(&mut self) {
+        self.device.cleanup();
+    }
 }
@@ -0,0 +1,426 @@
+//! Audio device backends.
+//!
+//! AudioSource and AudioSink blocks read and write their audio through a device, which is provided
+//! by one of several backends:
+//!
+//! PortAudio: The computer's audio hardware. This is the default.
+//!
+//! Null: Produces silence and discards all output. Runs as fast as possible, or paced by the wall
+//!       clock so that the project plays back in real time.
+//!
+//! File: Reads input from a wav file and writes output to a wav file. Output is written once
+//!       playback is complete.
+//!
+//! The backend is selected by the block's device name:
+//!
+//! ```yaml
+//! device: "null"              # Null, as fast as possible.
+//! device: "null:paced"        # Null, paced in real time.
+//! device: "file:take1.wav"    # Read from or write to take1.wav.
+//! device: "MacBook Pro Mic"   # Anything else is the name of a PortAudio device.
+//! ```
+//!
+//! Or for every device in the project at once, with `--audio-backend <null|null:paced|file>`.
+
+extern crate error;
+extern crate log;
+extern crate sampler;
+extern crate stream;
+extern crate wav;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A source of audio.
+pub trait InputDevice {
+    /// Read the next buffer of audio.
+    fn read(&mut self, buffer: &mut stream::RawStream);
+
+    /// Whether reading blocks on audio I/O.
+    fn is_blocking_io(&self) -> bool {
+        false
+    }
+
+    /// Whether the audio arrives late by the round-trip latency of the audio hardware.
+    fn has_latency(&self) -> bool {
+        false
+    }
+
+    /// The number of times audio was dropped because it wasn't read in time.
+    fn overflows(&self) -> usize {
+        0
+    }
+
+    /// Release the device.
+    fn cleanup(&mut self) {}
+}
+
+/// A destination for audio.
+pub trait OutputDevice {
+    /// Write the next buffer of audio.
+    fn write(&mut self, buffer: &stream::RawStream);
+
+    /// Whether writing blocks on audio I/O.
+    fn is_blocking_io(&self) -> bool {
+        false
+    }
+
+    /// The number of times the device ran out of audio because it wasn't written in time.
+    fn underflows(&self) -> usize {
+        0
+    }
+
+    /// Release the device.
+    fn cleanup(&mut self) {}
+}
+
+/// The available backends.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backend {
+    PortAudio,
+    Null,
+    NullPaced,
+    File,
+}
+
+impl Backend {
+    /// Parse the name of a backend, as given with `--audio-backend`. The PortAudio backend can only
+    /// be selected by device name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "null" => Some(Backend::Null),
+            "null:paced" => Some(Backend::NullPaced),
+            "file" => Some(Backend::File),
+            _ => None,
+        }
+    }
+
+    /// Parse a device name into its backend and the backend-specific part of the name, which is
+    /// the PortAudio device name or the file path.
+    pub fn from_device(device: &str) -> (Self, &str) {
+        match device {
+            "null" => (Backend::Null, ""),
+            "null:paced" => (Backend::NullPaced, ""),
+            _ => match device.strip_prefix("file:") {
+                Some(path) => (Backend::File, path),
+                None => (Backend::PortAudio, device),
+            },
+        }
+    }
+}
+
+/// Paces a device to the wall clock so that every buffer takes as long as it would on hardware.
+struct Pacer {
+    /// The sample rate to pace to [samples/s].
+    sample_rate: i32,
+
+    /// The number of interleaved audio channels in each buffer.
+    num_channels: usize,
+
+    /// The time the first buffer was requested.
+    start: Option<Instant>,
+
+    /// The number of frames requested so far.
+    num_frames: usize,
+
+    /// The number of buffers that were requested more than a buffer late.
+    num_late: usize,
+}
+
+impl Pacer {
+    fn new(sample_rate: i32, num_channels: usize) -> Self {
+        Pacer {
+            sample_rate: sample_rate,
+            num_channels: num_channels,
+            start: None,
+            num_frames: 0,
+            num_late: 0,
+        }
+    }
+
+    /// Block until the next buffer is due.
+    ///
+    /// # Arguments
+    ///
+    /// * num_samples - The number of samples in the buffer.
+    fn wait(&mut self, num_samples: usize) {
+        let start = *self.start.get_or_insert_with(Instant::now);
+        let deadline =
+            start + Duration::from_secs_f64(self.num_frames as f64 / self.sample_rate as f64);
+        self.num_frames += num_samples / self.num_channels;
+
+        let buffer_duration = Duration::from_secs_f64(
+            num_samples as f64 / self.num_channels as f64 / self.sample_rate as f64,
+        );
+
+        let now = Instant::now();
+        if deadline > now {
+            thread::sleep(deadline - now);
+        } else if now > deadline + buffer_duration {
+            // Hardware would have dropped a buffer by now.
+            self.num_late += 1;
+        }
+    }
+}
+
+/// An input device which produces silence.
+pub struct NullInput {
+    /// Paces reads in real time, if enabled.
+    pacer: Option<Pacer>,
+}
+
+/// An output device which discards everything written to it.
+pub struct NullOutput {
+    /// Paces writes in real time, if enabled.
+    pacer: Option<Pacer>,
+}
+
+/// An input device which plays a wav file.
+pub struct FileInput {
+    /// The sampler playing the wav file.
+    sampler: sampler::Sampler,
+}
+
+/// An output device which records everything written to it into a wav file.
+pub struct FileOutput {
+    /// The wav file to write.
+    filename: String,
+
+    /// The sample rate of the wav file [samples/s].
+    sample_rate: i32,
+
+    /// The number of audio channels in the wav file.
+    num_channels: usize,
+
+    /// Everything written so far.
+    clip: stream::Clip,
+}
+
+/// An output device which mixes everything written to it into a shared clip.
+pub struct MixOutput {
+    /// The clip to mix into. This may be shared between several devices.
+    mix: stream::Clip,
+
+    /// The number of samples written to the mix so far.
+    position: usize,
+}
+
+impl NullInput {
+    /// Create a null input device, which is optionally paced in real time at the sample rate.
+    pub fn new(paced: bool, sample_rate: i32, num_channels: usize) -> Self {
+        NullInput {
+            pacer: if paced {
+                Some(Pacer::new(sample_rate, num_channels))
+            } else {
+                None
+            },
+        }
+    }
+}
+
+impl NullOutput {
+    /// Create a null output device, which is optionally paced in real time at the sample rate.
+    pub fn new(paced: bool, sample_rate: i32, num_channels: usize) -> Self {
+        NullOutput {
+            pacer: if paced {
+                Some(Pacer::new(sample_rate, num_channels))
+            } else {
+                None
+            },
+        }
+    }
+}
+
+impl FileInput {
+    /// Create a file input device. An empty filename produces silence.
+    pub fn new(
+        filename: &str,
+        sample_rate: i32,
+        num_channels: usize,
+    ) -> Result<Self, error::LooperError> {
+        let mut sampler = sampler::Sampler::new();
+        if filename != "" {
+            let clip = wav::read_wav_file(filename, sample_rate, num_channels)?;
+            sampler.play(&clip, false);
+        }
+
+        Ok(FileInput { sampler: sampler })
+    }
+}
+
+impl FileOutput {
+    /// Create a file output device. The file isn't written until cleanup.
+    pub fn new(filename: &str, sample_rate: i32, num_channels: usize) -> Self {
+        FileOutput {
+            filename: filename.to_owned(),
+            sample_rate: sample_rate,
+            num_channels: num_channels,
+            clip: stream::empty_clip(),
+        }
+    }
+}
+
+impl MixOutput {
+    /// Create an output device which mixes into a clip.
+    pub fn new(mix: &stream::Clip) -> Self {
+        MixOutput {
+            mix: mix.clone(),
+            position: 0,
+        }
+    }
+}
+
+impl InputDevice for NullInput {
+    fn read(&mut self, buffer: &mut stream::RawStream) {
+        if let Some(pacer) = &mut self.pacer {
+            pacer.wait(buffer.len());
+        }
+        buffer.fill(stream::ZERO);
+    }
+
+    fn is_blocking_io(&self) -> bool {
+        self.pacer.is_some()
+    }
+
+    fn overflows(&self) -> usize {
+        self.pacer.as_ref().map_or(0, |pacer| pacer.num_late)
+    }
+}
+
+impl OutputDevice for NullOutput {
+    fn write(&mut self, buffer: &stream::RawStream) {
+        if let Some(pacer) = &mut self.pacer {
+            pacer.wait(buffer.len());
+        }
+    }
+
+    fn is_blocking_io(&self) -> bool {
+        self.pacer.is_some()
+    }
+
+    fn underflows(&self) -> usize {
+        self.pacer.as_ref().map_or(0, |pacer| pacer.num_late)
+    }
+}
+
+impl InputDevice for FileInput {
+    fn read(&mut self, buffer: &mut stream::RawStream) {
+        buffer.fill(stream::ZERO);
+        self.sampler.next(buffer);
+    }
+}
+
+impl OutputDevice for FileOutput {
+    fn write(&mut self, buffer: &stream::RawStream) {
+        self.clip.borrow_mut().extend_from_slice(buffer);
+    }
+
+    fn cleanup(&mut self) {
+        match wav::write_wav_file(&self.clip, &self.filename, self.sample_rate, self.num_channels) {
+            Ok(_) => println!("Saved output => {}", self.filename),
+            Err(_) => println!("Failed to save output => {}", self.filename),
+        }
+    }
+}
+
+impl OutputDevice for MixOutput {
+    fn write(&mut self, buffer: &stream::RawStream) {
+        let mut mix = self.mix.borrow_mut();
+
+        // Grow the mix if this is the first device to reach this point.
+        let end = self.position + buffer.len();
+        if mix.len() < end {
+            mix.resize(end, stream::ZERO);
+        }
+
+        for i in 0..buffer.len() {
+            mix[self.position + i] += buffer[i];
+        }
+        self.position = end;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::assert_approx_eq;
+
+    const SAMPLE_RATE: i32 = 48000;
+    const BUFFER_SIZE: usize = 64;
+
+    #[test]
+    fn test_from_device() {
+        assert_eq!(Backend::from_device("null"), (Backend::Null, ""));
+        assert_eq!(Backend::from_device("null:paced"), (Backend::NullPaced, ""));
+        assert_eq!(Backend::from_device("file:in.wav"), (Backend::File, "in.wav"));
+        assert_eq!(Backend::from_device("USB Mic"), (Backend::PortAudio, "USB Mic"));
+        assert_eq!(Backend::from_name("null:paced"), Some(Backend::NullPaced));
+        assert_eq!(Backend::from_name("USB Mic"), None);
+    }
+
+    #[test]
+    fn test_null() {
+        let mut buffer: stream::RawStream = vec![1.0; BUFFER_SIZE];
+        let mut input = NullInput::new(false, SAMPLE_RATE, 1);
+        input.read(&mut buffer);
+        assert!(buffer.iter().all(|&s| s == stream::ZERO));
+        assert!(!input.is_blocking_io());
+
+        // Four paced buffers take at least three buffers' worth of time.
+        let mut output = NullOutput::new(true, SAMPLE_RATE, 1);
+        let start = Instant::now();
+        for _ in 0..4 {
+            output.write(&buffer);
+        }
+        let min_duration = 3.0 * BUFFER_SIZE as f64 / SAMPLE_RATE as f64;
+        assert!(start.elapsed().as_secs_f64() >= min_duration);
+        assert!(output.is_blocking_io());
+    }
+
+    #[test]
+    fn test_file() {
+        let path = std::env::temp_dir().join("looper_backend_test_file.wav");
+        let filename = path.to_str().unwrap();
+
+        let mut buffer: stream::RawStream = vec![stream::ZERO; BUFFER_SIZE];
+        for i in 0..BUFFER_SIZE {
+            buffer[i] = i as f32 / BUFFER_SIZE as f32;
+        }
+
+        let mut output = FileOutput::new(filename, SAMPLE_RATE, 2);
+        output.write(&buffer);
+        output.write(&buffer);
+        output.cleanup();
+
+        // The file keeps both channels and plays back twice, then goes silent.
+        let mut input = FileInput::new(filename, SAMPLE_RATE, 2).unwrap();
+        let mut read_buffer: stream::RawStream = vec![stream::ZERO; BUFFER_SIZE];
+        for _ in 0..2 {
+            input.read(&mut read_buffer);
+            for i in 0..BUFFER_SIZE {
+                assert_approx_eq!(read_buffer[i], buffer[i], 0.001);
+            }
+        }
+        input.read(&mut read_buffer);
+        assert!(read_buffer.iter().all(|&s| s == stream::ZERO));
+
+        let _ = std::fs::remove_file(filename);
+    }
+
+    #[test]
+    fn test_mix() {
+        let mix = stream::empty_clip();
+        let buffer: stream::RawStream = vec![0.25; BUFFER_SIZE];
+
+        let mut first = MixOutput::new(&mix);
+        let mut second = MixOutput::new(&mix);
+        first.write(&buffer);
+        first.write(&buffer);
+        second.write(&buffer);
+
+        let mix = mix.borrow();
+        assert_eq!(mix.len(), 2 * BUFFER_SIZE);
+        assert_eq!(mix[0], 0.5);
+        assert_eq!(mix[BUFFER_SIZE], 0.25);
+    }
+}
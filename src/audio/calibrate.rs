@@ -0,0 +1,183 @@
+//! Round-trip latency calibration.
+//!
+//! Plays a series of clicks through an output device and listens for them on an input device. The
+//! time between playing a click and hearing it come back is the latency that the Loop and Recorder
+//! blocks need to compensate for. Point a microphone at the speakers, or connect the output to the
+//! input with a cable, before calibrating.
+//!
+//! ```
+//! let latency = calibrate::measure(&mut input, &mut output, 44100, 256)?;
+//! ```
+
+extern crate backend;
+extern crate error;
+extern crate log;
+extern crate stream;
+
+/// The number of clicks to play.
+const NUM_CLICKS: usize = 8;
+
+/// The time between clicks [s]. Latencies longer than this can't be measured.
+const CLICK_INTERVAL: f32 = 0.5;
+
+/// The number of silent buffers played before the first click, used to measure the background
+/// noise.
+const NUM_QUIET_BUFFERS: usize = 16;
+
+/// The length of a click [samples].
+const CLICK_LENGTH: usize = 32;
+
+/// The amplitude of a click.
+const CLICK_VOLUME: stream::Sample = 0.9;
+
+/// The quietest input that counts as a click.
+const MIN_THRESHOLD: stream::Sample = 0.05;
+
+/// Find the first sample which is louder than the threshold.
+pub fn detect_onset(samples: &[stream::Sample], threshold: stream::Sample) -> Option<usize> {
+    samples.iter().position(|s| s.abs() > threshold)
+}
+
+/// Measure the round-trip latency from an output device back to an input device [samples]. The
+/// devices are read and written in the same order as the runner, so the result can be used as the
+/// project latency as-is.
+///
+/// # Arguments
+///
+/// * input - The device listening for the clicks.
+/// * output - The device playing the clicks.
+/// * sample_rate - The sample rate of both devices [samples/s].
+/// * buffer_size - The number of samples in each buffer.
+pub fn measure(
+    input: &mut dyn backend::InputDevice,
+    output: &mut dyn backend::OutputDevice,
+    sample_rate: i32,
+    buffer_size: usize,
+) -> Result<usize, error::LooperError> {
+    let interval_buffers =
+        std::cmp::max(1, (CLICK_INTERVAL * sample_rate as f32) as usize / buffer_size);
+    let interval = interval_buffers * buffer_size;
+    let first_click = NUM_QUIET_BUFFERS * buffer_size;
+    let num_buffers = NUM_QUIET_BUFFERS + NUM_CLICKS * interval_buffers;
+
+    // Play the clicks and record everything that comes back.
+    let mut recording = stream::RawClip::with_capacity(num_buffers * buffer_size);
+    let mut in_buffer: stream::RawStream = vec![stream::ZERO; buffer_size];
+    let mut out_buffer: stream::RawStream = vec![stream::ZERO; buffer_size];
+    for i in 0..num_buffers {
+        input.read(&mut in_buffer);
+        recording.extend_from_slice(&in_buffer);
+
+        // Clicks alternate sign so that they make it through speakers which block DC.
+        out_buffer.fill(stream::ZERO);
+        if i >= NUM_QUIET_BUFFERS && (i - NUM_QUIET_BUFFERS) % interval_buffers == 0 {
+            for j in 0..std::cmp::min(CLICK_LENGTH, buffer_size) {
+                out_buffer[j] = if j % 2 == 0 {
+                    CLICK_VOLUME
+                } else {
+                    -CLICK_VOLUME
+                };
+            }
+        }
+        output.write(&out_buffer);
+    }
+
+    // Anything comfortably louder than the background noise counts as a click.
+    let noise = recording[..first_click]
+        .iter()
+        .fold(stream::ZERO, |max, s| max.max(s.abs()));
+    let threshold = MIN_THRESHOLD.max(4.0 * noise);
+
+    let mut latencies = Vec::<usize>::with_capacity(NUM_CLICKS);
+    for i in 0..NUM_CLICKS {
+        let click = first_click + i * interval;
+        if let Some(latency) = detect_onset(&recording[click..click + interval], threshold) {
+            latencies.push(latency);
+        }
+    }
+    println!("Heard {} of {} clicks.", latencies.len(), NUM_CLICKS);
+    log::abort_if_msg!(
+        latencies.len() < NUM_CLICKS / 2,
+        "Could not hear the clicks. Make sure the input device can hear the output device."
+    );
+
+    // Take the median to ignore the odd click that was masked or drowned out by noise.
+    latencies.sort();
+    Ok(latencies[latencies.len() / 2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    const SAMPLE_RATE: i32 = 44100;
+    const BUFFER_SIZE: usize = 256;
+
+    /// Connects an output device to an input device with a fixed delay.
+    struct Loopback {
+        /// The samples in flight between the devices.
+        queue: Rc<RefCell<VecDeque<stream::Sample>>>,
+
+        /// The volume of the audio that makes it back.
+        volume: stream::Sample,
+    }
+
+    impl backend::InputDevice for Loopback {
+        fn read(&mut self, buffer: &mut stream::RawStream) {
+            let mut queue = self.queue.borrow_mut();
+            for sample in buffer.iter_mut() {
+                *sample = queue.pop_front().unwrap_or(stream::ZERO);
+            }
+        }
+    }
+
+    impl backend::OutputDevice for Loopback {
+        fn write(&mut self, buffer: &stream::RawStream) {
+            let mut queue = self.queue.borrow_mut();
+            queue.extend(buffer.iter().map(|s| s * self.volume));
+        }
+    }
+
+    /// Create a pair of devices where the input hears the output after a delay.
+    fn loopback(delay: usize, volume: stream::Sample) -> (Loopback, Loopback) {
+        let queue = Rc::new(RefCell::new(VecDeque::from(vec![stream::ZERO; delay])));
+        (
+            Loopback {
+                queue: queue.clone(),
+                volume: volume,
+            },
+            Loopback {
+                queue: queue,
+                volume: volume,
+            },
+        )
+    }
+
+    #[test]
+    fn test_detect_onset() {
+        assert_eq!(detect_onset(&[0.0, 0.01, -0.5, 0.9], 0.1), Some(2));
+        assert_eq!(detect_onset(&[0.0, 0.01, -0.05], 0.1), None);
+    }
+
+    #[test]
+    fn test_measure() {
+        // The delay is measured exactly, even when the clicks come back quietly.
+        for (delay, volume) in [(300, 1.0), (1000, 0.1), (4321, 0.5)] {
+            let (mut input, mut output) = loopback(delay, volume);
+            let latency = measure(&mut input, &mut output, SAMPLE_RATE, BUFFER_SIZE).unwrap();
+            assert_eq!(latency, delay);
+        }
+    }
+
+    #[test]
+    fn test_measure_silence() {
+        // Nothing comes back from the null devices.
+        let mut input = backend::NullInput::new(false, SAMPLE_RATE, 1);
+        let mut output = backend::NullOutput::new(false, SAMPLE_RATE, 1);
+        assert!(measure(&mut input, &mut output, SAMPLE_RATE, BUFFER_SIZE).is_err());
+    }
+}
@@ -1,9 +1,14 @@
 //! Recorder Block
 //!
 //! The recorder block listens and saves a .wav file for every input segment. The file is saved in
-//! memory while the song is playing and is written to disk on cleanup. For this reason, the
-//! Looper configuration must be COMPLETE in order for the recording to work. In other words, if
-//! you Ctrl+C, you won't get your audio!
+//! memory while the song is playing and is written to disk on cleanup, which also runs when you
+//! Ctrl+C. If playback stops before the end of the segment, whatever was recorded is saved next to
+//! the regular file with a ".partial.wav" suffix instead.
+//!
+//! Input captured from audio hardware arrives late by the hardware latency, so the recording keeps
+//! going for that long after the segment and is shifted earlier to line up with the project.
+//!
+//! If the playhead jumps back to before the end of the segment, the take starts over.
 //!
 //! Recorder \[Sink\]:
 //!     Required parameters:
@@ -15,7 +20,9 @@
 
 extern crate block;
 extern crate config;
+extern crate error;
 extern crate log;
+extern crate registry;
 extern crate segment;
 extern crate stream;
 extern crate tempo;
@@ -31,15 +38,30 @@ pub struct Recorder {
     /// The input stream to record.
     stream: stream::Stream,
 
+    /// The latency of the input stream.
+    latency: stream::Latency,
+
     /// The segment to record.
     segment: segment::Segment,
 
     /// The .wav filename on disk to store the file.
     filename: String,
 
+    /// The .wav filename on disk to store the file if the recording is incomplete.
+    partial_filename: String,
+
     /// The clip recording the segment.
     clip: stream::Clip,
 
+    /// The sample rate of the recording [samples/s].
+    sample_rate: i32,
+
+    /// The number of audio channels in the recording. This matches the input stream.
+    num_channels: usize,
+
+    /// Whether any of the segment has been recorded yet.
+    is_recording: bool,
+
     /// Whether the stream is finished recording.
     complete: bool,
 
@@ -47,11 +69,16 @@ pub struct Recorder {
     disabled: bool,
 }
 
+/// Register the Recorder block.
+pub fn register(registry: &mut registry::Registry) {
+    registry.register_sink("Recorder", Recorder::new);
+}
+
 impl Recorder {
     pub fn new(
         config: &config::BlockConfig,
         stream_catalog: &stream::StreamCatalog,
-    ) -> Result<Self, ()> {
+    ) -> Result<Self, error::LooperError> {
         // Read in parameters.
         let input_channel = config.get_str("input_channel")?;
         let directory = config.get_str("directory")?;
@@ -60,6 +87,7 @@ impl Recorder {
 
         // Load stream.
         let stream = stream_catalog.bind_sink(input_channel)?;
+        let latency = stream_catalog.bind_latency(input_channel);
 
         // Validate the filename and create it on disk.
         let filename = format!("{}.wav", config.name);
@@ -70,9 +98,19 @@ impl Recorder {
                 log::abort_msg!(format!("Invalid path: {}", pathbuf.display()));
             }
         };
-        log::unwrap_abort_msg!(
-            fs::OpenOptions::new().write(true).create(true).open(path),
-            format!("Error creating file: {}", path)
+        if let Err(e) = fs::OpenOptions::new().write(true).create(true).open(path) {
+            println!("Error creating file: {}", path);
+            return Err(error::LooperError::File {
+                path: path.to_owned(),
+                cause: e.to_string(),
+            });
+        }
+
+        let partial_pathbuf =
+            path::Path::new(directory).join(format!("{}.partial.wav", config.name));
+        let partial_path = log::opt_abort_msg!(
+            partial_pathbuf.to_str(),
+            format!("Invalid path: {}", partial_pathbuf.display())
         );
 
         // Check segments.
@@ -89,13 +127,39 @@ impl Recorder {
         Ok(Recorder {
             name: config.name.to_owned(),
             stream: stream,
+            latency: latency,
             segment: segment,
             filename: path.to_owned(),
+            partial_filename: partial_path.to_owned(),
             clip: clip,
+            sample_rate: stream_catalog.sample_rate(),
+            num_channels: stream_catalog.num_channels(),
+            is_recording: false,
             complete: false,
             disabled: disabled,
         })
     }
+
+    /// Add the next buffer of the input to the recording, shifted earlier by its latency.
+    ///
+    /// # Arguments
+    ///
+    /// * position - Where the start of the buffer lands in the recording [frames]. This is
+    ///              negative if the recording starts partway through the buffer.
+    /// * buffer_size - The number of frames in the input.
+    /// * max_frames - The maximum length of the recording [frames].
+    fn record(&mut self, position: i64, buffer_size: usize, max_frames: usize) {
+        let stream = self.stream.borrow();
+        self.num_channels = stream.len() / buffer_size;
+        stream::mix_into_clip(
+            &mut self.clip.borrow_mut(),
+            self.num_channels,
+            position - self.latency.get() as i64,
+            &stream,
+            self.num_channels,
+            max_frames,
+        );
+    }
 }
 
 impl block::Sink for Recorder {
@@ -107,48 +171,212 @@ impl block::Sink for Recorder {
             return;
         }
 
-        // Record the input samples if we're in the recording segment.
-        if tempo.in_measure(self.segment.start, self.segment.stop) {
-            let mut clip = self.clip.borrow_mut();
-            if clip.is_empty() {
+        // The segment starts and stops on exact samples, which are usually partway through a
+        // buffer. The input arrives late by its latency, so the recording window is shifted too.
+        let buffer_start = tempo.current_sample;
+        let buffer_end = buffer_start + tempo.buffer_size as i64;
+        let latency = self.latency.get() as i64;
+        let recording_start = tempo.measure_sample(self.segment.start);
+        let recording_stop = tempo.measure_sample(self.segment.stop);
+        let length = tempo.samples_between(self.segment.start, self.segment.stop);
+
+        // Record the part of the input which lands in the recording segment.
+        if recording_start + latency < buffer_end && buffer_start < recording_stop + latency {
+            if !self.is_recording {
                 println!("Recording started: {}", self.name);
+                self.is_recording = true;
             }
+            self.record(buffer_start - recording_start, tempo.buffer_size, length);
+        }
 
-            // Resize the recording clip.
-            let start_index = clip.len();
-            clip.resize(start_index + stream::SAMPLES_PER_BUFFER, stream::ZERO);
-
-            // Add the input streams to the new extended portion of the recording.
-            let stream = self.stream.borrow();
-            for i in 0..stream::SAMPLES_PER_BUFFER {
-                clip[start_index + i] = stream[i];
+        // Finish once the late input has caught up with the end of the segment.
+        if buffer_end >= recording_stop + latency {
+            if self.is_recording {
+                println!("Recording complete: {}", self.name);
+                self.clip
+                    .borrow_mut()
+                    .resize(length * self.num_channels, stream::ZERO);
             }
-        } else if tempo.current_measure() > self.segment.stop {
-            println!("Recording complete: {}", self.name);
             self.complete = true;
         }
     }
 
-    fn cleanup(&mut self) {
-        if !self.complete {
-            println!("Abandoning recording \"{}\" because the segment wasn't complete.", self.name);
+    fn seek(&mut self, state: &block::PlaybackState) {
+        let tempo = state.tempo;
+        let cur_measure = tempo.current_measure();
+        if self.disabled || cur_measure >= self.segment.stop {
             return;
         }
-        if !self.disabled {
+
+        // Start the take over. Whatever part of the segment was skipped is left silent.
+        if !self.clip.borrow().is_empty() {
+            println!("Starting recording \"{}\" over.", self.name);
+        }
+        self.clip.borrow_mut().clear();
+        self.is_recording = false;
+        self.complete = false;
+    }
+
+    fn cleanup(&mut self) {
+        if self.disabled {
             println!("Recorder {} is disabled, nothing to do.", self.name);
             return;
         }
+        if self.clip.borrow().is_empty() {
+            println!("Abandoning recording \"{}\" because nothing was recorded.", self.name);
+            return;
+        }
+
+        // Save incomplete recordings to a separate file so they're never mistaken for a full take.
+        let filename = match self.complete {
+            true => &self.filename,
+            false => {
+                println!("Recording \"{}\" is incomplete, saving it as partial.", self.name);
+                &self.partial_filename
+            }
+        };
 
-        match wav::write_wav_file(&self.clip, &self.filename) {
-            Ok(_) => {}
+        match wav::write_wav_file(&self.clip, filename, self.sample_rate, self.num_channels) {
+            Ok(_) => {
+                println!("Saved \"{}\" recording to => {}", self.name, filename);
+            }
             Err(_) => {
-                println!(
-                    "Could not write recording to disk \"{}\" => {}",
-                    self.name, self.filename
-                );
+                println!("Could not write recording to disk \"{}\" => {}", self.name, filename);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use block::Sink;
+
+    extern crate keyboard;
+
+    /// A Recorder of measures 1 to 2, fed by an input which counts up by one every sample, so every
+    /// sample of the take says where it arrived.
+    struct TestRecorder {
+        tempo: tempo::Tempo,
+        keyboard: keyboard::Keyboard,
+        input: stream::Stream,
+        recorder: Recorder,
+    }
+
+    impl TestRecorder {
+        fn new(name: &str) -> Self {
+            // The recording goes to a temporary directory, so write a project which points there.
+            let directory = std::env::temp_dir().join(format!("looper_recorder_{}", name));
+            fs::create_dir_all(&directory).unwrap();
+            let project_path = directory.join("project.yaml");
+            let project_yaml = format!(
+                "config:
+    tempo: {{}}
+devices:
+-   name: take
+    type: Recorder
+    directory: {}
+    input_channel: input
+    segments:
+    -   start: 1
+        stop: 2
+        type: input
+",
+                directory.display()
+            );
+            fs::write(&project_path, project_yaml).unwrap();
+            let project = config::ProjectConfig::new(project_path.to_str().unwrap()).unwrap();
+
+            let tempo = tempo::Tempo::new(&project).unwrap();
+            let mut stream_catalog =
+                stream::StreamCatalog::with_format(44100, tempo.buffer_size, 1);
+            let input = stream_catalog.create_source("input").unwrap();
+            let recorder = Recorder::new(&project.blocks[0], &stream_catalog).unwrap();
+            TestRecorder {
+                tempo: tempo,
+                keyboard: keyboard::Keyboard::detached(),
+                input: input,
+                recorder: recorder,
             }
         }
 
-        println!("Saved \"{}\" recording to => {}", self.name, self.filename);
+        fn run_until(&mut self, until: f32) {
+            while self.tempo.current_measure() < until {
+                for (i, sample) in self.input.borrow_mut().iter_mut().enumerate() {
+                    *sample = (self.tempo.current_sample + i as i64) as stream::Sample;
+                }
+                self.recorder.write(&block::PlaybackState {
+                    tempo: &self.tempo,
+                    keyboard: &self.keyboard,
+                });
+                self.tempo.step(1);
+            }
+        }
+
+        fn seek(&mut self, measure: f32) {
+            self.tempo.seek(measure);
+            self.recorder.seek(&block::PlaybackState {
+                tempo: &self.tempo,
+                keyboard: &self.keyboard,
+            });
+        }
+    }
+
+    #[test]
+    fn test_alignment() {
+        // At 120bpm measure 1 starts at sample 88200, which isn't on a buffer boundary. The take
+        // still starts and stops on the exact samples of the segment.
+        let mut test = TestRecorder::new("alignment");
+        test.run_until(3.0);
+        assert!(test.recorder.complete);
+        let start = test.tempo.measure_sample(1.0) as usize;
+        let length = test.tempo.samples_between(1.0, 2.0);
+        assert_eq!(start, 88200);
+        let clip = test.recorder.clip.borrow();
+        assert_eq!(clip.len(), length);
+        for i in 0..length {
+            assert_eq!(clip[i], (start + i) as stream::Sample, "sample {}", i);
+        }
+
+        // Late input is shifted back onto the segment, so the take holds whatever arrived one
+        // latency after each sample of the segment.
+        let latency = 300;
+        let mut test = TestRecorder::new("alignment_latency");
+        test.recorder.latency.set(latency);
+        test.run_until(3.0);
+        assert!(test.recorder.complete);
+        let clip = test.recorder.clip.borrow();
+        assert_eq!(clip.len(), length);
+        for i in 0..length {
+            assert_eq!(clip[i], (start + i + latency) as stream::Sample, "sample {}", i);
+        }
+    }
+
+    #[test]
+    fn test_seek() {
+        // Jumping back into the segment starts the take over from the new playhead, leaving the
+        // part that was skipped silent.
+        let mut test = TestRecorder::new("seek");
+        test.run_until(1.5);
+        assert!(!test.recorder.clip.borrow().is_empty());
+        test.seek(1.25);
+        assert!(test.recorder.clip.borrow().is_empty());
+        test.run_until(3.0);
+        assert!(test.recorder.complete);
+
+        let skipped = test.tempo.samples_between(1.0, 1.25);
+        let length = test.tempo.samples_between(1.0, 2.0);
+        let start = test.tempo.measure_sample(1.0) as usize;
+        let clip = test.recorder.clip.borrow().clone();
+        assert_eq!(clip.len(), length);
+        assert!(clip[..skipped].iter().all(|s| *s == 0.0));
+        for i in skipped..length {
+            assert_eq!(clip[i], (start + i) as stream::Sample, "sample {}", i);
+        }
+
+        // Jumping back after the segment keeps the finished take.
+        test.seek(2.5);
+        assert!(test.recorder.complete);
+        assert_eq!(*test.recorder.clip.borrow(), clip);
     }
 }
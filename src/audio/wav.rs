@@ -2,84 +2,132 @@
 
 extern crate hound;
 
+extern crate error;
 extern crate log;
 extern crate stream;
 
 use std::{i32, i64, marker::PhantomData};
 
-use stream::SAMPLE_RATE;
-
-/// Read in a wav file as an audio clip.
-pub fn read_wav_file(filename: &str) -> Result<stream::Clip, ()> {
-    let mut reader = log::unwrap_abort_msg!(
-        hound::WavReader::open(filename),
-        format!("Could not load {} as a wav", filename)
-    );
+/// Read in a wav file as an audio clip. Files stored at a different sample rate are resampled and
+/// files with a different number of audio channels are converted.
+///
+/// # Arguments
+///
+/// * filename - The wav file to read.
+/// * sample_rate - The sample rate of the returned clip [samples/s].
+/// * num_channels - The number of interleaved audio channels in the returned clip.
+pub fn read_wav_file(
+    filename: &str,
+    sample_rate: i32,
+    num_channels: usize,
+) -> Result<stream::Clip, error::LooperError> {
+    let mut reader = hound::WavReader::open(filename).map_err(|e| file_error(filename, e))?;
 
     // Read in and convert samples.
     let spec = reader.spec();
-    let num_channels = spec.channels as usize;
-    let clip_size = (reader.len() as usize) / num_channels;
+    let file_channels = spec.channels as usize;
 
     // Initialize an empty clip.
-    let mut clip: Vec<stream::Sample> = Vec::with_capacity(clip_size);
+    let mut clip: Vec<stream::Sample> = Vec::with_capacity(reader.len() as usize);
 
     match spec.sample_format {
         hound::SampleFormat::Int => {
-            let samples = reader.samples::<i32>();
-            for (i, sample) in samples.enumerate() {
-                // TODO: Support multiple channels.
-                // Channels are interleaved, so if we only want channel '0' we need to grab every
-                // nth sample.
-                if i % num_channels == 0 {
-                    let val = log::unwrap_abort!(sample);
-                    clip.push(SampleConverter::<stream::Sample>::from_int(
-                        val,
-                        spec.bits_per_sample,
-                    ));
-                }
+            for sample in reader.samples::<i32>() {
+                let val = sample.map_err(|e| file_error(filename, e))?;
+                clip.push(SampleConverter::<stream::Sample>::from_int(val, spec.bits_per_sample));
             }
         }
         hound::SampleFormat::Float => {
-            let samples = reader.samples::<f32>();
-            for (i, sample) in samples.enumerate() {
-                // TODO: Support multiple channels.
-                if i % num_channels == 0 {
-                    let val = log::unwrap_abort!(sample);
-                    clip.push(SampleConverter::<stream::Sample>::from_float(val));
-                }
+            for sample in reader.samples::<f32>() {
+                let val = sample.map_err(|e| file_error(filename, e))?;
+                clip.push(SampleConverter::<stream::Sample>::from_float(val));
             }
         }
     }
 
+    if spec.sample_rate as i32 != sample_rate {
+        clip = resample(&clip, file_channels, spec.sample_rate as i32, sample_rate);
+    }
+    clip = stream::convert_channels(&clip, file_channels, num_channels);
+
     Ok(stream::Clip::new(clip.into()))
 }
 
 /// Write a Clip to a file using the wav audio format.
-pub fn write_wav_file(clip: &stream::Clip, filename: &str) -> Result<(), ()> {
+///
+/// # Arguments
+///
+/// * clip - The clip to write.
+/// * filename - The wav file to write.
+/// * sample_rate - The sample rate of the clip [samples/s].
+/// * num_channels - The number of interleaved audio channels in the clip.
+pub fn write_wav_file(
+    clip: &stream::Clip,
+    filename: &str,
+    sample_rate: i32,
+    num_channels: usize,
+) -> Result<(), error::LooperError> {
     let depth: u16 = 16;
     let spec = hound::WavSpec {
-        channels: 1,
-        sample_rate: SAMPLE_RATE as u32,
+        channels: num_channels as u16,
+        sample_rate: sample_rate as u32,
         bits_per_sample: depth,
         sample_format: hound::SampleFormat::Int,
     };
-    let mut writer = log::unwrap_abort_msg!(
-        hound::WavWriter::create(filename, spec),
-        "Failed to initialize the WavWriter"
-    );
+    let mut writer =
+        hound::WavWriter::create(filename, spec).map_err(|e| file_error(filename, e))?;
 
     let raw_clip = clip.borrow();
     for i in 0..raw_clip.len() {
-        log::unwrap_abort_msg!(
-            writer.write_sample(SampleConverter::<stream::Sample>::to_int(raw_clip[i], depth)),
-            "Failed to write wav sample to WavWriter"
-        );
+        writer
+            .write_sample(SampleConverter::<stream::Sample>::to_int(raw_clip[i], depth))
+            .map_err(|e| file_error(filename, e))?;
     }
+    writer.finalize().map_err(|e| file_error(filename, e))?;
 
     Ok(())
 }
 
+/// Build an error for a wav file which couldn't be read or written, and log it.
+fn file_error(filename: &str, e: hound::Error) -> error::LooperError {
+    let e = error::LooperError::File {
+        path: filename.to_owned(),
+        cause: e.to_string(),
+    };
+    println!("{}", e);
+    e
+}
+
+/// Resample an interleaved clip from one sample rate to another using linear interpolation.
+fn resample(
+    clip: &stream::RawClip,
+    num_channels: usize,
+    from_rate: i32,
+    to_rate: i32,
+) -> stream::RawClip {
+    let num_frames = clip.len() / num_channels;
+    if num_frames == 0 {
+        return stream::RawClip::new();
+    }
+
+    let ratio = from_rate as f64 / to_rate as f64;
+    let len = (num_frames as f64 / ratio).round() as usize;
+    let mut resampled = stream::RawClip::with_capacity(len * num_channels);
+    for i in 0..len {
+        let position = i as f64 * ratio;
+        let index = position as usize;
+        let fraction = (position - index as f64) as stream::Sample;
+
+        let current = index.min(num_frames - 1) * num_channels;
+        let next = (index + 1).min(num_frames - 1) * num_channels;
+        for c in 0..num_channels {
+            resampled.push(clip[current + c] + (clip[next + c] - clip[current + c]) * fraction);
+        }
+    }
+
+    resampled
+}
+
 //=====================================
 
 // WAV<->stream::Sample conversion code follows. Conversion code has been implemented for f32 and
@@ -187,4 +235,31 @@ mod tests {
         assert_eq!(SampleConverter::<i32>::to_int(118365240, 8), 7);
         assert_eq!(SampleConverter::<i32>::to_int(196614, 16), 3);
     }
+
+    #[test]
+    fn test_missing_file() {
+        match read_wav_file("missing.wav", stream::DEFAULT_SAMPLE_RATE, 1) {
+            Err(error::LooperError::File { path, .. }) => assert_eq!(path, "missing.wav"),
+            _ => panic!("Expected a file error"),
+        }
+    }
+
+    #[test]
+    fn test_resample() {
+        // Upsampling interpolates between samples.
+        let clip: stream::RawClip = vec![0.0, 1.0, 0.0, -1.0];
+        assert_eq!(resample(&clip, 1, 1, 2), vec![0.0, 0.5, 1.0, 0.5, 0.0, -0.5, -1.0, -1.0]);
+
+        // Downsampling skips samples.
+        assert_eq!(resample(&clip, 1, 2, 1), vec![0.0, 0.0]);
+
+        // Channels are interpolated separately.
+        assert_eq!(resample(&clip, 2, 1, 2), vec![0.0, 1.0, 0.0, 0.0, 0.0, -1.0, 0.0, -1.0]);
+
+        // 44.1kHz -> 48kHz keeps the duration of the clip.
+        let clip: stream::RawClip = vec![0.5; 44100];
+        let resampled = resample(&clip, 1, 44100, 48000);
+        assert_eq!(resampled.len(), 48000);
+        assert!(resampled.iter().all(|&s| s == 0.5));
+    }
 }
@@ -1,4 +1,5 @@
 extern crate keyboard;
+extern crate stream;
 extern crate tempo;
 
 /// Struct which provides read-only access to the playback state.
@@ -18,10 +19,23 @@ pub trait Source {
     /// Optional code to be run when the playback is complete.
     fn cleanup(&mut self) {}
 
+    /// Optional code to be run when the playhead jumps to a different position, e.g. when the user
+    /// seeks or restarts. The new position is in state.tempo.
+    fn seek(&mut self, _state: &PlaybackState) {}
+
     /// Optional property for whether the block contains blocking I/O.
     fn is_blocking_io(&self) -> bool {
         return false;
     }
+
+    /// Optional count of the times the block's input overflowed, i.e. audio was dropped.
+    fn overflows(&self) -> usize {
+        return 0;
+    }
+
+    /// Optional code to be run for every buffer of the count-in, instead of read(). Blocks reading
+    /// from audio hardware should keep up with it.
+    fn count_in(&mut self) {}
 }
 
 /// Block which ingests audio data and outputs it to I/O.
@@ -32,10 +46,23 @@ pub trait Sink {
     /// Optional code to be run when the playback is complete.
     fn cleanup(&mut self) {}
 
+    /// Optional code to be run when the playhead jumps to a different position, e.g. when the user
+    /// seeks or restarts. The new position is in state.tempo.
+    fn seek(&mut self, _state: &PlaybackState) {}
+
     /// Optional property for whether the block contains blocking I/O.
     fn is_blocking_io(&self) -> bool {
         return false;
     }
+
+    /// Optional count of the times the block's output underflowed, i.e. audio dropped out.
+    fn underflows(&self) -> usize {
+        return 0;
+    }
+
+    /// Optional code to be run for every buffer of the count-in, instead of write(). Blocks writing
+    /// to audio hardware should play the clicks.
+    fn count_in(&mut self, _clicks: &stream::RawStream) {}
 }
 
 /// Block which takes an input data source and produces a transformed output.
@@ -45,4 +72,8 @@ pub trait Transformer {
 
     /// Optional code to be run when the playback is complete.
     fn cleanup(&mut self) {}
+
+    /// Optional code to be run when the playhead jumps to a different position, e.g. when the user
+    /// seeks or restarts. The new position is in state.tempo.
+    fn seek(&mut self, _state: &PlaybackState) {}
 }
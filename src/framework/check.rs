@@ -0,0 +1,421 @@
+//! Project validator.
+//!
+//! Builds every block in a project without running it and reports every problem found, instead of
+//! stopping at the first one. Blocks are created with whichever registry is provided, so callers
+//! should swap out any blocks that touch audio hardware first.
+//!
+//! ```
+//! let problems = check::check(&project, &registry);
+//! for problem in &problems {
+//!     println!("{}", problem);
+//! }
+//! ```
+
+extern crate config;
+extern crate error;
+extern crate graph;
+extern crate keyboard;
+extern crate registry;
+extern crate segment;
+extern crate stream;
+extern crate yaml_rust;
+
+use std::fmt;
+
+/// How bad a problem is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    /// The project will run, but probably not the way the user intended.
+    Warning,
+
+    /// The project will not load.
+    Error,
+}
+
+/// A problem with the project.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Problem {
+    /// How bad the problem is.
+    pub severity: Severity,
+
+    /// The name of the block with the problem, if it's specific to one block.
+    pub block: Option<String>,
+
+    /// A description of the problem.
+    pub message: String,
+}
+
+impl Problem {
+    fn error(block: &str, message: String) -> Self {
+        Problem {
+            severity: Severity::Error,
+            block: Some(block.to_owned()),
+            message: message,
+        }
+    }
+
+    fn warning(block: &str, message: String) -> Self {
+        Problem {
+            severity: Severity::Warning,
+            block: Some(block.to_owned()),
+            message: message,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        match &self.block {
+            Some(block) => write!(f, "{}: device=\"{}\": {}", severity, block, self.message),
+            None => write!(f, "{}: {}", severity, self.message),
+        }
+    }
+}
+
+/// Describe an error from one of a block's parameters or streams, leaving out the block name.
+fn describe(e: &error::LooperError) -> String {
+    match e {
+        error::LooperError::Config { key, cause, .. } => format!("Invalid \"{}\": {}", key, cause),
+        error::LooperError::Stream { channel, cause, .. } => {
+            format!("Channel \"{}\": {}", channel, cause)
+        }
+        error::LooperError::Block { cause, .. } => describe(cause),
+        e => e.to_string(),
+    }
+}
+
+/// Check the segments of a single block.
+fn check_segments(
+    project: &config::ProjectConfig,
+    block_config: &config::BlockConfig,
+    problems: &mut Vec<Problem>,
+) {
+    let name = &block_config.name;
+    let raw_segments = match block_config.get_value_opt("segments") {
+        Some(v) => v,
+        None => return,
+    };
+
+    // Typos in the segment type would otherwise only show up as a generic parse error.
+    let mut types_valid = true;
+    for raw_segment in raw_segments.as_vec().unwrap_or(&Vec::new()) {
+        if let Some(type_str) = raw_segment["type"].as_str() {
+            if segment::SegmentType::from(type_str) == segment::SegmentType::Invalid {
+                problems.push(Problem::error(
+                    name,
+                    format!(
+                        "Unknown segment type \"{}\", expected \"input\" or \"output\"",
+                        type_str
+                    ),
+                ));
+                types_valid = false;
+            }
+        }
+    }
+    if !types_valid {
+        return;
+    }
+
+    let segments = match block_config.get_segments() {
+        Ok(v) => v,
+        Err(e) => {
+            problems.push(Problem::error(name, describe(&e)));
+            return;
+        }
+    };
+
+    // Loops can only play back what they've finished recording.
+    let input = segments
+        .iter()
+        .find(|s| s.segment_type == segment::SegmentType::Input);
+    if let (Some(input), "Loop") = (input, block_config.block_type.as_str()) {
+        for segment in &segments {
+            if segment.segment_type == segment::SegmentType::Output && segment.start < input.stop {
+                problems.push(Problem::error(
+                    name,
+                    format!(
+                        "Output segment [{}, {}] starts before the input segment ends at {}",
+                        segment.start, segment.stop, input.stop
+                    ),
+                ));
+            }
+        }
+    }
+
+    for (i, segment) in segments.iter().enumerate() {
+        if i > 0 && segment.start < segments[i - 1].start {
+            problems.push(Problem::warning(
+                name,
+                format!(
+                    "Segment [{}, {}] is listed after segment [{}, {}] but starts before it",
+                    segment.start,
+                    segment.stop,
+                    segments[i - 1].start,
+                    segments[i - 1].stop
+                ),
+            ));
+        }
+
+        for other in &segments[i + 1..] {
+            if segment.start < other.stop && other.start < segment.stop {
+                problems.push(Problem::warning(
+                    name,
+                    format!(
+                        "Segments [{}, {}] and [{}, {}] overlap",
+                        segment.start, segment.stop, other.start, other.stop
+                    ),
+                ));
+            }
+        }
+
+        if project.stop_measure >= 0.0 && segment.stop > project.stop_measure {
+            problems.push(Problem::warning(
+                name,
+                format!(
+                    "Segment [{}, {}] ends after stop_measure {}",
+                    segment.start, segment.stop, project.stop_measure
+                ),
+            ));
+        }
+    }
+}
+
+/// Check that every clip and instrument file referenced by a block exists.
+fn check_files(block_config: &config::BlockConfig, problems: &mut Vec<Problem>) {
+    let mut files = Vec::<(&str, String)>::new();
+
+    // Clips are referenced by name, other files by path.
+    if let Some(sound) = block_config.get_value_opt("sound").and_then(|v| v.as_str()) {
+        files.push(("clip", config::clip_path(sound)));
+    }
+    if let Some(sounds) = block_config
+        .get_value_opt("sounds")
+        .and_then(|v| v.as_vec())
+    {
+        for sound in sounds {
+            if let Some(file) = sound["file"].as_str() {
+                files.push(("clip", config::clip_path(file)));
+            }
+        }
+    }
+    if let Some(instrument) = block_config
+        .get_value_opt("instrument")
+        .and_then(|v| v.as_str())
+    {
+        files.push(("instrument", config::instrument_path(instrument)));
+    }
+    if let Some(path) = block_config
+        .get_value_opt("clip_override")
+        .and_then(|v| v.as_str())
+    {
+        files.push(("file", path.to_owned()));
+    }
+
+    // Audio sinks write their files rather than reading them.
+    if block_config.block_type == "AudioSource" {
+        if let Some(path) = block_config
+            .get_value_opt("render_file")
+            .and_then(|v| v.as_str())
+        {
+            files.push(("file", path.to_owned()));
+        }
+        if let Some(path) = block_config
+            .get_value_opt("device")
+            .and_then(|v| v.as_str())
+            .and_then(|v| v.strip_prefix("file:"))
+        {
+            files.push(("file", path.to_owned()));
+        }
+    }
+
+    for (kind, path) in files {
+        if !std::path::Path::new(&path).exists() {
+            problems
+                .push(Problem::error(&block_config.name, format!("Missing {}: {}", kind, path)));
+        }
+    }
+}
+
+/// Warn about instrument sounds on keys which are reserved for the transport, since they can never
+/// be played.
+fn check_keys(block_config: &config::BlockConfig, problems: &mut Vec<Problem>) {
+    let mut sounds = block_config.get_value_opt("sounds").cloned();
+
+    // Instruments loaded by name list their sounds in their own file. A missing file is reported
+    // by check_files().
+    if let Some(instrument) = block_config
+        .get_value_opt("instrument")
+        .and_then(|v| v.as_str())
+    {
+        let path = config::instrument_path(instrument);
+        if std::path::Path::new(&path).exists() {
+            if let Some(root) = config::read_yaml_file(&path)
+                .ok()
+                .and_then(|r| r.first().cloned())
+            {
+                sounds = Some(root["sounds"].clone());
+            }
+        }
+    }
+
+    let sounds = match sounds {
+        Some(v) => v,
+        None => return,
+    };
+    for sound in sounds.as_vec().unwrap_or(&Vec::new()) {
+        let key = sound["key"].as_str().unwrap_or("");
+        let mut chars = key.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            if keyboard::Transport::from_key(c).is_some() {
+                problems.push(Problem::warning(
+                    &block_config.name,
+                    format!(
+                        "Key \"{}\" is reserved for the transport, so \"{}\" can never be played",
+                        key,
+                        sound["file"].as_str().unwrap_or("")
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+/// Check a project for problems.
+///
+/// # Arguments
+///
+/// * project - The project to check.
+/// * registry - The registry used to create every block in the project.
+pub fn check(project: &config::ProjectConfig, registry: &registry::Registry) -> Vec<Problem> {
+    let mut problems = Vec::<Problem>::new();
+    let mut stream_catalog = stream::StreamCatalog::with_format(
+        project.sample_rate,
+        project.buffer_size,
+        project.num_channels,
+    );
+    let mut transformer_names = Vec::<String>::new();
+
+    // Build every block, carrying on past blocks that fail.
+    for block_config in &project.blocks {
+        let name = &block_config.name;
+        check_segments(project, block_config, &mut problems);
+        check_files(block_config, &mut problems);
+        check_keys(block_config, &mut problems);
+
+        stream_catalog.set_block(name);
+        let result = match registry.get(&block_config.block_type) {
+            Some(registry::Constructor::Source(new)) => {
+                new(block_config, &mut stream_catalog).map(|_| ())
+            }
+            Some(registry::Constructor::Transformer(new)) => {
+                transformer_names.push(name.to_owned());
+                new(block_config, &mut stream_catalog).map(|_| ())
+            }
+            Some(registry::Constructor::Sink(new)) => {
+                new(block_config, &stream_catalog).map(|_| ())
+            }
+            None => {
+                problems.push(Problem::error(
+                    name,
+                    format!("Unknown block type \"{}\"", block_config.block_type),
+                ));
+                continue;
+            }
+        };
+        if let Err(e) = result {
+            problems.push(Problem::error(name, format!("Failed to initialize: {}", describe(&e))));
+        }
+    }
+
+    // Check the connections between blocks.
+    for (channel, consumer) in stream_catalog.missing() {
+        problems.push(Problem::error(
+            &consumer,
+            format!("Input channel \"{}\" is not the output of any device", channel),
+        ));
+    }
+    for (channel, producer) in stream_catalog.unused() {
+        problems.push(Problem::warning(
+            &producer,
+            format!("Output channel \"{}\" is never used as an input", channel),
+        ));
+    }
+    if let Err(e) = graph::Graph::new(&stream_catalog).sort(&transformer_names) {
+        problems.push(Problem {
+            severity: Severity::Error,
+            block: None,
+            message: e,
+        });
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern crate test_blocks;
+
+    fn test_registry() -> registry::Registry {
+        test_blocks::registry(&test_blocks::Created::default())
+    }
+
+    fn has_problem(problems: &Vec<Problem>, severity: Severity, block: &str, message: &str) {
+        assert!(
+            problems.iter().any(|p| p.severity == severity
+                && p.block.as_deref() == Some(block)
+                && p.message.contains(message)),
+            "Missing {:?} for {}: {}",
+            severity,
+            block,
+            message
+        );
+    }
+
+    #[test]
+    fn test_valid() {
+        let project = config::ProjectConfig::new("dat/check/valid.yaml").unwrap();
+        let problems = check(&project, &test_registry());
+        assert!(problems.is_empty(), "{:?}", problems);
+    }
+
+    #[test]
+    fn test_problems() {
+        let project = config::ProjectConfig::new("dat/check/problems.yaml").unwrap();
+        let problems = check(&project, &test_registry());
+        has_problem(&problems, Severity::Error, "typo", "Unknown segment type \"ouput\"");
+        has_problem(
+            &problems,
+            Severity::Error,
+            "typo",
+            "Failed to initialize: Invalid \"segments\": Unknown segment type \"ouput\"",
+        );
+        has_problem(&problems, Severity::Warning, "unordered", "starts before it");
+        has_problem(&problems, Severity::Warning, "unordered", "overlap");
+        has_problem(&problems, Severity::Warning, "late", "ends after stop_measure 8");
+        has_problem(
+            &problems,
+            Severity::Error,
+            "late",
+            "Missing clip: assets/clips/not-a-clip.wav",
+        );
+        has_problem(&problems, Severity::Error, "late", "Missing instrument");
+        has_problem(&problems, Severity::Error, "unknown", "Unknown block type \"Nope\"");
+        has_problem(&problems, Severity::Warning, "keys", "Key \" \" is reserved");
+        has_problem(&problems, Severity::Warning, "keys", "Key \"0\" is reserved");
+        assert!(!problems
+            .iter()
+            .any(|p| p.block.as_deref() == Some("keys") && p.message.contains("Key \"a\"")));
+        has_problem(&problems, Severity::Error, "loop", "starts before the input segment ends");
+        has_problem(&problems, Severity::Error, "dangling", "\"nowhere\" is not the output");
+        has_problem(&problems, Severity::Warning, "dangling", "\"dangling\" is never used");
+        assert!(problems
+            .iter()
+            .any(|p| p.block.is_none() && p.message.starts_with("Stream cycle detected")));
+    }
+}
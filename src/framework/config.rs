@@ -18,7 +18,7 @@
 //!     pub fn new(
 //!         config: &config::BlockConfig,
 //!         stream_catalog: &mut stream::StreamCatalog
-//!     ) -> Result<Self, ()> {
+//!     ) -> Result<Self, error::LooperError> {
 //!         let int_param = config.get_i32("int_param")?;
 //!         let str_param = config.get_str("str_param")?;
 //!         ...
@@ -32,18 +32,29 @@
 //!  - `f32`
 //!  - `Vec<str>`
 //!  - `Vec<segment::Segment>`
+//!
+//! If a parameter is missing or invalid, the accessor returns a `LooperError::Config` naming the
+//! block and key, which the block should pass straight back with `?`.
 
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::path::Path;
 use std::rc::Rc;
+use yaml_rust::yaml::Hash;
 use yaml_rust::{Yaml, YamlLoader};
 
+extern crate error;
+extern crate expr;
 extern crate log;
 extern crate segment;
+extern crate stream;
 extern crate yaml_rust;
 
 const SEGMENTS_KEY: &str = "segments";
 
+/// The number of ticks in a beat, for times written as "<measure>:<beat>:<tick>".
+pub const TICKS_PER_BEAT: i32 = 480;
+
 /// Load a file in as yaml.
 pub fn read_yaml_file(filename: &str) -> Result<Vec<Yaml>, String> {
     let contents = log::unwrap_abort_str!(std::fs::read_to_string(filename));
@@ -68,6 +79,28 @@ struct NamedVariables {
 
     /// Floating variables.
     pub vars_f32: HashMap<String, f32>,
+
+    /// Groove templates.
+    pub grooves: HashMap<String, Groove>,
+
+    /// Song sections, each a [start, stop] list.
+    pub sections: HashMap<String, Yaml>,
+
+    /// Resolves musical and absolute times, once the tempo is known.
+    pub timeline: Option<Rc<dyn Timeline>>,
+}
+
+/// Converts beats and seconds into positions in the song [measures], following every change of
+/// tempo and time signature. Implemented by tempo::Tempo.
+pub trait Timeline {
+    /// The position of a beat in the song [measures]. Beats are counted from 0.
+    fn measure_at_beat(&self, beat: f64) -> f32;
+
+    /// The beat at a position in the song [measures].
+    fn beat_at_measure(&self, measure: f32) -> f64;
+
+    /// The position of a time since the start of the song [measures].
+    fn measure_at_seconds(&self, seconds: f64) -> f32;
 }
 
 /// Configuration for a single Block.
@@ -85,6 +118,67 @@ pub struct BlockConfig {
     variables: Rc<RefCell<NamedVariables>>,
 }
 
+/// A range of measures which is played over and over for practice.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PracticeLoop {
+    /// The measure to jump back to.
+    pub start: f32,
+
+    /// The measure where playback jumps back to the start.
+    pub stop: f32,
+
+    /// The number of times to play the range, or None to play it until playback is stopped.
+    pub repeats: Option<u32>,
+}
+
+/// A count-in of clicks which plays before the transport reaches the start measure.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CountIn {
+    /// The number of measures to count in.
+    pub measures: u32,
+
+    /// The clip to play on each beat.
+    pub sound: String,
+
+    /// The clip to play on the first beat of each measure, if it's accented.
+    pub accent_sound: Option<String>,
+}
+
+/// A groove template, which shifts the evenly spaced subdivisions of every pulse of the bpm to give
+/// it a feel, e.g. swing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Groove {
+    /// How far to shift each subdivision, as a fraction of a subdivision. The first subdivision is
+    /// the pulse itself, which always stays put, so its offset must be 0.
+    pub offsets: Vec<f32>,
+}
+
+/// A change of tempo partway through the song.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TempoChange {
+    /// The measure where the change starts.
+    pub measure: f32,
+
+    /// The new tempo [beats/min].
+    pub bpm: f32,
+
+    /// The number of measures to ramp linearly to the new tempo over, or 0 to jump straight to it.
+    pub ramp: f32,
+}
+
+/// A change of time signature at the start of a measure.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeterChange {
+    /// The first measure in the new time signature.
+    pub measure: i32,
+
+    /// The number of beats per measure (top of the time signature).
+    pub beats_per_measure: i32,
+
+    /// The duration of the beat (bottom of the time signature), or None to keep the previous one.
+    pub beat_duration: Option<i32>,
+}
+
 /// Top level config.
 pub struct ProjectConfig {
     /// Global configuration parameters.
@@ -96,17 +190,116 @@ pub struct ProjectConfig {
     /// The stop measure.
     pub stop_measure: f32,
 
+    /// The sample rate of the project [samples/s].
+    pub sample_rate: i32,
+
+    /// The number of samples processed by every block on each cycle, per audio channel.
+    pub buffer_size: usize,
+
+    /// The number of audio channels in every stream, unless a block says otherwise.
+    pub num_channels: usize,
+
+    /// The round-trip latency of the audio hardware [samples], unless an AudioSource says
+    /// otherwise.
+    pub latency: usize,
+
+    /// The number of measures to jump back or forward with the transport keys.
+    pub seek_measures: f32,
+
+    /// The range of measures to repeat, if any.
+    pub practice_loop: Option<PracticeLoop>,
+
+    /// The count-in before playback starts, if any.
+    pub count_in: Option<CountIn>,
+
+    /// Changes to the tempo, in order.
+    pub tempo_changes: Vec<TempoChange>,
+
+    /// Changes to the time signature, in order.
+    pub meter_changes: Vec<MeterChange>,
+
+    /// The groove of the whole song, if any. Blocks can have their own.
+    pub groove: Option<Groove>,
+
     /// The list of blocks.
     pub blocks: Vec<BlockConfig>,
+
+    /// The project file and every file it includes.
+    pub files: Vec<String>,
+
+    /// User-defined variables, shared with every block.
+    variables: Rc<RefCell<NamedVariables>>,
+}
+
+// Evaluate an expression, or look up a variable, as an f32.
+fn evaluate_f32(s: &str, vars: &NamedVariables) -> Result<f32, error::LooperError> {
+    match vars.evaluate(s) {
+        Ok(v) => Ok(v),
+        Err(e) => {
+            log::abort_msg!(e);
+        }
+    }
+}
+
+// Evaluate an expression, or look up a variable, as an i32.
+fn evaluate_i32(s: &str, vars: &NamedVariables) -> Result<i32, error::LooperError> {
+    if let Some(value) = vars.resolve_i32(s) {
+        return Ok(value);
+    }
+    let value = evaluate_f32(s, vars)?;
+    log::abort_if_msg!(
+        value.fract() != 0.0,
+        format!("Expected an integer value, but \"{}\" is {}", s, value)
+    );
+    Ok(value as i32)
+}
+
+// Evaluate a variable which is defined by an expression, after every variable it refers to.
+//
+// # Arguments
+//
+// * name - The variable to evaluate.
+// * definitions - The expression of every variable which has one.
+// * values - The value of every variable evaluated so far.
+// * path - The variables which are waiting on this one, to report cycles.
+fn evaluate_variable(
+    name: &str,
+    definitions: &HashMap<String, expr::Expr>,
+    values: &mut HashMap<String, f64>,
+    path: &mut Vec<String>,
+) -> Result<f64, String> {
+    if let Some(value) = values.get(name) {
+        return Ok(*value);
+    }
+    if let Some(i) = path.iter().position(|n| n == name) {
+        let mut cycle = path[i..].to_vec();
+        cycle.push(name.to_owned());
+        return Err(format!("Variables refer to each other in a cycle: {}", cycle.join(" -> ")));
+    }
+    let expr = match (definitions.get(name), path.last()) {
+        (Some(expr), _) => expr,
+        (None, Some(from)) => {
+            return Err(format!("Variable \"{}\" refers to unknown variable \"{}\"", from, name));
+        }
+        (None, None) => return Err(format!("Could not resolve variable: {}", name)),
+    };
+
+    path.push(name.to_owned());
+    for dependency in expr.variables() {
+        evaluate_variable(dependency, definitions, values, path)?;
+    }
+    path.pop();
+
+    let value = expr.evaluate(&|n| values.get(n).copied())?;
+    values.insert(name.to_owned(), value);
+    Ok(value)
 }
 
 // Parse a yaml object as an i32.
-fn yaml_as_i32(obj: &Yaml, vars: &NamedVariables) -> Result<i32, ()> {
+fn yaml_as_i32(obj: &Yaml, vars: &NamedVariables) -> Result<i32, error::LooperError> {
     let result = match obj {
         Yaml::Integer(i) => *i as i32,
-        Yaml::String(s) => {
-            log::opt_abort_msg!(vars.resolve_i32(s), format!("Could not resolve variable: {}", s))
-        }
+        Yaml::String(s) => evaluate_i32(s, vars)?,
         _ => {
             log::abort_msg!("Expected an integer value");
         }
@@ -115,25 +308,25 @@ fn yaml_as_i32(obj: &Yaml, vars: &NamedVariables) -> Result<i32, ()> {
 }
 
 // Parse a yaml object as an i32.
-fn yaml_as_i32_opt(obj: &Yaml, vars: &NamedVariables, default: &i32) -> Result<i32, ()> {
+fn yaml_as_i32_opt(
+    obj: &Yaml,
+    vars: &NamedVariables,
+    default: &i32,
+) -> Result<i32, error::LooperError> {
     let result = match obj {
         Yaml::Integer(i) => *i as i32,
-        Yaml::String(s) => {
-            log::opt_abort_msg!(vars.resolve_i32(s), format!("Could not resolve variable: {}", s))
-        }
+        Yaml::String(s) => evaluate_i32(s, vars)?,
         _ => *default,
     };
     Ok(result)
 }
 
 // Parse a yaml object as an f32. Supports casting if the object is an int.
-fn yaml_as_f32(obj: &Yaml, vars: &NamedVariables) -> Result<f32, ()> {
+fn yaml_as_f32(obj: &Yaml, vars: &NamedVariables) -> Result<f32, error::LooperError> {
     let result = match obj {
         Yaml::Real(_) => obj.as_f64().unwrap() as f32,
         Yaml::Integer(_) => obj.as_i64().unwrap() as f32,
-        Yaml::String(s) => {
-            log::opt_abort_msg!(vars.resolve_f32(s), format!("Could not resolve variable: {}", s))
-        }
+        Yaml::String(s) => evaluate_f32(s, vars)?,
         _ => {
             log::abort_msg!("Expected a number value");
         }
@@ -141,31 +334,587 @@ fn yaml_as_f32(obj: &Yaml, vars: &NamedVariables) -> Result<f32, ()> {
     Ok(result)
 }
 
+// Parse a number, or the name of a variable holding one.
+fn resolve_number(s: &str, vars: &NamedVariables) -> Result<f32, error::LooperError> {
+    evaluate_f32(s.trim(), vars)
+}
+
+// The parts of a time which can be numbers or variable names, e.g. "CHORUS" and "3" in "CHORUS:3".
+fn time_parts(s: &str) -> Vec<&str> {
+    if s.contains(':') {
+        return s.split(':').collect();
+    }
+    match s.strip_suffix(&['b', 's']) {
+        Some(v) => vec![v],
+        None => vec![],
+    }
+}
+
+// Parse a yaml object as a position in the song [measures]. This is either a number of measures
+// or a variable, like yaml_as_f32(), or one of:
+//  - "<measure>:<beat>[:<tick>]", with beats counted from 1 and TICKS_PER_BEAT ticks per beat.
+//  - "<beats>b", counted from the start of the song.
+//  - "<seconds>s", from the start of the song.
+// which are resolved through the timeline. Every number can also be a variable.
+fn yaml_as_measure(obj: &Yaml, vars: &NamedVariables) -> Result<f32, error::LooperError> {
+    let s = match obj {
+        Yaml::String(s) if vars.evaluate(s).is_err() && !time_parts(s).is_empty() => s,
+        _ => return yaml_as_f32(obj, vars),
+    };
+    let timeline = log::opt_abort_msg!(
+        vars.timeline.as_ref(),
+        format!("\"{}\" can't be resolved without the project tempo", s)
+    );
+
+    if s.contains(':') {
+        let parts = time_parts(s);
+        log::abort_if_msg!(
+            parts.len() > 3,
+            format!("Expected <measure>:<beat>[:<tick>], got \"{}\"", s)
+        );
+        let measure = resolve_number(parts[0], vars)?;
+        let beat = resolve_number(parts[1], vars)?;
+        let tick = match parts.get(2) {
+            Some(v) => resolve_number(v, vars)?,
+            None => 0.0,
+        };
+        let first_beat = timeline.beat_at_measure(measure);
+        let num_beats = timeline.beat_at_measure(measure + 1.0) - first_beat;
+        log::abort_if_msg!(
+            beat < 1.0 || beat as f64 >= num_beats + 1.0,
+            format!("Beat {} is outside of measure {}", beat, measure)
+        );
+        log::abort_if_msg!(
+            tick < 0.0 || tick >= TICKS_PER_BEAT as f32,
+            format!("Tick {} must be less than {}", tick, TICKS_PER_BEAT)
+        );
+        let beats = (beat - 1.0) as f64 + tick as f64 / TICKS_PER_BEAT as f64;
+        return Ok(timeline.measure_at_beat(first_beat + beats));
+    }
+
+    let value = resolve_number(time_parts(s)[0], vars)?;
+    log::abort_if_msg!(value < 0.0, format!("\"{}\" is before the start of the song", s));
+    match s.ends_with('b') {
+        true => Ok(timeline.measure_at_beat(value as f64)),
+        false => Ok(timeline.measure_at_seconds(value as f64)),
+    }
+}
+
+// Parse the time ranges of a segment [measures]. A segment has either a "start" and a "stop", a
+// "section", or a list of "repeat_sections" which it covers one after another.
+fn yaml_as_ranges(
+    member: &Yaml,
+    vars: &NamedVariables,
+) -> Result<Vec<(f32, f32)>, error::LooperError> {
+    let section = &member["section"];
+    let repeat = &member["repeat_sections"];
+    let names: Vec<&Yaml> = match (section.is_badvalue(), repeat.is_badvalue()) {
+        (true, true) => {
+            let start = yaml_as_measure(&member["start"], vars)?;
+            let stop = yaml_as_measure(&member["stop"], vars)?;
+            return Ok(vec![(start, stop)]);
+        }
+        (false, true) => vec![section],
+        (true, false) => log::opt_abort_msg!(repeat.as_vec(), "\"repeat_sections\" must be a list")
+            .iter()
+            .collect(),
+        (false, false) => {
+            log::abort_msg!("Only one of \"section\" and \"repeat_sections\" can be given");
+        }
+    };
+    log::abort_if_msg!(
+        !member["start"].is_badvalue() || !member["stop"].is_badvalue(),
+        "A segment can't have both sections and a \"start\" or \"stop\""
+    );
+
+    let mut ranges = Vec::with_capacity(names.len());
+    for name in names {
+        let name = log::opt_abort_msg!(name.as_str(), "Section names must be strings");
+        let section =
+            log::opt_abort_msg!(vars.sections.get(name), format!("Unknown section: \"{}\"", name));
+        let start = yaml_as_measure(&section[0], vars)?;
+        let stop = yaml_as_measure(&section[1], vars)?;
+        ranges.push((start, stop));
+    }
+    Ok(ranges)
+}
+
+// Merge one yaml object over another. Hashes are merged key by key, and anything else in the
+// overlay replaces what's underneath.
+fn merge_yaml(base: &Yaml, overlay: &Yaml) -> Yaml {
+    match (base, overlay) {
+        (Yaml::Hash(base), Yaml::Hash(overlay)) => {
+            let mut merged = base.clone();
+            for (key, value) in overlay {
+                let value = match base.get(key) {
+                    Some(base_value) => merge_yaml(base_value, value),
+                    None => value.clone(),
+                };
+                merged.insert(key.clone(), value);
+            }
+            Yaml::Hash(merged)
+        }
+        _ => overlay.clone(),
+    }
+}
+
+// Merge one project file over another, adding its devices after the other file's devices.
+fn merge_project(base: &Yaml, overlay: &Yaml) -> Yaml {
+    let mut merged = merge_yaml(base, overlay);
+    if let (Some(base_devices), Some(devices), Yaml::Hash(hash)) =
+        (base["devices"].as_vec(), overlay["devices"].as_vec(), &mut merged)
+    {
+        let devices = base_devices.iter().chain(devices).cloned().collect();
+        hash.insert(Yaml::from_str("devices"), Yaml::Array(devices));
+    }
+    merged
+}
+
+// Load a project file and every file it includes, merged into one yaml document. Each file is
+// merged over the files it includes, except for "devices", which are added one after the other.
+//
+// # Arguments
+//
+// * filename - The file to load.
+// * files - Every file loaded so far.
+// * stack - The files which are including this one, to report cycles.
+fn read_project_file(
+    filename: &str,
+    files: &mut Vec<String>,
+    stack: &mut Vec<String>,
+) -> Result<Yaml, String> {
+    if stack.iter().any(|f| f == filename) {
+        let mut cycle = stack.clone();
+        cycle.push(filename.to_owned());
+        return Err(format!("Files include each other in a cycle: {}", cycle.join(" -> ")));
+    }
+    let docs = log::unwrap_abort_msg_str!(
+        read_yaml_file(filename),
+        format!("Could not read \"{}\"", filename)
+    );
+    let root = match docs.into_iter().next() {
+        Some(Yaml::Hash(root)) => root,
+        _ => return Err(format!("\"{}\" must be a yaml hash", filename)),
+    };
+    if !files.iter().any(|f| f == filename) {
+        files.push(filename.to_owned());
+    }
+
+    // Included files are relative to the file which includes them.
+    let includes = match root.get(&Yaml::from_str("include")) {
+        None => vec![],
+        Some(Yaml::String(include)) => vec![include.as_str()],
+        Some(Yaml::Array(list)) => {
+            let mut includes = vec![];
+            for include in list {
+                includes.push(log::opt_abort_str!(
+                    include.as_str(),
+                    format!("\"include\" in \"{}\" must be a list of files", filename)
+                ));
+            }
+            includes
+        }
+        Some(_) => return Err(format!("\"include\" in \"{}\" must be a list of files", filename)),
+    };
+    let directory = Path::new(filename).parent().unwrap_or(Path::new(""));
+    let mut merged = Yaml::Hash(Hash::new());
+    stack.push(filename.to_owned());
+    for include in includes {
+        let path = directory.join(include).to_string_lossy().into_owned();
+        merged = merge_project(&merged, &read_project_file(&path, files, stack)?);
+    }
+    stack.pop();
+
+    let mut root = root;
+    root.remove(&Yaml::from_str("include"));
+    Ok(merge_project(&merged, &Yaml::Hash(root)))
+}
+
+// Substitute template arguments into every string of a yaml object. A string which is just
+// "$NAME" is replaced by the argument itself, so numbers stay numbers and lists stay lists.
+// Otherwise "$NAME" or "${NAME}" is replaced by the text of the argument, e.g. "${PREFIX}kick".
+//
+// # Arguments
+//
+// * obj - The yaml object from the template.
+// * arguments - The value of every parameter.
+// * template - The name of the template, for error messages.
+fn substitute_arguments(
+    obj: &Yaml,
+    arguments: &HashMap<String, Yaml>,
+    template: &str,
+) -> Result<Yaml, String> {
+    let argument = |name: &str| match arguments.get(name) {
+        Some(v) => Ok(v),
+        None => Err(format!("Template \"{}\" refers to unknown parameter \"{}\"", template, name)),
+    };
+    match obj {
+        Yaml::String(s) => {
+            let chars: Vec<char> = s.chars().collect();
+            let mut result = String::new();
+            let mut i = 0;
+            while i < chars.len() {
+                if chars[i] != '$' {
+                    result.push(chars[i]);
+                    i += 1;
+                    continue;
+                }
+
+                // Find the parameter name, with or without braces.
+                let braced = chars.get(i + 1) == Some(&'{');
+                let start = if braced { i + 2 } else { i + 1 };
+                let mut end = start;
+                while end < chars.len() && (chars[end].is_alphanumeric() || chars[end] == '_') {
+                    end += 1;
+                }
+                log::abort_if_msg_str!(
+                    end == start || (braced && chars.get(end) != Some(&'}')),
+                    format!("Invalid parameter reference in \"{}\"", s)
+                );
+                let name: String = chars[start..end].iter().collect();
+                let next = if braced { end + 1 } else { end };
+                let value = argument(&name)?;
+                if i == 0 && next == chars.len() {
+                    return Ok(value.clone());
+                }
+                match value {
+                    Yaml::String(v) => result.push_str(v),
+                    Yaml::Integer(v) => result.push_str(&v.to_string()),
+                    Yaml::Real(v) => result.push_str(v),
+                    _ => {
+                        return Err(format!(
+                            "Parameter \"{}\" of template \"{}\" can't be part of \"{}\"",
+                            name, template, s
+                        ));
+                    }
+                }
+                i = next;
+            }
+            Ok(Yaml::String(result))
+        }
+        Yaml::Array(list) => {
+            let mut result = Vec::new();
+            for item in list {
+                result.push(substitute_arguments(item, arguments, template)?);
+            }
+            Ok(Yaml::Array(result))
+        }
+        Yaml::Hash(hash) => {
+            let mut result = Hash::new();
+            for (key, value) in hash {
+                result.insert(key.clone(), substitute_arguments(value, arguments, template)?);
+            }
+            Ok(Yaml::Hash(result))
+        }
+        _ => Ok(obj.clone()),
+    }
+}
+
+// Expand every template instance in a list of devices into the devices of the template. Templates
+// can use other templates.
+//
+// # Arguments
+//
+// * devices - The list of devices, some of which may be template instances.
+// * templates - Every template, by name.
+// * stack - The templates which are being expanded, to report cycles.
+fn expand_templates(
+    devices: &[Yaml],
+    templates: &Hash,
+    stack: &mut Vec<String>,
+) -> Result<Vec<Yaml>, String> {
+    let mut expanded: Vec<Yaml> = Vec::new();
+    for device in devices {
+        let template_name = match device["template"].as_str() {
+            Some(v) => v,
+            None => {
+                expanded.push(device.clone());
+                continue;
+            }
+        };
+        let template = log::opt_abort_str!(
+            templates.get(&Yaml::from_str(template_name)),
+            format!("Unknown template \"{}\"", template_name)
+        );
+        if stack.iter().any(|t| t == template_name) {
+            let mut cycle = stack.clone();
+            cycle.push(template_name.to_owned());
+            return Err(format!("Templates use each other in a cycle: {}", cycle.join(" -> ")));
+        }
+        let prefix = log::opt_abort_str!(
+            device["prefix"].as_str(),
+            format!(
+                "Instance of template \"{}\" did not contain a valid \"prefix\"",
+                template_name
+            )
+        );
+
+        // Every parameter needs an argument, unless it has a default.
+        let mut arguments: HashMap<String, Yaml> = HashMap::new();
+        arguments.insert("PREFIX".to_owned(), Yaml::String(prefix.to_owned()));
+        let parameters = template["parameters"]
+            .as_hash()
+            .cloned()
+            .unwrap_or_default();
+        let given = device["arguments"].as_hash().cloned().unwrap_or_default();
+        for key in given.keys() {
+            log::abort_if_msg_str!(
+                !parameters.contains_key(key),
+                format!(
+                    "Template \"{}\" has no parameter \"{}\"",
+                    template_name,
+                    key.as_str().unwrap_or("?")
+                )
+            );
+        }
+        for (key, default) in &parameters {
+            let name = log::opt_abort_str!(key.as_str(), "Parameter name must be a string");
+            let value = match (given.get(key), default) {
+                (Some(value), _) => value.clone(),
+                (None, Yaml::Null) => {
+                    return Err(format!(
+                        "Instance \"{}\" of template \"{}\" is missing parameter \"{}\"",
+                        prefix, template_name, name
+                    ));
+                }
+                (None, default) => default.clone(),
+            };
+            arguments.insert(name.to_owned(), value);
+        }
+
+        // Every device in the template gets the prefix in front of its name.
+        let template_devices = log::opt_abort_str!(
+            template["devices"].as_vec(),
+            format!("Template \"{}\" must have a list of \"devices\"", template_name)
+        );
+        let mut instance: Vec<Yaml> = Vec::new();
+        for template_device in template_devices {
+            let mut device = match substitute_arguments(template_device, &arguments, template_name)?
+            {
+                Yaml::Hash(device) => device,
+                _ => {
+                    return Err(format!("Devices of template \"{}\" must be hashes", template_name))
+                }
+            };
+            let name_key = match device.contains_key(&Yaml::from_str("template")) {
+                true => Yaml::from_str("prefix"),
+                false => Yaml::from_str("name"),
+            };
+            let name = log::opt_abort_str!(
+                device.get(&name_key).and_then(|n| n.as_str()),
+                format!("Devices of template \"{}\" must have a valid \"name\"", template_name)
+            );
+            let name = format!("{}{}", prefix, name);
+            device.insert(name_key, Yaml::String(name));
+            instance.push(Yaml::Hash(device));
+        }
+        stack.push(template_name.to_owned());
+        expanded.extend(expand_templates(&instance, templates, stack)?);
+        stack.pop();
+    }
+    Ok(expanded)
+}
+
+// Collect every string in a yaml object, any of which could be a variable name.
+fn yaml_strings<'a>(obj: &'a Yaml, strings: &mut Vec<&'a str>) {
+    match obj {
+        Yaml::String(s) => strings.push(s),
+        Yaml::Array(list) => {
+            for item in list {
+                yaml_strings(item, strings);
+            }
+        }
+        Yaml::Hash(hash) => {
+            for value in hash.values() {
+                yaml_strings(value, strings);
+            }
+        }
+        _ => {}
+    }
+}
+
 // Parse a yaml object as an f32. Supports casting if the object is an int.
-fn yaml_as_f32_opt(obj: &Yaml, vars: &NamedVariables, default: &f32) -> Result<f32, ()> {
+fn yaml_as_f32_opt(
+    obj: &Yaml,
+    vars: &NamedVariables,
+    default: &f32,
+) -> Result<f32, error::LooperError> {
     let result = match obj {
         Yaml::Real(_) => obj.as_f64().unwrap() as f32,
         Yaml::Integer(_) => obj.as_i64().unwrap() as f32,
-        Yaml::String(s) => {
-            log::opt_abort_msg!(vars.resolve_f32(s), format!("Could not resolve variable: {}", s))
-        }
+        Yaml::String(s) => evaluate_f32(s, vars)?,
         _ => *default,
     };
     Ok(result)
 }
 
+// Parse a yaml object as a latency in samples. Integers are a number of samples and strings ending
+// in "ms" are a number of milliseconds.
+fn yaml_as_latency_opt(
+    obj: &Yaml,
+    vars: &NamedVariables,
+    sample_rate: i32,
+    default: usize,
+) -> Result<usize, error::LooperError> {
+    let latency = match obj {
+        Yaml::String(s) if s.ends_with("ms") => {
+            let ms = log::unwrap_abort_msg!(
+                s.trim_end_matches("ms").trim().parse::<f32>(),
+                format!("Invalid latency: {}", s)
+            );
+            (ms * sample_rate as f32 / 1000.0).round() as i32
+        }
+        _ => yaml_as_i32_opt(obj, vars, &(default as i32))?,
+    };
+    log::abort_if_msg!(latency < 0, "Latency must not be negative");
+    Ok(latency as usize)
+}
+
+impl CountIn {
+    /// A count-in of some number of measures with the default click and no accent.
+    pub fn new(measures: u32) -> Self {
+        CountIn {
+            measures: measures,
+            sound: "hihat-closed1".to_owned(),
+            accent_sound: None,
+        }
+    }
+}
+
+// Parse a yaml list of numbers as a groove template.
+fn yaml_as_groove(obj: &Yaml, vars: &NamedVariables) -> Result<Groove, error::LooperError> {
+    let list = log::opt_abort_msg!(obj.as_vec(), "A groove must be a list of offsets");
+    let mut offsets = Vec::with_capacity(list.len());
+    for item in list {
+        offsets.push(yaml_as_f32(item, vars)?);
+    }
+    let groove = Groove { offsets: offsets };
+    groove.validate()?;
+    Ok(groove)
+}
+
+// Parse the optional "groove" (a template name) or "swing" (an amount) of a project or a block.
+fn yaml_as_groove_opt(
+    obj: &Yaml,
+    vars: &NamedVariables,
+) -> Result<Option<Groove>, error::LooperError> {
+    let groove = &obj["groove"];
+    let swing = &obj["swing"];
+    log::abort_if_msg!(
+        !groove.is_badvalue() && !swing.is_badvalue(),
+        "Only one of \"groove\" and \"swing\" can be given"
+    );
+    if !swing.is_badvalue() {
+        let groove = Groove::swing(yaml_as_f32(swing, vars)?);
+        groove.validate()?;
+        return Ok(Some(groove));
+    }
+    if groove.is_badvalue() {
+        return Ok(None);
+    }
+    let name = log::opt_abort_msg!(groove.as_str(), "\"groove\" must be a name");
+    let groove =
+        log::opt_abort_msg!(vars.grooves.get(name), format!("Unknown groove: \"{}\"", name));
+    Ok(Some(groove.clone()))
+}
+
+impl Groove {
+    /// A groove which delays every second subdivision, e.g. swung eighth notes. An amount of 1/3
+    /// gives a triplet feel.
+    pub fn swing(amount: f32) -> Self {
+        Groove {
+            offsets: vec![0.0, amount],
+        }
+    }
+
+    /// Check that the subdivisions stay in order.
+    fn validate(&self) -> Result<(), error::LooperError> {
+        log::abort_if_msg!(
+            self.offsets.first() != Some(&0.0),
+            "The first offset of a groove must be 0"
+        );
+        for (i, offset) in self.offsets.iter().enumerate() {
+            let next = self.offsets.get(i + 1).unwrap_or(&0.0) + 1.0;
+            log::abort_if_msg!(
+                *offset >= next,
+                format!("Groove offset {} overlaps the next subdivision", offset)
+            );
+        }
+        Ok(())
+    }
+}
+
+impl PracticeLoop {
+    /// Parse a practice loop from the command line as "<start>:<stop>" or
+    /// "<start>:<stop>:<repeats>".
+    pub fn from_arg(arg: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = arg.split(':').collect();
+        log::abort_if_msg_str!(
+            parts.len() != 2 && parts.len() != 3,
+            format!("Expected <start>:<stop>[:<repeats>], got \"{}\"", arg)
+        );
+
+        let start = log::unwrap_abort_msg_str!(parts[0].parse::<f32>(), "Invalid practice start");
+        let stop = log::unwrap_abort_msg_str!(parts[1].parse::<f32>(), "Invalid practice stop");
+        let repeats = match parts.get(2) {
+            Some(v) => Some(log::unwrap_abort_msg_str!(v.parse::<u32>(), "Invalid repeats")),
+            None => None,
+        };
+
+        let practice_loop = PracticeLoop {
+            start: start,
+            stop: stop,
+            repeats: repeats,
+        };
+        practice_loop.validate()?;
+        Ok(practice_loop)
+    }
+
+    /// Check that the range makes sense.
+    fn validate(&self) -> Result<(), String> {
+        log::abort_if_msg_str!(self.start < 0.0, "Practice loop must not start before 0");
+        log::abort_if_msg_str!(
+            self.start >= self.stop,
+            "Practice loop \"start\" must be before \"stop\""
+        );
+        log::abort_if_msg_str!(
+            self.repeats == Some(0),
+            "Practice loop \"repeats\" must be positive"
+        );
+        Ok(())
+    }
+
+    /// Check that the range ends before playback stops, so that it gets played at all.
+    ///
+    /// # Arguments
+    ///
+    /// * stop_measure - The measure where playback stops, or negative to play forever.
+    pub fn validate_stop(&self, stop_measure: f32) -> Result<(), String> {
+        log::abort_if_msg_str!(
+            stop_measure >= 0.0 && self.stop > stop_measure,
+            "Practice loop \"stop\" must not be after \"stop_measure\""
+        );
+        Ok(())
+    }
+}
+
 impl ProjectConfig {
-    /// Initialze a new project config.
+    /// Initialze a new project config from a file, and every file it includes.
     pub fn new(filename: &str) -> Result<ProjectConfig, String> {
-        let root = &log::unwrap_abort_str!(read_yaml_file(filename))[0];
+        let mut files: Vec<String> = Vec::new();
+        let root = &read_project_file(filename, &mut files, &mut Vec::new())?;
 
         // Read in the global configs.
         let global_config = &root["config"];
         log::abort_if_msg_str!(global_config.is_badvalue(), "Missing top-level \"config\" key");
 
-        // Load all of the variables.
+        // Load all of the variables. Variables can be expressions which refer to each other, so
+        // those are evaluated after the plain numbers, in dependency order.
         let mut vars_i32: HashMap<String, i32> = HashMap::new();
         let mut vars_f32: HashMap<String, f32> = HashMap::new();
+        let mut values: HashMap<String, f64> = HashMap::new();
+        let mut definitions: HashMap<String, expr::Expr> = HashMap::new();
+        let mut order: Vec<String> = Vec::new();
         match global_config["variables"].as_hash() {
             Some(var_config) => {
                 for (yk, value) in var_config.iter() {
@@ -174,9 +923,21 @@ impl ProjectConfig {
                         Yaml::Integer(v) => {
                             vars_i32.insert(key.to_owned(), *v as i32);
                             vars_f32.insert(key.to_owned(), *v as f32);
+                            values.insert(key.to_owned(), *v as f64);
                         }
                         Yaml::Real(_) => {
                             vars_f32.insert(key.to_owned(), value.as_f64().unwrap() as f32);
+                            values.insert(key.to_owned(), value.as_f64().unwrap());
+                        }
+                        Yaml::String(s) => {
+                            let definition = match expr::parse(s) {
+                                Ok(v) => v,
+                                Err(e) => {
+                                    return Err(format!("Invalid variable \"{}\": {}", key, e))
+                                }
+                            };
+                            definitions.insert(key.to_owned(), definition);
+                            order.push(key.to_owned());
                         }
                         _ => {
                             return Err(format!("Unsupport variable type for \"{}\"", key));
@@ -186,20 +947,65 @@ impl ProjectConfig {
             }
             None => {}
         };
-        let variables = Rc::new(RefCell::new(NamedVariables {
+        for key in &order {
+            let value = evaluate_variable(key, &definitions, &mut values, &mut Vec::new())?;
+            vars_f32.insert(key.to_owned(), value as f32);
+            if value.fract() == 0.0 {
+                vars_i32.insert(key.to_owned(), value as i32);
+            }
+        }
+        let mut variables = NamedVariables {
             vars_i32: vars_i32,
             vars_f32: vars_f32,
-        }));
+            grooves: HashMap::new(),
+            sections: HashMap::new(),
+            timeline: None,
+        };
+
+        // Load all of the groove templates.
+        if let Some(groove_config) = global_config["grooves"].as_hash() {
+            for (yk, value) in groove_config.iter() {
+                let key = log::opt_abort_str!(yk.as_str(), "Groove name must be a string");
+                let groove = log::unwrap_abort_msg_str!(
+                    yaml_as_groove(value, &variables),
+                    format!("Invalid groove \"{}\"", key)
+                );
+                variables.grooves.insert(key.to_owned(), groove);
+            }
+        }
+        // Load the song sections. Their times are resolved by the segments which use them.
+        if let Some(section_config) = global_config["sections"].as_hash() {
+            for (yk, value) in section_config.iter() {
+                let key = log::opt_abort_str!(yk.as_str(), "Section name must be a string");
+                log::abort_if_msg_str!(
+                    value.as_vec().map_or(true, |v| v.len() != 2),
+                    format!("Section \"{}\" must be a [start, stop] list", key)
+                );
+                variables.sections.insert(key.to_owned(), value.clone());
+            }
+        }
+
+        let groove = log::unwrap_abort_msg_str!(
+            yaml_as_groove_opt(global_config, &variables),
+            "Invalid project groove"
+        );
+        let variables = Rc::new(RefCell::new(variables));
 
-        // Load all of the blocks.
+        // Load all of the blocks, after expanding the templates into ordinary devices.
         let block_config = match root["devices"].as_vec() {
             Some(v) => v,
             None => {
                 return Err("\"devices\" must be a list".to_owned());
             }
         };
+        let templates = match &root["templates"] {
+            Yaml::BadValue => Hash::new(),
+            Yaml::Hash(templates) => templates.clone(),
+            _ => return Err("\"templates\" must be a hash".to_owned()),
+        };
+        let block_config = expand_templates(block_config, &templates, &mut Vec::new())?;
         let mut blocks: Vec<BlockConfig> = Vec::new();
-        for block in block_config {
+        for block in &block_config {
             let name = log::opt_abort_str!(
                 block["name"].as_str(),
                 "Block did not contain a valid \"name\""
@@ -208,6 +1014,10 @@ impl ProjectConfig {
                 block["type"].as_str(),
                 format!("Block \"{}\" did not contain a valid \"type\"", name)
             );
+            log::abort_if_msg_str!(
+                blocks.iter().any(|b| b.name == name),
+                format!("Block name \"{}\" is not unique", name)
+            );
 
             blocks.push(BlockConfig {
                 name: name.to_owned(),
@@ -226,49 +1036,296 @@ impl ProjectConfig {
             yaml_as_f32_opt(&global_config["stop_measure"], &variables.borrow(), &-1f32),
             ""
         );
+        let seek_measures = log::unwrap_abort_msg_str!(
+            yaml_as_f32_opt(&global_config["seek_measures"], &variables.borrow(), &1f32),
+            "Invalid \"seek_measures\""
+        );
         println!("Start measure: {}", start_measure);
         println!("Stop measure: {}", stop_measure);
 
+        // Load in the practice loop.
+        let practice_config = &global_config["practice_loop"];
+        let practice_loop = match practice_config.is_badvalue() {
+            true => None,
+            false => {
+                let vars = &variables.borrow();
+                let repeats = log::unwrap_abort_msg_str!(
+                    yaml_as_i32_opt(&practice_config["repeats"], vars, &-1),
+                    "Invalid practice loop \"repeats\""
+                );
+                log::abort_if_msg_str!(
+                    repeats == 0 || repeats < -1,
+                    "Practice loop \"repeats\" must be positive"
+                );
+                let practice_loop = PracticeLoop {
+                    start: log::unwrap_abort_msg_str!(
+                        yaml_as_f32(&practice_config["start"], vars),
+                        "Invalid practice loop \"start\""
+                    ),
+                    stop: log::unwrap_abort_msg_str!(
+                        yaml_as_f32(&practice_config["stop"], vars),
+                        "Invalid practice loop \"stop\""
+                    ),
+                    repeats: if repeats > 0 {
+                        Some(repeats as u32)
+                    } else {
+                        None
+                    },
+                };
+                practice_loop.validate()?;
+                practice_loop.validate_stop(stop_measure)?;
+                Some(practice_loop)
+            }
+        };
+
+        // Load in the count-in.
+        let count_in_config = &global_config["count_in"];
+        let count_in = match count_in_config.is_badvalue() {
+            true => None,
+            false => {
+                let measures = log::unwrap_abort_msg_str!(
+                    yaml_as_i32(&count_in_config["measures"], &variables.borrow()),
+                    "Invalid count-in \"measures\""
+                );
+                log::abort_if_msg_str!(measures <= 0, "Count-in \"measures\" must be positive");
+                let mut count_in = CountIn::new(measures as u32);
+                if let Some(sound) = count_in_config["sound"].as_str() {
+                    count_in.sound = sound.to_owned();
+                }
+                count_in.accent_sound = count_in_config["accent_sound"].as_str().map(String::from);
+                Some(count_in)
+            }
+        };
+
+        // Load in the tempo changes.
+        let mut tempo_changes: Vec<TempoChange> = Vec::new();
+        if !global_config["tempo_changes"].is_badvalue() {
+            let changes_config = log::opt_abort_str!(
+                global_config["tempo_changes"].as_vec(),
+                "\"tempo_changes\" must be a list"
+            );
+            let vars = &variables.borrow();
+            for change_config in changes_config {
+                let change = TempoChange {
+                    measure: log::unwrap_abort_msg_str!(
+                        yaml_as_f32(&change_config["measure"], vars),
+                        "Invalid tempo change \"measure\""
+                    ),
+                    bpm: log::unwrap_abort_msg_str!(
+                        yaml_as_f32(&change_config["bpm"], vars),
+                        "Invalid tempo change \"bpm\""
+                    ),
+                    ramp: log::unwrap_abort_msg_str!(
+                        yaml_as_f32_opt(&change_config["ramp"], vars, &0f32),
+                        "Invalid tempo change \"ramp\""
+                    ),
+                };
+                log::abort_if_msg_str!(
+                    change.measure < 0.0,
+                    "Tempo change \"measure\" must not be negative"
+                );
+                log::abort_if_msg_str!(change.bpm <= 0.0, "Tempo change \"bpm\" must be positive");
+                log::abort_if_msg_str!(
+                    change.ramp < 0.0,
+                    "Tempo change \"ramp\" must not be negative"
+                );
+                if let Some(previous) = tempo_changes.last() {
+                    log::abort_if_msg_str!(
+                        change.measure < previous.measure + previous.ramp,
+                        format!(
+                            "Tempo change at measure {} starts before the previous one finishes",
+                            change.measure
+                        )
+                    );
+                }
+                tempo_changes.push(change);
+            }
+        }
+
+        // Load in the time signature changes.
+        let mut meter_changes: Vec<MeterChange> = Vec::new();
+        if !global_config["meter_changes"].is_badvalue() {
+            let changes_config = log::opt_abort_str!(
+                global_config["meter_changes"].as_vec(),
+                "\"meter_changes\" must be a list"
+            );
+            let vars = &variables.borrow();
+            for change_config in changes_config {
+                let beat_duration = log::unwrap_abort_msg_str!(
+                    yaml_as_i32_opt(&change_config["beat_duration"], vars, &0),
+                    "Invalid meter change \"beat_duration\""
+                );
+                let change = MeterChange {
+                    measure: log::unwrap_abort_msg_str!(
+                        yaml_as_i32(&change_config["measure"], vars),
+                        "Invalid meter change \"measure\", it must be a whole measure"
+                    ),
+                    beats_per_measure: log::unwrap_abort_msg_str!(
+                        yaml_as_i32(&change_config["beats_per_measure"], vars),
+                        "Invalid meter change \"beats_per_measure\""
+                    ),
+                    beat_duration: if beat_duration != 0 {
+                        Some(beat_duration)
+                    } else {
+                        None
+                    },
+                };
+                log::abort_if_msg_str!(
+                    change.measure < 0,
+                    "Meter change \"measure\" must not be negative"
+                );
+                log::abort_if_msg_str!(
+                    change.beats_per_measure <= 0,
+                    "Meter change \"beats_per_measure\" must be positive"
+                );
+                log::abort_if_msg_str!(
+                    beat_duration < 0,
+                    "Meter change \"beat_duration\" must be positive"
+                );
+                if let Some(previous) = meter_changes.last() {
+                    log::abort_if_msg_str!(
+                        change.measure <= previous.measure,
+                        format!("Meter change at measure {} is out of order", change.measure)
+                    );
+                }
+                meter_changes.push(change);
+            }
+        }
+
+        // Load in the audio format.
+        let sample_rate = log::unwrap_abort_msg_str!(
+            yaml_as_i32_opt(
+                &global_config["sample_rate"],
+                &variables.borrow(),
+                &stream::DEFAULT_SAMPLE_RATE
+            ),
+            "Invalid \"sample_rate\""
+        );
+        let buffer_size = log::unwrap_abort_msg_str!(
+            yaml_as_i32_opt(
+                &global_config["buffer_size"],
+                &variables.borrow(),
+                &(stream::DEFAULT_BUFFER_SIZE as i32)
+            ),
+            "Invalid \"buffer_size\""
+        );
+        let num_channels = log::unwrap_abort_msg_str!(
+            yaml_as_i32_opt(
+                &global_config["num_channels"],
+                &variables.borrow(),
+                &(stream::DEFAULT_NUM_CHANNELS as i32)
+            ),
+            "Invalid \"num_channels\""
+        );
+        log::abort_if_msg_str!(sample_rate <= 0, "\"sample_rate\" must be positive");
+        log::abort_if_msg_str!(buffer_size <= 0, "\"buffer_size\" must be positive");
+        log::abort_if_msg_str!(num_channels <= 0, "\"num_channels\" must be positive");
+        let latency = log::unwrap_abort_msg_str!(
+            yaml_as_latency_opt(&global_config["latency"], &variables.borrow(), sample_rate, 0),
+            "Invalid \"latency\""
+        );
+
         Ok(ProjectConfig {
             tempo_config: global_config["tempo"].clone(),
             start_measure: start_measure,
             stop_measure: stop_measure,
+            sample_rate: sample_rate,
+            buffer_size: buffer_size as usize,
+            num_channels: num_channels as usize,
+            latency: latency,
+            seek_measures: seek_measures,
+            practice_loop: practice_loop,
+            count_in: count_in,
+            tempo_changes: tempo_changes,
+            meter_changes: meter_changes,
+            groove: groove,
             blocks: blocks,
+            files: files,
+            variables: variables,
         })
     }
+
+    /// Get a whole number from the tempo config, which can be a variable or an expression, or None
+    /// if it's missing.
+    pub fn get_tempo_i32_opt(&self, key: &str) -> Result<Option<i32>, String> {
+        let value = &self.tempo_config[key];
+        if value.is_badvalue() {
+            return Ok(None);
+        }
+        match yaml_as_i32(value, &self.variables.borrow()) {
+            Ok(v) => Ok(Some(v)),
+            Err(e) => Err(format!("Invalid tempo \"{}\": {}", key, e)),
+        }
+    }
+
+    /// Let the blocks resolve segment times written in beats or seconds through the timeline.
+    pub fn set_timeline(&self, timeline: Rc<dyn Timeline>) {
+        self.variables.borrow_mut().timeline = Some(timeline);
+    }
+}
+
+/// Build an error for an invalid configuration value and log a trace with println!.
+macro_rules! config_error {
+    ( $e:expr, $name:expr, $key:expr, $msg:expr ) => {{
+        let e = error::LooperError::Config {
+            block: $name.to_string(),
+            key: $key.to_string(),
+            cause: $msg.to_string(),
+        };
+        println!("{}:{} abort({}): {}", file!(), line!(), stringify!($e), e);
+        e
+    }};
 }
 
 /// Shorthand for asserting configuration values are valid with context.
 macro_rules! abort_config {
     ( $e:expr, $name:expr, $key:expr, $msg:expr ) => {
-        log::abort_if_msg!($e, format!("device=\"{}\" -> key=\"{}\": {}", $name, $key, $msg));
+        if $e {
+            return Err(config_error!($e, $name, $key, $msg));
+        }
     };
 }
 
 /// Shorthand for asserting configuration values are valid with context.
 macro_rules! unwrap_config {
     ( $e:expr, $name:expr, $key:expr, $msg:expr ) => {
-        log::opt_abort_msg!($e, format!("device=\"{}\" -> key=\"{}\": {}", $name, $key, $msg))
+        match $e {
+            Some(x) => x,
+            None => return Err(config_error!($e, $name, $key, $msg)),
+        }
     };
 }
 
 impl BlockConfig {
     /// Get a Yaml value from a key. Returns an error if the value is not present.
     /// i.e. obj.is_badvalue() returns true.
-    pub fn get_value(&self, key: &str) -> Result<&Yaml, ()> {
+    pub fn get_value(&self, key: &str) -> Result<&Yaml, error::LooperError> {
         let value = &self.root[key];
         abort_config!(value.is_badvalue(), self.name, key, "Missing required parameter");
         Ok(value)
     }
 
+    /// Get a Yaml value from a key, or None if the value is not present.
+    pub fn get_value_opt(&self, key: &str) -> Option<&Yaml> {
+        let value = &self.root[key];
+        match value.is_badvalue() {
+            true => None,
+            false => Some(value),
+        }
+    }
+
     /// Get a boolean value from config.
-    pub fn get_bool(&self, key: &str) -> Result<bool, ()> {
+    pub fn get_bool(&self, key: &str) -> Result<bool, error::LooperError> {
         let value = self.get_value(key)?;
         Ok(unwrap_config!(value.as_bool(), self.name, key, "Expected a boolean value"))
     }
 
     /// Get an optional bool value from config with a default.
-    pub fn get_bool_opt<'a>(&'a self, key: &str, default: bool) -> Result<bool, ()> {
+    pub fn get_bool_opt<'a>(
+        &'a self,
+        key: &str,
+        default: bool,
+    ) -> Result<bool, error::LooperError> {
         let value = &self.root[key];
         if value.is_badvalue() {
             return Ok(default);
@@ -277,13 +1334,17 @@ impl BlockConfig {
     }
 
     /// Get a string value from config.
-    pub fn get_str(&self, key: &str) -> Result<&str, ()> {
+    pub fn get_str(&self, key: &str) -> Result<&str, error::LooperError> {
         let value = self.get_value(key)?;
         Ok(unwrap_config!(value.as_str(), self.name, key, "Expected a string value"))
     }
 
     /// Get an optional string value from config with a default.
-    pub fn get_str_opt<'a>(&'a self, key: &str, default: &'a str) -> Result<&str, ()> {
+    pub fn get_str_opt<'a>(
+        &'a self,
+        key: &str,
+        default: &'a str,
+    ) -> Result<&str, error::LooperError> {
         let value = &self.root[key];
         if value.is_badvalue() {
             return Ok(default);
@@ -291,32 +1352,69 @@ impl BlockConfig {
         Ok(unwrap_config!(value.as_str(), self.name, key, "Expected a string value"))
     }
 
+    /// Attribute an error parsing a value to one of this block's keys.
+    fn key_error(&self, key: &str, e: error::LooperError) -> error::LooperError {
+        match e {
+            error::LooperError::Abort { cause } => error::LooperError::Config {
+                block: self.name.to_owned(),
+                key: key.to_owned(),
+                cause: cause,
+            },
+            e => e,
+        }
+    }
+
     /// Get an int value from config.
-    pub fn get_i32(&self, key: &str) -> Result<i32, ()> {
+    pub fn get_i32(&self, key: &str) -> Result<i32, error::LooperError> {
         let value = self.get_value(key)?;
-        yaml_as_i32(value, &self.variables.borrow())
+        yaml_as_i32(value, &self.variables.borrow()).map_err(|e| self.key_error(key, e))
     }
 
     /// Get an optional int value from config with a default.
-    pub fn get_i32_opt(&self, key: &str, default: &i32) -> Result<i32, ()> {
+    pub fn get_i32_opt(&self, key: &str, default: &i32) -> Result<i32, error::LooperError> {
         let value = &self.root[key];
         yaml_as_i32_opt(value, &self.variables.borrow(), default)
+            .map_err(|e| self.key_error(key, e))
     }
 
     /// Get a float value from config.
-    pub fn get_f32(&self, key: &str) -> Result<f32, ()> {
+    pub fn get_f32(&self, key: &str) -> Result<f32, error::LooperError> {
         let value = self.get_value(key)?;
-        yaml_as_f32(value, &self.variables.borrow())
+        yaml_as_f32(value, &self.variables.borrow()).map_err(|e| self.key_error(key, e))
     }
 
     /// Get an optional float value from config with a default.
-    pub fn get_f32_opt(&self, key: &str, default: &f32) -> Result<f32, ()> {
+    pub fn get_f32_opt(&self, key: &str, default: &f32) -> Result<f32, error::LooperError> {
         let value = &self.root[key];
         yaml_as_f32_opt(value, &self.variables.borrow(), default)
+            .map_err(|e| self.key_error(key, e))
+    }
+
+    /// Get the block's optional groove, given either as the name of a groove template in "groove"
+    /// or as a swing amount in "swing".
+    pub fn get_groove_opt(&self) -> Result<Option<Groove>, error::LooperError> {
+        yaml_as_groove_opt(&self.root, &self.variables.borrow())
+            .map_err(|e| self.key_error("groove", e))
+    }
+
+    /// Get an optional latency from config [samples], given either as a number of samples or as a
+    /// number of milliseconds like "12.5ms".
+    pub fn get_latency_opt(
+        &self,
+        key: &str,
+        sample_rate: i32,
+    ) -> Result<Option<usize>, error::LooperError> {
+        let value = &self.root[key];
+        if value.is_badvalue() {
+            return Ok(None);
+        }
+        let latency = yaml_as_latency_opt(value, &self.variables.borrow(), sample_rate, 0)
+            .map_err(|e| self.key_error(key, e))?;
+        Ok(Some(latency))
     }
 
     /// Get a list of output channels.
-    pub fn get_str_list(&self, key: &str) -> Result<Vec<&str>, ()> {
+    pub fn get_str_list(&self, key: &str) -> Result<Vec<&str>, error::LooperError> {
         let value = self.get_value(key)?;
         let list_vec = unwrap_config!(value.as_vec(), self.name, key, "Expected a list");
 
@@ -329,8 +1427,63 @@ impl BlockConfig {
         Ok(str_list)
     }
 
+    /// Get an optional list of float values from config. Returns an empty list if it's missing.
+    pub fn get_f32_list_opt(&self, key: &str) -> Result<Vec<f32>, error::LooperError> {
+        let value = &self.root[key];
+        if value.is_badvalue() {
+            return Ok(Vec::new());
+        }
+        let list_vec = unwrap_config!(value.as_vec(), self.name, key, "Expected a list");
+
+        let mut f32_list: Vec<f32> = Vec::new();
+        for member in list_vec {
+            let val = yaml_as_f32(member, &self.variables.borrow())
+                .map_err(|e| self.key_error(key, e))?;
+            f32_list.push(val);
+        }
+
+        Ok(f32_list)
+    }
+
+    /// Whether another config would build the same block, i.e. it has the same type and
+    /// parameters, and every variable that the parameters refer to has the same value.
+    pub fn same_as(&self, other: &BlockConfig) -> bool {
+        if self.block_type != other.block_type || self.root != other.root {
+            return false;
+        }
+
+        let vars = self.variables.borrow();
+        let other_vars = other.variables.borrow();
+        let mut names = Vec::<&str>::new();
+        yaml_strings(&self.root, &mut names);
+
+        // Sections can refer to variables too.
+        let mut section_names = Vec::<&str>::new();
+        for name in &names {
+            if let Some(section) = vars.sections.get(*name) {
+                yaml_strings(section, &mut section_names);
+            }
+        }
+        names.extend(section_names);
+
+        // So can musical times, e.g. "CHORUS:3", and expressions, e.g. "CHORUS + 4".
+        let parts: Vec<&str> = names.iter().flat_map(|s| time_parts(s)).collect();
+        names.extend(parts);
+        let identifiers: Vec<&str> = names
+            .iter()
+            .flat_map(|s| s.split(|c: char| !c.is_alphanumeric() && c != '_'))
+            .filter(|s| !s.is_empty())
+            .collect();
+        names.extend(identifiers);
+        names.iter().all(|name| {
+            vars.resolve_f32(name) == other_vars.resolve_f32(name)
+                && vars.grooves.get(*name) == other_vars.grooves.get(*name)
+                && vars.sections.get(*name) == other_vars.sections.get(*name)
+        })
+    }
+
     /// Get the segments.
-    pub fn get_segments(&self) -> Result<Vec<segment::Segment>, ()> {
+    pub fn get_segments(&self) -> Result<Vec<segment::Segment>, error::LooperError> {
         let mut segments: Vec<segment::Segment> = Vec::new();
 
         // segments is not required. Return empty list if it's missing.
@@ -348,27 +1501,42 @@ impl BlockConfig {
                 SEGMENTS_KEY,
                 "Each segment must be an object"
             );
-            let start = log::unwrap_abort!(yaml_as_f32(&member["start"], &self.variables.borrow()));
-            let stop = log::unwrap_abort!(yaml_as_f32(&member["stop"], &self.variables.borrow()));
-            let type_str = log::opt_abort!(member["type"].as_str());
+            let vars = &self.variables.borrow();
+            let ranges =
+                yaml_as_ranges(member, vars).map_err(|e| self.key_error(SEGMENTS_KEY, e))?;
+            let type_str = unwrap_config!(
+                member["type"].as_str(),
+                self.name,
+                SEGMENTS_KEY,
+                "Each segment must have a \"type\""
+            );
+            let segment_type = segment::SegmentType::from(type_str);
             let name = match member["name"].as_str() {
                 Some(s) => Some(s.to_owned()),
                 None => None,
             };
 
             abort_config!(
-                start > stop,
+                segment_type == segment::SegmentType::Invalid,
                 self.name,
                 SEGMENTS_KEY,
-                "Segment start must be < segment stop"
+                format!("Unknown segment type \"{}\"", type_str)
             );
 
-            segments.push(segment::Segment {
-                start: start,
-                stop: stop,
-                segment_type: segment::SegmentType::from(type_str),
-                name: name,
-            })
+            for (start, stop) in ranges {
+                abort_config!(
+                    start > stop,
+                    self.name,
+                    SEGMENTS_KEY,
+                    "Segment start must be < segment stop"
+                );
+                segments.push(segment::Segment {
+                    start: start,
+                    stop: stop,
+                    segment_type: segment_type.clone(),
+                    name: name.clone(),
+                })
+            }
         }
 
         Ok(segments)
@@ -376,6 +1544,17 @@ impl BlockConfig {
 }
 
 impl NamedVariables {
+    // Evaluate an expression of numbers and variables, e.g. "CHORUS_1_START + 4". A plain variable
+    // name is looked up as is.
+    pub fn evaluate(self: &Self, s: &str) -> Result<f32, String> {
+        if let Some(value) = self.resolve_f32(s) {
+            return Ok(value);
+        }
+        let expr = expr::parse(s)?;
+        let value = expr.evaluate(&|name| self.resolve_f32(name).map(|v| v as f64))?;
+        Ok(value as f32)
+    }
+
     // Get an int32 variable.
     pub fn resolve_i32(self: &Self, s: &str) -> Option<i32> {
         self.vars_i32.get(s).copied()
@@ -415,6 +1594,16 @@ mod tests {
 
         assert_eq!(project.start_measure, 0.0);
         assert_eq!(project.stop_measure, 20.0);
+        assert_eq!(project.sample_rate, 44100);
+        assert_eq!(project.buffer_size, 256);
+        assert_eq!(project.num_channels, 1);
+        assert_eq!(project.latency, 0);
+        assert_eq!(project.seek_measures, 1.0);
+        assert_eq!(project.practice_loop, None);
+        assert_eq!(project.count_in, None);
+        assert_eq!(project.tempo_changes, vec![]);
+        assert_eq!(project.meter_changes, vec![]);
+        assert_eq!(project.groove, None);
 
         // Test the block getters.
         let blocks = &project.blocks;
@@ -448,6 +1637,248 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_latency() {
+        let project = ProjectConfig::new("dat/config/latency.yaml").unwrap();
+        assert_eq!(project.latency, 480);
+
+        // Latency can be given in samples or milliseconds.
+        let block = &project.blocks[0];
+        assert_eq!(block.get_latency_opt("latency", 48000).unwrap(), Some(600));
+        assert_eq!(block.get_latency_opt("latency_samples", 48000).unwrap(), Some(256));
+        assert_eq!(block.get_latency_opt("missing", 48000).unwrap(), None);
+        assert!(block.get_latency_opt("negative", 48000).is_err());
+    }
+
+    #[test]
+    fn test_practice_loop() {
+        let project = ProjectConfig::new("dat/config/practice_loop.yaml").unwrap();
+        assert_eq!(
+            project.practice_loop,
+            Some(PracticeLoop {
+                start: 15.0,
+                stop: 21.0,
+                repeats: Some(3),
+            })
+        );
+
+        // The same loop can be given on the command line, optionally forever.
+        assert_eq!(PracticeLoop::from_arg("15:21:3"), Ok(project.practice_loop.unwrap()));
+        assert_eq!(PracticeLoop::from_arg("2.5:4").unwrap().repeats, None);
+        assert!(PracticeLoop::from_arg("21:15").is_err());
+        assert!(PracticeLoop::from_arg("15:21:0").is_err());
+        assert!(PracticeLoop::from_arg("15").is_err());
+
+        // The loop has to end before playback stops, if it ever does.
+        assert!(ProjectConfig::new("dat/config/practice_loop_past_stop.yaml").is_err());
+        let practice_loop = PracticeLoop::from_arg("15:21").unwrap();
+        assert!(practice_loop.validate_stop(21.0).is_ok());
+        assert!(practice_loop.validate_stop(20.0).is_err());
+        assert!(practice_loop.validate_stop(-1.0).is_ok());
+    }
+
+    #[test]
+    fn test_tempo_changes() {
+        let project = ProjectConfig::new("dat/config/tempo_changes.yaml").unwrap();
+        assert_eq!(
+            project.tempo_changes,
+            vec![
+                TempoChange {
+                    measure: 17.0,
+                    bpm: 140.0,
+                    ramp: 0.0,
+                },
+                TempoChange {
+                    measure: 40.0,
+                    bpm: 90.5,
+                    ramp: 4.0,
+                },
+            ]
+        );
+
+        // A change can't start in the middle of another one's ramp.
+        assert!(ProjectConfig::new("dat/config/tempo_changes_overlap.yaml").is_err());
+    }
+
+    #[test]
+    fn test_meter_changes() {
+        let project = ProjectConfig::new("dat/config/meter_changes.yaml").unwrap();
+        assert_eq!(
+            project.meter_changes,
+            vec![
+                MeterChange {
+                    measure: 9,
+                    beats_per_measure: 7,
+                    beat_duration: Some(8),
+                },
+                MeterChange {
+                    measure: 17,
+                    beats_per_measure: 4,
+                    beat_duration: None,
+                },
+            ]
+        );
+
+        // Meters only change on a bar line.
+        assert!(ProjectConfig::new("dat/config/meter_changes_fraction.yaml").is_err());
+    }
+
+    #[test]
+    fn test_sections() {
+        let project = ProjectConfig::new("dat/config/sections.yaml").unwrap();
+        let blocks = &project.blocks;
+
+        // A segment can cover one section, or several in a row.
+        let segments = blocks[0].get_segments().unwrap();
+        let times: Vec<(f32, f32)> = segments.iter().map(|s| (s.start, s.stop)).collect();
+        assert_eq!(times, vec![(1.0, 3.0), (0.0, 4.0), (15.0, 21.0), (33.0, 39.0)]);
+        assert_eq!(segments[3].segment_type, segment::SegmentType::Output);
+
+        assert!(blocks[1].get_segments().is_err());
+        assert!(blocks[2].get_segments().is_err());
+    }
+
+    #[test]
+    fn test_expressions() {
+        let project = ProjectConfig::new("dat/config/expressions.yaml").unwrap();
+        let block = &project.blocks[0];
+
+        // Variables can refer to variables which are defined after them.
+        assert_eq!(block.get_i32("channel").unwrap(), 2);
+        assert_eq!(block.get_f32("volume").unwrap(), 0.25);
+        assert!(block.get_i32("fraction").is_err());
+
+        let segments = block.get_segments().unwrap();
+        let times: Vec<(f32, f32)> = segments.iter().map(|s| (s.start, s.stop)).collect();
+        assert_eq!(times, vec![(9.0, 13.0), (10.5, 24.0)]);
+
+        // Variables can't depend on themselves, or on variables which don't exist.
+        let cycle = ProjectConfig::new("dat/config/expressions_cycle.yaml");
+        assert_eq!(
+            cycle.err(),
+            Some(
+                "Variables refer to each other in a cycle: VERSE -> CHORUS -> BRIDGE -> VERSE"
+                    .to_owned()
+            )
+        );
+        let unknown = ProjectConfig::new("dat/config/expressions_unknown.yaml");
+        assert_eq!(
+            unknown.err(),
+            Some("Variable \"CHORUS\" refers to unknown variable \"BRIDGE\"".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_includes() {
+        let project = ProjectConfig::new("dat/config/includes.yaml").unwrap();
+        assert_eq!(
+            project.files,
+            vec![
+                "dat/config/includes.yaml",
+                "dat/config/include_drums.yaml",
+                "dat/config/include_song.yaml",
+            ]
+        );
+
+        // Each file is merged over the files it includes.
+        assert_eq!(project.tempo_config["bpm"].as_i64(), Some(120));
+        assert_eq!(project.tempo_config["beats_per_measure"].as_i64(), Some(3));
+        assert_eq!(project.tempo_config["beat_duration"].as_i64(), Some(4));
+
+        // Devices from the included files come first.
+        let names: Vec<&str> = project.blocks.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, vec!["drums", "bass"]);
+        let segments = project.blocks[1].get_segments().unwrap();
+        assert_eq!((segments[0].start, segments[0].stop), (9.0, 13.0));
+
+        assert_eq!(
+            ProjectConfig::new("dat/config/include_cycle.yaml").err(),
+            Some(
+                "Files include each other in a cycle: dat/config/include_cycle.yaml -> \
+                 dat/config/include_cycle_back.yaml -> dat/config/include_cycle.yaml"
+                    .to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn test_templates() {
+        let project = ProjectConfig::new("dat/config/templates.yaml").unwrap();
+        let blocks = &project.blocks;
+        let names: Vec<&str> = blocks.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "verse_loop",
+                "verse_mix",
+                "chorus_loop",
+                "chorus_mix",
+                "drums"
+            ]
+        );
+
+        // Arguments are substituted into the devices of the template.
+        assert_eq!(blocks[0].block_type, "Loop");
+        assert_eq!(blocks[0].get_str_list("input_channels").unwrap(), vec!["drums"]);
+        assert_eq!(blocks[0].get_str("output_channel").unwrap(), "verse_loop");
+        assert_eq!(blocks[0].get_f32("volume").unwrap(), 0.5);
+        assert_eq!(blocks[2].get_f32("volume").unwrap(), 1.0);
+        assert_eq!(blocks[3].get_str_list("input_channels").unwrap(), vec!["chorus_loop", "drums"]);
+        let times = |block: &BlockConfig| {
+            let segments = block.get_segments().unwrap();
+            (segments[0].start, segments[0].stop)
+        };
+        assert_eq!(times(&blocks[0]), (5.0, 9.0));
+        assert_eq!(times(&blocks[2]), (13.0, 21.0));
+
+        // Every instance in this file is invalid.
+        let root = &read_yaml_file("dat/config/templates_invalid.yaml").unwrap()[0];
+        let templates = root["templates"].as_hash().unwrap();
+        for device in root["devices"].as_vec().unwrap() {
+            let result = expand_templates(&[device.clone()], templates, &mut Vec::new());
+            assert!(result.is_err(), "{:?}", device);
+        }
+    }
+
+    #[test]
+    fn test_count_in() {
+        let project = ProjectConfig::new("dat/config/count_in.yaml").unwrap();
+        assert_eq!(
+            project.count_in,
+            Some(CountIn {
+                measures: 2,
+                sound: "hihat-closed1".to_owned(),
+                accent_sound: Some("crash-cymbal1".to_owned()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_grooves() {
+        let project = ProjectConfig::new("dat/config/grooves.yaml").unwrap();
+        assert_eq!(project.groove, Some(Groove::swing(0.25)));
+
+        // Blocks can pick a template, swing on their own, or follow the project.
+        let blocks = &project.blocks;
+        let shuffle = Groove {
+            offsets: vec![0.0, 0.1, -0.05],
+        };
+        assert_eq!(blocks[0].get_groove_opt().unwrap(), Some(shuffle));
+        assert_eq!(blocks[1].get_groove_opt().unwrap(), Some(Groove::swing(0.33)));
+        assert_eq!(blocks[2].get_groove_opt().unwrap(), None);
+        assert!(blocks[3].get_groove_opt().is_err());
+        assert!(blocks[4].get_groove_opt().is_err());
+
+        // Subdivisions can't be shifted past each other.
+        assert!(Groove::swing(1.0).validate().is_err());
+        assert!(Groove::swing(-0.9).validate().is_ok());
+        assert!(Groove::swing(-1.0).validate().is_err());
+        let late = Groove {
+            offsets: vec![0.1, 0.0],
+        };
+        assert!(late.validate().is_err());
+    }
+
     #[test]
     fn test_missing_devices() {
         // Expect an Err result because the devices config is missing.
@@ -490,6 +1921,31 @@ mod tests {
         assert_eq!(segments[3].stop, 20.0);
     }
 
+    #[test]
+    fn test_same_as() {
+        let before = ProjectConfig::new("dat/config/reload_before.yaml").unwrap();
+        let after = ProjectConfig::new("dat/config/reload_after.yaml").unwrap();
+
+        // Only the blocks which changed or refer to a variable that changed are different.
+        assert!(before.blocks[0].same_as(&after.blocks[0]));
+        assert!(!before.blocks[1].same_as(&after.blocks[1]));
+        assert!(!before.blocks[2].same_as(&after.blocks[2]));
+        assert!(!before.blocks[0].same_as(&before.blocks[1]));
+    }
+
+    #[test]
+    fn test_invalid_segment_type() {
+        let project = ProjectConfig::new("dat/config/invalid_segment_type.yaml").unwrap();
+
+        // The segment type is misspelled.
+        match project.blocks[0].get_segments() {
+            Ok(_) => {
+                panic!("Segment type should have been rejected.");
+            }
+            Err(_) => {}
+        };
+    }
+
     #[test]
     fn test_missing_variables() {
         let project = ProjectConfig::new("dat/config/missing_variables.yaml").unwrap();
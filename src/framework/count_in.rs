@@ -0,0 +1,186 @@
+//! Count-in before playback starts.
+//!
+//! The count-in plays a few measures of clicks at the tempo and time signature of the start
+//! measure, so that the first beat after the count-in is the first beat of the start measure. It
+//! has its own clock, so the project timeline doesn't move and no segments are triggered while it
+//! plays. Only sinks which play audio (see block::Sink::count_in()) hear it.
+//!
+//! ```
+//! let mut count_in = CountIn::new(&config, &tempo, num_channels)?;
+//! while !count_in.is_done() {
+//!     let clicks = count_in.next();
+//!     // Play the clicks.
+//! }
+//! ```
+
+extern crate config;
+extern crate error;
+extern crate sampler;
+extern crate stream;
+extern crate tempo;
+extern crate wav;
+
+/// Plays the count-in one buffer at a time.
+pub struct CountIn {
+    /// The clip to play on each beat.
+    clip: stream::Clip,
+
+    /// The clip to play on the first beat of each measure.
+    accent_clip: stream::Clip,
+
+    /// The sampler.
+    sampler: sampler::Sampler,
+
+    /// The number of samples in a beat.
+    samples_per_beat: f64,
+
+    /// The number of beats in a measure.
+    beats_per_measure: i32,
+
+    /// The number of beats to count in.
+    num_beats: i32,
+
+    /// How far into the count-in the first beat starts [samples]. The count-in is padded at the
+    /// start so that it's a whole number of buffers long.
+    lead: f64,
+
+    /// The number of samples in a single step.
+    buffer_size: usize,
+
+    /// The number of audio channels in the clicks.
+    num_channels: usize,
+
+    /// The current position in the count-in [samples].
+    current_sample: usize,
+
+    /// The length of the count-in [samples].
+    length: usize,
+
+    /// The clicks of the current buffer.
+    buffer: stream::RawStream,
+}
+
+impl CountIn {
+    /// Create a count-in which ends where the tempo currently is.
+    ///
+    /// # Arguments
+    ///
+    /// * config - The count-in configuration.
+    /// * tempo - The tempo, at the start measure.
+    /// * num_channels - The number of audio channels in the clicks.
+    pub fn new(
+        config: &config::CountIn,
+        tempo: &tempo::Tempo,
+        num_channels: usize,
+    ) -> Result<Self, error::LooperError> {
+        let load_clip = |sound: &str| {
+            wav::read_wav_file(&config::clip_path(sound), tempo.sample_rate, num_channels)
+        };
+        let clip = load_clip(&config.sound)?;
+        let accent_clip = match &config.accent_sound {
+            Some(sound) => load_clip(sound)?,
+            None => clip.clone(),
+        };
+
+        let samples_per_beat = tempo.current_samples_per_beat();
+        let beats_per_measure = tempo.current_meter().beats_per_measure;
+        let num_beats = config.measures as i32 * beats_per_measure;
+        let beats_length = num_beats as f64 * samples_per_beat;
+        let num_buffers = (beats_length / tempo.buffer_size as f64).ceil() as usize;
+        let length = num_buffers * tempo.buffer_size;
+
+        Ok(CountIn {
+            clip: clip,
+            accent_clip: accent_clip,
+            sampler: sampler::Sampler::new(),
+            samples_per_beat: samples_per_beat,
+            beats_per_measure: beats_per_measure,
+            num_beats: num_beats,
+            lead: length as f64 - beats_length,
+            buffer_size: tempo.buffer_size,
+            num_channels: num_channels,
+            current_sample: 0,
+            length: length,
+            buffer: vec![stream::ZERO; tempo.buffer_size * num_channels],
+        })
+    }
+
+    /// Whether the whole count-in has been played.
+    pub fn is_done(&self) -> bool {
+        self.current_sample >= self.length
+    }
+
+    /// The next beat which starts in the current buffer, how far into the buffer it starts
+    /// [frames], and whether it's the first beat of a measure.
+    fn next_beat(&self) -> Option<(usize, bool)> {
+        let start = self.current_sample as f64;
+        let beat = ((start - self.lead) / self.samples_per_beat)
+            .ceil()
+            .max(0.0) as i32;
+        if beat >= self.num_beats {
+            return None;
+        }
+        let offset = (self.lead + beat as f64 * self.samples_per_beat).ceil() - start;
+        match offset < self.buffer_size as f64 {
+            true => Some((offset as usize, beat % self.beats_per_measure == 0)),
+            false => None,
+        }
+    }
+
+    /// Get the clicks of the next buffer.
+    pub fn next(&mut self) -> &stream::RawStream {
+        if let Some((offset, accent)) = self.next_beat() {
+            let clip = match accent {
+                true => &self.accent_clip,
+                false => &self.clip,
+            };
+            self.sampler
+                .play_at(clip, false, offset * self.num_channels);
+        }
+
+        self.buffer.fill(stream::ZERO);
+        self.sampler.next(&mut self.buffer);
+        self.current_sample += self.buffer_size;
+        &self.buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_in() {
+        let project = config::ProjectConfig::new("dat/count_in/count_in.yaml").unwrap();
+        let mut tempo = tempo::Tempo::new(&project).unwrap();
+        tempo.skip(project.start_measure);
+        let mut count_in = CountIn::new(project.count_in.as_ref().unwrap(), &tempo, 1).unwrap();
+
+        // Where every click lands [samples].
+        let mut beats = vec![];
+        let mut num_buffers = 0;
+        while !count_in.is_done() {
+            if let Some((offset, accent)) = count_in.next_beat() {
+                beats.push((count_in.current_sample + offset, accent));
+            }
+            let clicks = count_in.next();
+            assert_eq!(clicks.len(), 256);
+            num_buffers += 1;
+        }
+
+        // Two measures of 3/4 at 100bpm at 44.1kHz => 6 * 26460 = 158760 samples, which is padded
+        // out to 621 buffers of 256 samples so that the last beat ends right on the start measure.
+        assert_eq!(num_buffers, 621);
+        assert_eq!(
+            beats,
+            vec![
+                (216, true),
+                (26676, false),
+                (53136, false),
+                (79596, true),
+                (106056, false),
+                (132516, false),
+            ]
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! Project visualization.
+//!
+//! Exports the signal flow of a project as a Graphviz DOT graph, where nodes are blocks and edges
+//! are streams, along with the segment timing of every block as a CSV arrangement.
+//!
+//! ```
+//! std::fs::write("project.dot", dot::graph(&project))?;
+//! std::fs::write("project.csv", dot::arrangement(&project))?;
+//! ```
+//!
+//! The graph is built from the channel names in each block's configuration, so blocks are never
+//! initialized and projects that fail to load can still be visualized.
+
+extern crate config;
+extern crate graph;
+extern crate segment;
+extern crate stream;
+
+/// Get all of the channel names configured under the singular or plural form of a key.
+fn channels<'a>(block_config: &'a config::BlockConfig, key: &str) -> Vec<&'a str> {
+    let mut channels = Vec::<&str>::new();
+    if let Some(channel) = block_config.get_value_opt(key).and_then(|v| v.as_str()) {
+        channels.push(channel);
+    }
+    let plural = format!("{}s", key);
+    if let Some(list) = block_config.get_value_opt(&plural).and_then(|v| v.as_vec()) {
+        channels.extend(list.iter().filter_map(|v| v.as_str()));
+    }
+    channels
+}
+
+/// Escape a string for use inside a quoted DOT identifier or label.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Quote a string for use as a DOT identifier or label.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", escape(s))
+}
+
+/// Quote a string for use as a CSV field, if it needs it.
+fn csv_field(s: &str) -> String {
+    match s.contains(|c| matches!(c, ',' | '"' | '\n' | '\r')) {
+        true => format!("\"{}\"", s.replace('"', "\"\"")),
+        false => s.to_owned(),
+    }
+}
+
+/// Describe a segment type.
+fn segment_type_str(segment_type: &segment::SegmentType) -> &str {
+    match segment_type {
+        segment::SegmentType::Input => "input",
+        segment::SegmentType::Output => "output",
+        segment::SegmentType::Invalid => "invalid",
+    }
+}
+
+/// Build the signal flow graph of a project in the Graphviz DOT format.
+pub fn graph(project: &config::ProjectConfig) -> String {
+    // Wire up a stream catalog the same way the blocks would.
+    let mut stream_catalog = stream::StreamCatalog::new();
+    for block_config in &project.blocks {
+        stream_catalog.set_block(&block_config.name);
+        for channel in channels(block_config, "input_channel") {
+            let _ = stream_catalog.bind_sink(channel);
+        }
+        for channel in channels(block_config, "output_channel") {
+            let _ = stream_catalog.create_source(channel);
+        }
+    }
+
+    let mut dot = String::from("digraph looper {\n    rankdir=LR;\n    node [shape=box];\n\n");
+
+    // One node per block, labelled with its type and segments.
+    for block_config in &project.blocks {
+        let mut label =
+            format!("{}\\n[{}]", escape(&block_config.name), escape(&block_config.block_type));
+        for segment in block_config.get_segments().unwrap_or_default() {
+            label += &format!(
+                "\\n{} {}-{}",
+                segment_type_str(&segment.segment_type),
+                segment.start,
+                segment.stop
+            );
+        }
+        dot += &format!("    {} [label=\"{}\"];\n", quote(&block_config.name), label);
+    }
+    dot += "\n";
+
+    // One edge per stream binding.
+    for link in graph::Graph::new(&stream_catalog).links() {
+        dot += &format!(
+            "    {} -> {} [label={}];\n",
+            quote(&link.producer),
+            quote(&link.consumer),
+            quote(&link.channel)
+        );
+    }
+
+    // Channels which nothing writes to.
+    for (channel, consumer) in stream_catalog.missing() {
+        let node = quote(&format!("missing:{}", channel));
+        dot +=
+            &format!("    {} [label={}, shape=plaintext, fontcolor=red];\n", node, quote(&channel));
+        dot += &format!("    {} -> {} [style=dashed, color=red];\n", node, quote(&consumer));
+    }
+
+    dot += "}\n";
+    dot
+}
+
+/// Build the arrangement of a project: every segment of every block, in CSV format.
+pub fn arrangement(project: &config::ProjectConfig) -> String {
+    let mut csv = String::from("device,type,segment,start,stop,name\n");
+    for block_config in &project.blocks {
+        for segment in block_config.get_segments().unwrap_or_default() {
+            csv += &format!(
+                "{},{},{},{},{},{}\n",
+                csv_field(&block_config.name),
+                csv_field(&block_config.block_type),
+                segment_type_str(&segment.segment_type),
+                segment.start,
+                segment.stop,
+                csv_field(segment.name.as_deref().unwrap_or(""))
+            );
+        }
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_graph() {
+        let project = config::ProjectConfig::new("dat/dot/project.yaml").unwrap();
+        let dot = graph(&project);
+
+        assert!(dot.starts_with("digraph looper {\n"));
+        assert!(dot.contains("    \"drums\" [label=\"drums\\n[VirtualInstrument]\"];\n"));
+        assert!(dot.contains("    \"loop\" [label=\"loop\\n[Loop]\\ninput 1-2\\noutput 3-5\"];\n"));
+        assert!(dot.contains("    \"drums\" -> \"loop\" [label=\"drums\"];\n"));
+        assert!(dot.contains("    \"drums\" -> \"mix\" [label=\"drums\"];\n"));
+        assert!(dot.contains("    \"loop\" -> \"mix\" [label=\"drumloop\"];\n"));
+        assert!(dot.contains("    \"missing:bass\" -> \"mix\" [style=dashed, color=red];\n"));
+        assert!(dot.ends_with("}\n"));
+
+        // Backslashes and quotes in names are escaped in labels as well as identifiers.
+        let project = config::ProjectConfig::new("dat/dot/quoted.yaml").unwrap();
+        let dot = graph(&project);
+        assert!(dot.contains(
+            "    \"guitar, \\\"clean\\\"\" \
+             [label=\"guitar, \\\"clean\\\"\\n[Loop]\\ninput 1-2\"];\n"
+        ));
+        assert!(dot.contains("    \"amp\\\\left\" [label=\"amp\\\\left\\n[Combiner]\"];\n"));
+    }
+
+    #[test]
+    fn test_arrangement() {
+        let project = config::ProjectConfig::new("dat/dot/project.yaml").unwrap();
+        assert_eq!(
+            arrangement(&project),
+            "device,type,segment,start,stop,name\n\
+             loop,Loop,input,1,2,\n\
+             loop,Loop,output,3,5,chorus\n"
+        );
+
+        // Names with commas or quotes in them are quoted.
+        let project = config::ProjectConfig::new("dat/dot/quoted.yaml").unwrap();
+        assert_eq!(
+            arrangement(&project),
+            "device,type,segment,start,stop,name\n\
+             \"guitar, \"\"clean\"\"\",Loop,input,1,2,\"verse, take 1\"\n"
+        );
+    }
+}
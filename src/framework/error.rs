@@ -0,0 +1,124 @@
+//! Errors raised while loading or running a project.
+//!
+//! Every error says what went wrong and, where it's known, which block, config key, stream or file
+//! was involved, so that tools and applications embedding the runner can match on them.
+//!
+//! ```
+//! match runner.run() {
+//!     Err(LooperError::Config { block, key, .. }) => println!("Fix {} in {}", key, block),
+//!     Err(e) => println!("{}", e),
+//!     Ok(_) => {}
+//! }
+//! ```
+
+use std::error;
+use std::fmt;
+
+/// An error raised by the framework or by a block.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LooperError {
+    /// A block parameter is missing or invalid.
+    Config {
+        block: String,
+        key: String,
+        cause: String,
+    },
+
+    /// A stream could not be created or bound.
+    Stream {
+        block: String,
+        channel: String,
+        cause: String,
+    },
+
+    /// A file could not be read or written.
+    File { path: String, cause: String },
+
+    /// A block failed for some other reason.
+    Block {
+        block: String,
+        cause: Box<LooperError>,
+    },
+
+    /// Anything else.
+    Abort { cause: String },
+}
+
+impl LooperError {
+    /// Attribute this error to a block, unless it already names one.
+    pub fn in_block(self, block: &str) -> LooperError {
+        match self {
+            LooperError::Config { .. } | LooperError::Stream { .. } | LooperError::Block { .. } => {
+                self
+            }
+            _ => LooperError::Block {
+                block: block.to_owned(),
+                cause: Box::new(self),
+            },
+        }
+    }
+}
+
+impl fmt::Display for LooperError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LooperError::Config { block, key, cause } => {
+                write!(f, "device=\"{}\" -> key=\"{}\": {}", block, key, cause)
+            }
+            LooperError::Stream {
+                block,
+                channel,
+                cause,
+            } => write!(f, "device=\"{}\" -> channel=\"{}\": {}", block, channel, cause),
+            LooperError::File { path, cause } => write!(f, "{}: {}", path, cause),
+            LooperError::Block { block, cause } => write!(f, "device=\"{}\": {}", block, cause),
+            LooperError::Abort { cause } => write!(f, "{}", cause),
+        }
+    }
+}
+
+impl error::Error for LooperError {}
+
+/// Errors that are only described by a message, e.g. from the log macros.
+impl From<String> for LooperError {
+    fn from(cause: String) -> Self {
+        LooperError::Abort { cause: cause }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_block() {
+        // Errors that already name a block are left alone.
+        let config = LooperError::Config {
+            block: "drums".to_owned(),
+            key: "volume".to_owned(),
+            cause: "Expected a number value".to_owned(),
+        };
+        assert_eq!(config.clone().in_block("other"), config);
+        assert_eq!(
+            config.to_string(),
+            "device=\"drums\" -> key=\"volume\": Expected a number value"
+        );
+
+        // Everything else is attributed to the block.
+        let file = LooperError::File {
+            path: "kick.wav".to_owned(),
+            cause: "No such file".to_owned(),
+        };
+        let block = file.in_block("drums");
+        assert_eq!(block.to_string(), "device=\"drums\": kick.wav: No such file");
+        match block {
+            LooperError::Block { block, cause } => {
+                assert_eq!(block, "drums");
+                assert!(matches!(*cause, LooperError::File { .. }));
+            }
+            _ => panic!("Expected a block error"),
+        }
+
+        assert_eq!(LooperError::from("oops".to_string()).to_string(), "oops");
+    }
+}
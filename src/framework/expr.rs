@@ -0,0 +1,253 @@
+//! Arithmetic expressions in config values.
+//!
+//! Numbers in the project config can be written as expressions with +, -, *, /, parentheses and
+//! the names of variables, e.g. "CHORUS_1_START + 4" or "(VERSE_2 - VERSE_1) / 2".
+//!
+//! ```
+//! let expr = expr::parse("CHORUS_1_START + 4")?;
+//! let value = expr.evaluate(&|name| variables.get(name).copied())?;
+//! ```
+
+/// An arithmetic operator.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Op {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+/// A parsed expression.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    /// A literal number.
+    Number(f64),
+
+    /// A reference to a variable.
+    Variable(String),
+
+    /// The negation of an expression.
+    Negate(Box<Expr>),
+
+    /// An operator applied to two expressions.
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+/// A token of an expression.
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Op(Op),
+    Open,
+    Close,
+}
+
+/// Split an expression into tokens.
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        i += 1;
+        let token = match c {
+            ' ' | '\t' => continue,
+            '+' => Token::Op(Op::Add),
+            '-' => Token::Op(Op::Subtract),
+            '*' => Token::Op(Op::Multiply),
+            '/' => Token::Op(Op::Divide),
+            '(' => Token::Open,
+            ')' => Token::Close,
+            c if c.is_ascii_digit() || c == '.' => {
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let number: String = chars[start..i].iter().collect();
+                match number.parse::<f64>() {
+                    Ok(v) => Token::Number(v),
+                    Err(_) => return Err(format!("Invalid number \"{}\" in \"{}\"", number, s)),
+                }
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                Token::Name(chars[start..i].iter().collect())
+            }
+            c => return Err(format!("Unexpected \"{}\" in \"{}\"", c, s)),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// A recursive descent parser over the tokens of an expression.
+struct Parser<'a> {
+    /// The expression being parsed, for error messages.
+    text: &'a str,
+
+    /// The tokens of the expression.
+    tokens: Vec<Token>,
+
+    /// The index of the next token.
+    next: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// Look at the next token without consuming it.
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    /// Consume the next token.
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        token
+    }
+
+    /// expr := term (("+" | "-") term)*
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.term()?;
+        while let Some(Token::Op(op @ (Op::Add | Op::Subtract))) = self.peek() {
+            let op = *op;
+            self.advance();
+            let rhs = self.term()?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    /// term := factor (("*" | "/") factor)*
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.factor()?;
+        while let Some(Token::Op(op @ (Op::Multiply | Op::Divide))) = self.peek() {
+            let op = *op;
+            self.advance();
+            let rhs = self.factor()?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    /// factor := ("-" | "+") factor | number | name | "(" expr ")"
+    fn factor(&mut self) -> Result<Expr, String> {
+        match self.advance() {
+            Some(Token::Op(Op::Subtract)) => Ok(Expr::Negate(Box::new(self.factor()?))),
+            Some(Token::Op(Op::Add)) => self.factor(),
+            Some(Token::Number(v)) => Ok(Expr::Number(v)),
+            Some(Token::Name(name)) => Ok(Expr::Variable(name)),
+            Some(Token::Open) => {
+                let inner = self.expr()?;
+                match self.advance() {
+                    Some(Token::Close) => Ok(inner),
+                    _ => Err(format!("Missing \")\" in \"{}\"", self.text)),
+                }
+            }
+            _ => Err(format!("Incomplete expression \"{}\"", self.text)),
+        }
+    }
+}
+
+/// Parse an expression.
+pub fn parse(s: &str) -> Result<Expr, String> {
+    let mut parser = Parser {
+        text: s,
+        tokens: tokenize(s)?,
+        next: 0,
+    };
+    let expr = parser.expr()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(_) => Err(format!("Unexpected text after the end of \"{}\"", s)),
+    }
+}
+
+impl Expr {
+    /// Evaluate the expression.
+    ///
+    /// # Arguments
+    ///
+    /// * lookup - Gets the value of a variable, or None if there is no such variable.
+    pub fn evaluate(&self, lookup: &dyn Fn(&str) -> Option<f64>) -> Result<f64, String> {
+        match self {
+            Expr::Number(v) => Ok(*v),
+            Expr::Variable(name) => match lookup(name) {
+                Some(v) => Ok(v),
+                None => Err(format!("Could not resolve variable: {}", name)),
+            },
+            Expr::Negate(inner) => Ok(-inner.evaluate(lookup)?),
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = lhs.evaluate(lookup)?;
+                let rhs = rhs.evaluate(lookup)?;
+                match op {
+                    Op::Add => Ok(lhs + rhs),
+                    Op::Subtract => Ok(lhs - rhs),
+                    Op::Multiply => Ok(lhs * rhs),
+                    Op::Divide if rhs == 0.0 => Err("Division by zero".to_owned()),
+                    Op::Divide => Ok(lhs / rhs),
+                }
+            }
+        }
+    }
+
+    /// The names of every variable the expression refers to.
+    pub fn variables(&self) -> Vec<&str> {
+        match self {
+            Expr::Number(_) => vec![],
+            Expr::Variable(name) => vec![name],
+            Expr::Negate(inner) => inner.variables(),
+            Expr::Binary(_, lhs, rhs) => {
+                let mut names = lhs.variables();
+                names.extend(rhs.variables());
+                names
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<f64> {
+        match name {
+            "CHORUS_1_START" => Some(15.0),
+            "VERSE" => Some(4.0),
+            _ => None,
+        }
+    }
+
+    fn eval(s: &str) -> Result<f64, String> {
+        parse(s)?.evaluate(&lookup)
+    }
+
+    #[test]
+    fn test_evaluate() {
+        assert_eq!(eval("CHORUS_1_START + 4"), Ok(19.0));
+        assert_eq!(eval("1 + 2 * 3"), Ok(7.0));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(9.0));
+        assert_eq!(eval("10 - 4 - 3"), Ok(3.0));
+        assert_eq!(eval("CHORUS_1_START - -VERSE / 8"), Ok(15.5));
+        assert_eq!(eval("2.5"), Ok(2.5));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(eval("1 +").is_err());
+        assert!(eval("(1 + 2").is_err());
+        assert!(eval("1 2").is_err());
+        assert!(eval("1 % 2").is_err());
+        assert!(eval("1.2.3").is_err());
+        assert!(eval("VERSE / (VERSE - 4)").is_err());
+        assert_eq!(eval("BRIDGE + 1"), Err("Could not resolve variable: BRIDGE".to_owned()));
+    }
+
+    #[test]
+    fn test_variables() {
+        let expr = parse("(A + B) * -A").unwrap();
+        assert_eq!(expr.variables(), vec!["A", "B", "A"]);
+    }
+}
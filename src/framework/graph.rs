@@ -0,0 +1,175 @@
+//! Block dependency graph.
+//!
+//! Blocks are connected to one another through streams. The block which creates a stream must run
+//! before every block which binds to it, otherwise the reader sees the previous cycle's buffer.
+//! The graph is built from the links recorded by the StreamCatalog while blocks are initialized.
+//!
+//! ```
+//! let graph = graph::Graph::new(&stream_catalog);
+//! let order = graph.sort(&transformer_names)?;
+//! ```
+
+extern crate stream;
+
+/// The dependency graph between blocks.
+pub struct Graph {
+    /// All of the links between blocks.
+    links: Vec<stream::Link>,
+}
+
+impl Graph {
+    /// Build a graph from all of the streams in the catalog.
+    pub fn new(stream_catalog: &stream::StreamCatalog) -> Self {
+        Graph {
+            links: stream_catalog.links(),
+        }
+    }
+
+    /// Get all of the links between blocks.
+    pub fn links(&self) -> &Vec<stream::Link> {
+        &self.links
+    }
+
+    /// Sort a list of blocks such that every block comes after all of the blocks it reads from.
+    /// Links to blocks outside of the list are ignored. Blocks without a dependency between them
+    /// keep their original order.
+    ///
+    /// Returns the indices into `blocks` in the order they should be run, or an error naming the
+    /// blocks and channels involved if the blocks depend on each other in a cycle.
+    ///
+    /// # Arguments
+    ///
+    /// * blocks - The names of the blocks to sort.
+    pub fn sort(&self, blocks: &Vec<String>) -> Result<Vec<usize>, String> {
+        // For each block, the list of (producer index, channel) it depends on.
+        let mut deps: Vec<Vec<(usize, &str)>> = vec![Vec::new(); blocks.len()];
+        for link in &self.links {
+            for (consumer, name) in blocks.iter().enumerate() {
+                if *name != link.consumer {
+                    continue;
+                }
+                for (producer, name) in blocks.iter().enumerate() {
+                    if *name == link.producer {
+                        deps[consumer].push((producer, &link.channel));
+                    }
+                }
+            }
+        }
+
+        // Repeatedly pick the first block whose dependencies have all been scheduled.
+        let mut order = Vec::<usize>::with_capacity(blocks.len());
+        let mut scheduled = vec![false; blocks.len()];
+        while order.len() < blocks.len() {
+            let next = (0..blocks.len())
+                .find(|&i| !scheduled[i] && deps[i].iter().all(|(p, _)| scheduled[*p]));
+            match next {
+                Some(i) => {
+                    scheduled[i] = true;
+                    order.push(i);
+                }
+                None => {
+                    return Err(Self::describe_cycle(blocks, &deps, &scheduled));
+                }
+            }
+        }
+
+        Ok(order)
+    }
+
+    /// Find a cycle among the unscheduled blocks and describe it.
+    ///
+    /// Every unscheduled block depends on at least one other unscheduled block, so walking
+    /// backwards through dependencies must eventually revisit a block.
+    fn describe_cycle(
+        blocks: &Vec<String>,
+        deps: &Vec<Vec<(usize, &str)>>,
+        scheduled: &Vec<bool>,
+    ) -> String {
+        let start = scheduled.iter().position(|s| !s).unwrap();
+
+        // Walk from consumer to producer, recording (block, channel it reads) pairs.
+        let mut path = Vec::<(usize, &str)>::new();
+        let mut cur = start;
+        loop {
+            if let Some(pos) = path.iter().position(|(b, _)| *b == cur) {
+                path.drain(..pos);
+                break;
+            }
+            let (producer, channel) = *deps[cur].iter().find(|(p, _)| !scheduled[*p]).unwrap();
+            path.push((cur, channel));
+            cur = producer;
+        }
+
+        // Print the cycle in the direction the audio flows, which is the reverse of the walk.
+        let mut msg = format!("Stream cycle detected: \"{}\"", blocks[path[0].0]);
+        for (block, channel) in path.iter().rev() {
+            msg += &format!(" -> [{}] -> \"{}\"", channel, blocks[*block]);
+        }
+        msg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a graph with a series of (block, creates, binds) entries.
+    fn build(blocks: &[(&str, &[&str], &[&str])]) -> (Graph, Vec<String>) {
+        let mut sc = stream::StreamCatalog::new();
+        for (name, creates, binds) in blocks {
+            sc.set_block(name);
+            for channel in *binds {
+                sc.bind_sink(channel).unwrap();
+            }
+            for channel in *creates {
+                sc.create_source(channel).unwrap();
+            }
+        }
+        let names = blocks.iter().map(|(name, _, _)| name.to_string()).collect();
+        (Graph::new(&sc), names)
+    }
+
+    #[test]
+    fn test_sort() {
+        // "loop" is listed before the combiner it reads from.
+        let (graph, names) = build(&[
+            ("loop", &["out"], &["mix"]),
+            ("combiner", &["mix"], &["a", "b"]),
+            ("filter", &["b"], &["src"]),
+            ("unrelated", &["other"], &[]),
+        ]);
+        assert_eq!(graph.sort(&names).unwrap(), vec![2, 1, 0, 3]);
+    }
+
+    #[test]
+    fn test_sort_stable() {
+        // Independent blocks keep their original order.
+        let (graph, names) = build(&[("a", &["x"], &[]), ("b", &["y"], &[]), ("c", &["z"], &[])]);
+        assert_eq!(graph.sort(&names).unwrap(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_cycle() {
+        // second -> third -> fourth -> second.
+        let (graph, names) = build(&[
+            ("first", &["one"], &["src"]),
+            ("second", &["two"], &["one", "four"]),
+            ("third", &["three"], &["two"]),
+            ("fourth", &["four"], &["three"]),
+        ]);
+
+        let err = graph.sort(&names).unwrap_err();
+        assert_eq!(
+            err,
+            "Stream cycle detected: \"second\" -> [two] -> \"third\" -> [three] -> \"fourth\" \
+             -> [four] -> \"second\""
+        );
+    }
+
+    #[test]
+    fn test_self_cycle() {
+        let (graph, names) = build(&[("loop", &["out"], &["out"])]);
+        let err = graph.sort(&names).unwrap_err();
+        assert_eq!(err, "Stream cycle detected: \"loop\" -> [out] -> \"loop\"");
+    }
+}
@@ -1,6 +1,13 @@
 //! Lightweight keypress detection library.
 //!
 //! Blocks can access keypresses from the Keyboard object.
+//!
+//! Some keys are reserved for transport controls which are handled by the runner and are never
+//! passed on to blocks:
+//!     space: Pause/resume playback
+//!     [: Jump back
+//!     ]: Jump forward
+//!     0: Restart from the start measure
 
 extern crate filedescriptor;
 extern crate log;
@@ -16,10 +23,45 @@ use termios::*;
 /// The max number of chars that can be read in a single cycle.
 const BUFSIZE: usize = 64;
 
+/// Reserved keys for controlling playback.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transport {
+    /// Pause playback, or resume it if it's paused.
+    PauseResume,
+
+    /// Jump back some measures.
+    Back,
+
+    /// Jump forward some measures.
+    Forward,
+
+    /// Jump back to the start measure.
+    Restart,
+}
+
+impl Transport {
+    /// Get the transport control for a key, or None if the key isn't reserved.
+    pub fn from_key(key: char) -> Option<Self> {
+        match key {
+            ' ' => Some(Transport::PauseResume),
+            '[' => Some(Transport::Back),
+            ']' => Some(Transport::Forward),
+            '0' => Some(Transport::Restart),
+            _ => None,
+        }
+    }
+}
+
 /// Struct which handles keyboard presses from the terminal.
 pub struct Keyboard {
-    /// The keys that were pressed since the last refresh() call.
+    /// The keys that were pressed since the last refresh() call, except for transport keys.
     pub keys: Vec<char>,
+
+    /// The transport keys that were pressed since the last refresh() call.
+    pub transport: Vec<Transport>,
+
+    /// The terminal settings before the keyboard was attached to stdin, if it was.
+    original: Option<Termios>,
 }
 
 impl Keyboard {
@@ -29,6 +71,7 @@ impl Keyboard {
         let fd = stdin.as_raw_fd();
 
         let mut termios = log::unwrap_abort_str!(termios::Termios::from_fd(fd));
+        let original = termios.clone();
         // Turn off canonical mode to read in char by char.
         termios.c_lflag &= !ICANON;
         // Echo characters to the terminal.
@@ -38,14 +81,29 @@ impl Keyboard {
 
         Ok(Keyboard {
             keys: Vec::<char>::with_capacity(5),
+            transport: Vec::<Transport>::new(),
+            original: Some(original),
         })
     }
 
+    /// Construct a keyboard which is not attached to any terminal and never reports keypresses.
+    pub fn detached() -> Self {
+        Keyboard {
+            keys: Vec::<char>::new(),
+            transport: Vec::<Transport>::new(),
+            original: None,
+        }
+    }
+
     /// Reset the keyboard.
     ///
     /// This clears all existing keypresses and then buffers any new ones.
     pub fn reset(&mut self) {
         self.keys.clear();
+        self.transport.clear();
+        if self.original.is_none() {
+            return;
+        }
 
         // Poll stdin for the number of bytes ready to be read.
         let pollfd = filedescriptor::pollfd {
@@ -75,8 +133,41 @@ impl Keyboard {
             };
 
             for i in 0..count {
-                self.keys.push(buf[i] as char);
+                let key = buf[i] as char;
+                match Transport::from_key(key) {
+                    Some(transport) => self.transport.push(transport),
+                    None => self.keys.push(key),
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Keyboard {
+    /// Restore the terminal settings.
+    fn drop(&mut self) {
+        if let Some(original) = &self.original {
+            if let Err(e) = tcsetattr(io::stdin().as_raw_fd(), TCSANOW, original) {
+                println!("Failed to restore terminal settings: {}", e.to_string());
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transport_keys() {
+        assert_eq!(Transport::from_key(' '), Some(Transport::PauseResume));
+        assert_eq!(Transport::from_key('['), Some(Transport::Back));
+        assert_eq!(Transport::from_key(']'), Some(Transport::Forward));
+        assert_eq!(Transport::from_key('0'), Some(Transport::Restart));
+
+        // Everything else is left for instruments.
+        for key in ['a', '1', '9', 'q', '{'] {
+            assert_eq!(Transport::from_key(key), None);
+        }
+    }
+}
@@ -4,30 +4,31 @@
 macro_rules! abort_msg {
     ( $msg:expr ) => {{
         println!("{}:{} abort(): {}", file!(), line!(), $msg);
-        return Err(());
+        return Err(std::convert::From::from(format!("{}", $msg)));
     }};
 }
 
-/// Abort with Err(()) if the expression evaluates to true.
+/// Abort with an error if the expression evaluates to true.
 /// Logs a trace with println!
 #[macro_export]
 macro_rules! abort_if {
     ( $e:expr ) => {
         if $e {
-            println!("{}:{} abort({})", file!(), line!(), stringify!($e));
-            return Err(());
+            let msg = format!("{}:{} abort({})", file!(), line!(), stringify!($e));
+            println!("{}", msg);
+            return Err(std::convert::From::from(msg));
         }
     };
 }
 
-/// Abort with Err(()) if the expression evaluates to true.
+/// Abort with an error if the expression evaluates to true.
 /// Logs a trace with println! plus additional provided context.
 #[macro_export]
 macro_rules! abort_if_msg {
     ( $e:expr, $msg:expr ) => {
         if $e {
             println!("{}:{} abort({}): {}", file!(), line!(), stringify!($e), $msg);
-            return Err(());
+            return Err(std::convert::From::from(format!("{}", $msg)));
         }
     };
 }
@@ -44,7 +45,7 @@ macro_rules! abort_if_msg_str {
     };
 }
 
-/// Unwrap a Result or return Err(()).
+/// Unwrap a Result or return an error.
 /// Log a trace with println!.
 #[macro_export]
 macro_rules! unwrap_abort {
@@ -52,14 +53,15 @@ macro_rules! unwrap_abort {
         match $e {
             Ok(x) => x,
             Err(_) => {
-                println!("{}:{} abort({})", file!(), line!(), stringify!($e));
-                return Err(());
+                let msg = format!("{}:{} abort({})", file!(), line!(), stringify!($e));
+                println!("{}", msg);
+                return Err(std::convert::From::from(msg));
             }
         }
     };
 }
 
-/// Unwrap a Result or return Err(()).
+/// Unwrap a Result or return an error.
 /// Log a trace with println! with a message as context.
 #[macro_export]
 macro_rules! unwrap_abort_msg {
@@ -68,7 +70,7 @@ macro_rules! unwrap_abort_msg {
             Ok(x) => x,
             Err(_) => {
                 println!("{}:{} abort({}): {}", file!(), line!(), stringify!($e), $msg);
-                return Err(());
+                return Err(std::convert::From::from(format!("{}", $msg)));
             }
         }
     };
@@ -105,21 +107,22 @@ macro_rules! unwrap_abort_msg_str {
     };
 }
 
-/// Unwrap an Option or return Err(()).
+/// Unwrap an Option or return an error.
 #[macro_export]
 macro_rules! opt_abort {
     ( $e:expr ) => {
         match $e {
             Some(x) => x,
             None => {
-                println!("{}:{} abort({})", file!(), line!(), stringify!($e));
-                return Err(());
+                let msg = format!("{}:{} abort({})", file!(), line!(), stringify!($e));
+                println!("{}", msg);
+                return Err(std::convert::From::from(msg));
             }
         }
     };
 }
 
-/// Unwrap an Option or return Err(()).
+/// Unwrap an Option or return an error.
 /// Log a trace with println! with a message as context.
 #[macro_export]
 macro_rules! opt_abort_msg {
@@ -128,7 +131,7 @@ macro_rules! opt_abort_msg {
             Some(x) => x,
             None => {
                 println!("{}:{} abort({}): {}", file!(), line!(), stringify!($e), $msg);
-                return Err(());
+                return Err(std::convert::From::from(format!("{}", $msg)));
             }
         }
     };
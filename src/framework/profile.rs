@@ -0,0 +1,473 @@
+//! Per-block profiling.
+//!
+//! The runner times every block's read()/transform()/write() call. A cycle whose compute time goes
+//! over the realtime budget (the wall clock duration of one buffer) is counted as an overrun,
+//! because when playing through audio hardware that is when the audio drops out. Time spent
+//! blocked on audio I/O doesn't count as compute. There's no budget when rendering offline, so no
+//! overruns are counted.
+//!
+//! The durations aren't kept, so the profiler uses the same small amount of memory however long it
+//! runs. The 99th percentile comes from a histogram of durations, so it's only accurate to within a
+//! few percent.
+//!
+//! ```
+//! let mut profiler = Profiler::new(Some(budget));
+//! profiler.record("drums", Stage::Read, false, duration);
+//! profiler.end_cycle();
+//! profiler.print();
+//! profiler.write_json("profile.json")?;
+//! ```
+
+extern crate error;
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Which block call was timed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+    Read,
+    Transform,
+    Write,
+}
+
+impl Stage {
+    /// The name of the block call.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Read => "read",
+            Stage::Transform => "transform",
+            Stage::Write => "write",
+        }
+    }
+}
+
+/// The number of histogram buckets for every doubling of a duration. Each bucket is about 4% wide.
+const BUCKETS_PER_OCTAVE: usize = 16;
+
+/// The number of histogram buckets, which covers durations up to 2^40ns (about 18 minutes).
+const NUM_BUCKETS: usize = 40 * BUCKETS_PER_OCTAVE;
+
+/// Summary statistics of a block's call durations.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stats {
+    /// The number of calls.
+    pub count: usize,
+
+    /// The mean duration.
+    pub mean: Duration,
+
+    /// The 99th percentile duration.
+    pub p99: Duration,
+
+    /// The longest duration.
+    pub max: Duration,
+}
+
+/// Accumulates call durations in a fixed amount of memory.
+#[derive(Clone, Debug)]
+pub struct Timings {
+    /// The number of calls.
+    count: usize,
+
+    /// The total duration of every call.
+    total: Duration,
+
+    /// The longest duration.
+    max: Duration,
+
+    /// The number of calls in each histogram bucket.
+    histogram: Vec<usize>,
+}
+
+/// Everything recorded about a single block.
+struct BlockProfile {
+    /// The block name.
+    name: String,
+
+    /// The block call which was timed.
+    stage: Stage,
+
+    /// Whether the block blocks on audio I/O.
+    blocking_io: bool,
+
+    /// The call durations.
+    timings: Timings,
+
+    /// The number of times the block's audio device overflowed.
+    overflows: usize,
+
+    /// The number of times the block's audio device underflowed.
+    underflows: usize,
+}
+
+/// Collects timing for every block.
+pub struct Profiler {
+    /// The realtime budget of a cycle, or None if there isn't one, e.g. when rendering offline.
+    budget: Option<Duration>,
+
+    /// Every block profiled so far, in the order they were first recorded.
+    blocks: Vec<BlockProfile>,
+
+    /// The index of each block in blocks, keyed by block name.
+    index: HashMap<String, usize>,
+
+    /// The compute time of the current cycle so far.
+    cycle_compute: Duration,
+
+    /// The number of cycles completed.
+    num_cycles: usize,
+
+    /// The number of cycles which went over budget.
+    num_overruns: usize,
+}
+
+/// The histogram bucket of a duration.
+fn bucket(duration: Duration) -> usize {
+    let nanos = duration.as_nanos().max(1) as f64;
+    ((nanos.log2() * BUCKETS_PER_OCTAVE as f64) as usize).min(NUM_BUCKETS - 1)
+}
+
+/// The longest duration in a histogram bucket.
+fn bucket_limit(bucket: usize) -> Duration {
+    let nanos = 2f64.powf((bucket + 1) as f64 / BUCKETS_PER_OCTAVE as f64);
+    Duration::from_nanos(nanos as u64)
+}
+
+impl Timings {
+    /// Create an empty set of timings. This is the only time memory is allocated.
+    pub fn new() -> Self {
+        Timings {
+            count: 0,
+            total: Duration::ZERO,
+            max: Duration::ZERO,
+            histogram: vec![0; NUM_BUCKETS],
+        }
+    }
+
+    /// Add the duration of a call.
+    pub fn add(&mut self, duration: Duration) {
+        self.count += 1;
+        self.total += duration;
+        self.max = self.max.max(duration);
+        self.histogram[bucket(duration)] += 1;
+    }
+
+    /// Get summary statistics of every call so far.
+    pub fn stats(&self) -> Stats {
+        if self.count == 0 {
+            return Stats {
+                count: 0,
+                mean: Duration::ZERO,
+                p99: Duration::ZERO,
+                max: Duration::ZERO,
+            };
+        }
+
+        // Find the bucket which the 99th percentile call landed in.
+        let p99_rank = ((self.count as f64 * 0.99).ceil() as usize).max(1);
+        let mut seen = 0;
+        let mut p99 = self.max;
+        for (i, count) in self.histogram.iter().enumerate() {
+            seen += count;
+            if seen >= p99_rank {
+                p99 = bucket_limit(i).min(self.max);
+                break;
+            }
+        }
+        Stats {
+            count: self.count,
+            mean: self.total / self.count as u32,
+            p99: p99,
+            max: self.max,
+        }
+    }
+}
+
+/// Format a duration in microseconds.
+fn micros(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1e6
+}
+
+/// Quote a string for JSON.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl Profiler {
+    /// Create a profiler.
+    ///
+    /// # Arguments
+    ///
+    /// * budget - The realtime budget of a cycle, i.e. the duration of one buffer, or None to not
+    ///            count overruns.
+    pub fn new(budget: Option<Duration>) -> Self {
+        Profiler {
+            budget: budget,
+            blocks: Vec::new(),
+            index: HashMap::new(),
+            cycle_compute: Duration::ZERO,
+            num_cycles: 0,
+            num_overruns: 0,
+        }
+    }
+
+    /// Get a block's profile, adding it if this is the first time it's been seen.
+    fn block(&mut self, name: &str, stage: Stage) -> &mut BlockProfile {
+        let i = match self.index.get(name) {
+            Some(i) => *i,
+            None => {
+                self.index.insert(name.to_owned(), self.blocks.len());
+                self.blocks.push(BlockProfile {
+                    name: name.to_owned(),
+                    stage: stage,
+                    blocking_io: false,
+                    timings: Timings::new(),
+                    overflows: 0,
+                    underflows: 0,
+                });
+                self.blocks.len() - 1
+            }
+        };
+        &mut self.blocks[i]
+    }
+
+    /// Record the duration of a block call.
+    ///
+    /// # Arguments
+    ///
+    /// * name - The block name.
+    /// * stage - The block call which was timed.
+    /// * blocking_io - Whether the call blocks on audio I/O, so it doesn't count as compute.
+    /// * duration - How long the call took.
+    pub fn record(&mut self, name: &str, stage: Stage, blocking_io: bool, duration: Duration) {
+        let block = self.block(name, stage);
+        block.blocking_io = blocking_io;
+        block.timings.add(duration);
+        if !blocking_io {
+            self.cycle_compute += duration;
+        }
+    }
+
+    /// Finish the current cycle, counting it as an overrun if it went over budget.
+    pub fn end_cycle(&mut self) {
+        self.num_cycles += 1;
+        if self
+            .budget
+            .map_or(false, |budget| self.cycle_compute > budget)
+        {
+            self.num_overruns += 1;
+        }
+        self.cycle_compute = Duration::ZERO;
+    }
+
+    /// Record how many times a block's audio device overflowed and underflowed.
+    pub fn set_xruns(&mut self, name: &str, stage: Stage, overflows: usize, underflows: usize) {
+        let block = self.block(name, stage);
+        block.overflows = overflows;
+        block.underflows = underflows;
+    }
+
+    /// The number of cycles completed.
+    pub fn num_cycles(&self) -> usize {
+        self.num_cycles
+    }
+
+    /// The number of cycles which went over budget.
+    pub fn num_overruns(&self) -> usize {
+        self.num_overruns
+    }
+
+    /// Get the statistics of a block's calls, if it has been recorded.
+    pub fn block_stats(&self, name: &str) -> Option<Stats> {
+        self.index
+            .get(name)
+            .map(|i| self.blocks[*i].timings.stats())
+    }
+
+    /// Print a report of every block.
+    pub fn print(&self) {
+        println!(
+            "{:<24} {:<10} {:>10} {:>10} {:>10}",
+            "Block", "Stage", "Mean (us)", "p99 (us)", "Max (us)"
+        );
+        for block in &self.blocks {
+            let stats = block.timings.stats();
+            println!(
+                "{:<24} {:<10} {:>10.1} {:>10.1} {:>10.1}{}",
+                block.name,
+                block.stage.name(),
+                micros(stats.mean),
+                micros(stats.p99),
+                micros(stats.max),
+                if block.blocking_io {
+                    " (blocking I/O)"
+                } else {
+                    ""
+                }
+            );
+        }
+        match self.budget {
+            Some(budget) => println!(
+                "Overruns: {} of {} cycles went over the {:.2}ms budget",
+                self.num_overruns,
+                self.num_cycles,
+                budget.as_secs_f64() * 1e3
+            ),
+            None => println!("Overruns: not counted for {} offline cycles", self.num_cycles),
+        }
+        for block in &self.blocks {
+            if block.overflows > 0 || block.underflows > 0 {
+                println!(
+                    "{}: {} overflows, {} underflows",
+                    block.name, block.overflows, block.underflows
+                );
+            }
+        }
+    }
+
+    /// Get the report as JSON.
+    pub fn to_json(&self) -> String {
+        let blocks: Vec<String> = self
+            .blocks
+            .iter()
+            .map(|block| {
+                let stats = block.timings.stats();
+                format!(
+                    "    {{\"name\": {}, \"stage\": \"{}\", \"blocking_io\": {}, \"calls\": {}, \
+                     \"mean_us\": {:.3}, \"p99_us\": {:.3}, \"max_us\": {:.3}, \
+                     \"overflows\": {}, \"underflows\": {}}}",
+                    json_string(&block.name),
+                    block.stage.name(),
+                    block.blocking_io,
+                    stats.count,
+                    micros(stats.mean),
+                    micros(stats.p99),
+                    micros(stats.max),
+                    block.overflows,
+                    block.underflows
+                )
+            })
+            .collect();
+        format!(
+            "{{\n  \"budget_us\": {},\n  \"cycles\": {},\n  \"overruns\": {},\n  \"blocks\": [\n{}\n  ]\n}}\n",
+            self.budget.map_or("null".to_owned(), |b| format!("{:.3}", micros(b))),
+            self.num_cycles,
+            self.budget.map_or("null".to_owned(), |_| self.num_overruns.to_string()),
+            blocks.join(",\n")
+        )
+    }
+
+    /// Write the report to a JSON file.
+    pub fn write_json(&self, filename: &str) -> Result<(), error::LooperError> {
+        match std::fs::write(filename, self.to_json()) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(error::LooperError::File {
+                path: filename.to_owned(),
+                cause: e.to_string(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn us(n: u64) -> Duration {
+        Duration::from_micros(n)
+    }
+
+    fn timings(durations: &[Duration]) -> Timings {
+        let mut timings = Timings::new();
+        for duration in durations {
+            timings.add(*duration);
+        }
+        timings
+    }
+
+    #[test]
+    fn test_stats() {
+        let durations: Vec<Duration> = (1..=200).map(us).collect();
+        let stats = timings(&durations).stats();
+        assert_eq!(stats.count, 200);
+        assert_eq!(stats.max, us(200));
+        assert_eq!(stats.mean, Duration::from_nanos(100500));
+
+        // The 99th percentile is 198us, give or take a histogram bucket.
+        assert!(stats.p99 >= us(198) && stats.p99 <= us(200), "{:?}", stats.p99);
+
+        assert_eq!(timings(&[us(7)]).stats().p99, us(7));
+        assert_eq!(timings(&[]).stats().count, 0);
+    }
+
+    #[test]
+    fn test_fixed_memory() {
+        // A long session doesn't keep every duration.
+        let mut timings = Timings::new();
+        for i in 0..1_000_000 {
+            timings.add(us(i % 1000));
+        }
+        assert_eq!(timings.histogram.len(), NUM_BUCKETS);
+
+        let stats = timings.stats();
+        assert_eq!(stats.count, 1_000_000);
+        assert_eq!(stats.max, us(999));
+        assert!(stats.p99 >= us(989) && stats.p99 <= us(999), "{:?}", stats.p99);
+    }
+
+    #[test]
+    fn test_overruns() {
+        let mut profiler = Profiler::new(Some(us(100)));
+
+        // Blocking I/O doesn't count towards the budget.
+        profiler.record("mic", Stage::Read, true, us(500));
+        profiler.record("delay", Stage::Transform, false, us(60));
+        profiler.end_cycle();
+
+        // But everything else adds up.
+        profiler.record("mic", Stage::Read, true, us(10));
+        profiler.record("delay", Stage::Transform, false, us(60));
+        profiler.record("speaker", Stage::Write, false, us(60));
+        profiler.end_cycle();
+
+        assert_eq!(profiler.num_cycles(), 2);
+        assert_eq!(profiler.num_overruns(), 1);
+        assert_eq!(profiler.block_stats("delay").unwrap().count, 2);
+        assert_eq!(profiler.block_stats("mic").unwrap().max, us(500));
+        assert!(profiler.block_stats("missing").is_none());
+
+        // Rendering offline has no budget to go over.
+        let mut profiler = Profiler::new(None);
+        profiler.record("delay", Stage::Transform, false, us(500));
+        profiler.end_cycle();
+        assert_eq!(profiler.num_overruns(), 0);
+        assert!(profiler.to_json().contains("\"overruns\": null"));
+    }
+
+    #[test]
+    fn test_json() {
+        let mut profiler = Profiler::new(Some(us(100)));
+        profiler.record("the \"drums\"", Stage::Read, false, us(20));
+        profiler.end_cycle();
+        profiler.set_xruns("speaker", Stage::Write, 0, 3);
+
+        let json = profiler.to_json();
+        assert!(json.contains("\"budget_us\": 100.000"));
+        assert!(json.contains("\"cycles\": 1,"));
+        assert!(json.contains("\"name\": \"the \\\"drums\\\"\", \"stage\": \"read\""));
+        assert!(json.contains("\"mean_us\": 20.000"));
+        assert!(json.contains("\"calls\": 0"));
+        assert!(json.contains("\"underflows\": 3}"));
+    }
+}
@@ -0,0 +1,200 @@
+//! Block registry.
+//!
+//! The registry maps the "type" of a block in the project config to the function which constructs
+//! it. Every block crate exposes a `register()` function which adds its blocks to a registry, and
+//! applications embedding the runner can register their own blocks the same way:
+//!
+//! ```
+//! let mut registry = registry::Registry::new();
+//! registry.register_transformer("MyBlock", MyBlock::new);
+//! ```
+//!
+//! Sink constructors do not get mutable references to StreamCatalog because sinks should not be
+//! allowed to create streams.
+
+extern crate block;
+extern crate config;
+extern crate error;
+extern crate stream;
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// Constructs a Source block.
+pub type SourceConstructor = Box<
+    dyn Fn(
+        &config::BlockConfig,
+        &mut stream::StreamCatalog,
+    ) -> Result<Box<dyn block::Source>, error::LooperError>,
+>;
+
+/// Constructs a Transformer block.
+pub type TransformerConstructor = Box<
+    dyn Fn(
+        &config::BlockConfig,
+        &mut stream::StreamCatalog,
+    ) -> Result<Box<dyn block::Transformer>, error::LooperError>,
+>;
+
+/// Constructs a Sink block.
+pub type SinkConstructor = Box<
+    dyn Fn(
+        &config::BlockConfig,
+        &stream::StreamCatalog,
+    ) -> Result<Box<dyn block::Sink>, error::LooperError>,
+>;
+
+/// The variety of a block.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlockKind {
+    /// A block that has only outputs and no inputs.
+    Source,
+
+    /// A block that has both inputs and outputs.
+    Transformer,
+
+    /// A block that has only inputs and no outputs.
+    Sink,
+}
+
+/// A constructor for any kind of block.
+pub enum Constructor {
+    Source(SourceConstructor),
+    Transformer(TransformerConstructor),
+    Sink(SinkConstructor),
+}
+
+/// A mapping of block type names to their constructors.
+pub struct Registry {
+    constructors: HashMap<String, Constructor>,
+}
+
+impl fmt::Display for BlockKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlockKind::Source => write!(f, "Source"),
+            BlockKind::Transformer => write!(f, "Transformer"),
+            BlockKind::Sink => write!(f, "Sink"),
+        }
+    }
+}
+
+impl Constructor {
+    /// The kind of block this constructor creates.
+    pub fn kind(&self) -> BlockKind {
+        match self {
+            Constructor::Source(_) => BlockKind::Source,
+            Constructor::Transformer(_) => BlockKind::Transformer,
+            Constructor::Sink(_) => BlockKind::Sink,
+        }
+    }
+}
+
+impl Registry {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Registry {
+            constructors: HashMap::new(),
+        }
+    }
+
+    /// Register a Source block. Replaces any block previously registered with the same type.
+    pub fn register_source<B, F>(&mut self, block_type: &str, new: F)
+    where
+        B: block::Source + 'static,
+        F: Fn(&config::BlockConfig, &mut stream::StreamCatalog) -> Result<B, error::LooperError>
+            + 'static,
+    {
+        let constructor: SourceConstructor =
+            Box::new(move |config, stream_catalog| Ok(Box::new(new(config, stream_catalog)?)));
+        self.constructors
+            .insert(block_type.to_owned(), Constructor::Source(constructor));
+    }
+
+    /// Register a Transformer block. Replaces any block previously registered with the same type.
+    pub fn register_transformer<B, F>(&mut self, block_type: &str, new: F)
+    where
+        B: block::Transformer + 'static,
+        F: Fn(&config::BlockConfig, &mut stream::StreamCatalog) -> Result<B, error::LooperError>
+            + 'static,
+    {
+        let constructor: TransformerConstructor =
+            Box::new(move |config, stream_catalog| Ok(Box::new(new(config, stream_catalog)?)));
+        self.constructors
+            .insert(block_type.to_owned(), Constructor::Transformer(constructor));
+    }
+
+    /// Register a Sink block. Replaces any block previously registered with the same type.
+    pub fn register_sink<B, F>(&mut self, block_type: &str, new: F)
+    where
+        B: block::Sink + 'static,
+        F: Fn(&config::BlockConfig, &stream::StreamCatalog) -> Result<B, error::LooperError>
+            + 'static,
+    {
+        let constructor: SinkConstructor =
+            Box::new(move |config, stream_catalog| Ok(Box::new(new(config, stream_catalog)?)));
+        self.constructors
+            .insert(block_type.to_owned(), Constructor::Sink(constructor));
+    }
+
+    /// Get the constructor for a block type.
+    pub fn get(&self, block_type: &str) -> Option<&Constructor> {
+        self.constructors.get(block_type)
+    }
+
+    /// List all of the registered block types and their kinds, sorted by type name.
+    pub fn list(&self) -> Vec<(&str, BlockKind)> {
+        let mut list: Vec<(&str, BlockKind)> = self
+            .constructors
+            .iter()
+            .map(|(name, constructor)| (name.as_str(), constructor.kind()))
+            .collect();
+        list.sort_by(|a, b| a.0.cmp(b.0));
+        list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Passthrough {}
+
+    impl block::Transformer for Passthrough {
+        fn transform(&mut self, _: &block::PlaybackState) {}
+    }
+
+    struct Silence {}
+
+    impl block::Source for Silence {
+        fn read(&mut self, _: &block::PlaybackState) {}
+    }
+
+    fn new_passthrough(
+        _: &config::BlockConfig,
+        _: &mut stream::StreamCatalog,
+    ) -> Result<Passthrough, error::LooperError> {
+        Ok(Passthrough {})
+    }
+
+    #[test]
+    fn test_register() {
+        let mut registry = Registry::new();
+        registry.register_transformer("Passthrough", new_passthrough);
+        registry.register_source("Silence", |_, _| Ok(Silence {}));
+
+        assert!(registry.get("Unknown").is_none());
+        assert_eq!(registry.get("Passthrough").unwrap().kind(), BlockKind::Transformer);
+        assert_eq!(
+            registry.list(),
+            vec![
+                ("Passthrough", BlockKind::Transformer),
+                ("Silence", BlockKind::Source)
+            ]
+        );
+
+        // Registering the same type again replaces the old block.
+        registry.register_source("Passthrough", |_, _| Ok(Silence {}));
+        assert_eq!(registry.get("Passthrough").unwrap().kind(), BlockKind::Source);
+    }
+}
@@ -4,21 +4,98 @@
 //! let runner = Runner::new("my_project.json")?;
 //! let _ = runner.run();
 //! ```
+//!
+//! Or, to render the project offline to a wav file without any audio hardware:
+//! ```
+//! let _ = runner.render("out.wav");
+//! ```
+//!
+//! Or, to measure the latency of the audio hardware:
+//! ```
+//! let latency = runner.calibrate()?;
+//! ```
+//!
+//! Or, to repeat a range of measures for practice:
+//! ```
+//! runner.set_practice_loop(config::PracticeLoop::from_arg("15:21:3")?)?;
+//! let _ = runner.run();
+//! ```
+//!
+//! Or, to run without a sound card:
+//! ```
+//! runner.set_audio_backend(backend::Backend::NullPaced);
+//! let _ = runner.run();
+//! ```
+//!
+//! Or, to count in two measures before playback starts:
+//! ```
+//! runner.set_count_in(2);
+//! let _ = runner.run();
+//! ```
+//!
+//! Every block is profiled while it runs, and the report is printed when playback stops. To also
+//! write it as JSON:
+//! ```
+//! runner.set_profile_output("profile.json");
+//! let _ = runner.run();
+//! ```
 
 extern crate audio;
+extern crate backend;
 extern crate block;
+extern crate check;
 extern crate combiner;
 extern crate config;
+extern crate count_in;
+extern crate error;
+extern crate graph;
 extern crate instrument;
 extern crate keyboard;
 extern crate log;
 extern crate looper;
 extern crate low_pass;
 extern crate metronome;
+extern crate profile;
 extern crate recorder;
+extern crate registry;
+extern crate shutdown;
 extern crate tempo;
 extern crate timer;
 extern crate toggle;
+extern crate wav;
+
+use std::rc::Rc;
+use std::thread;
+use std::time;
+
+/// Create a registry containing all of the built-in blocks.
+pub fn default_registry() -> registry::Registry {
+    let mut registry = registry::Registry::new();
+
+    // SOURCES
+    audio::register(&mut registry);
+    instrument::register(&mut registry);
+    metronome::register(&mut registry);
+
+    // TRANSFORMERS
+    combiner::register(&mut registry);
+    looper::register(&mut registry);
+    low_pass::register(&mut registry);
+    toggle::register(&mut registry);
+
+    // SINKS
+    recorder::register(&mut registry);
+
+    registry
+}
+
+/// When each file was last modified, or None if that can't be determined.
+fn files_modified(filenames: &[String]) -> Vec<Option<time::SystemTime>> {
+    filenames
+        .iter()
+        .map(|f| std::fs::metadata(f).and_then(|m| m.modified()).ok())
+        .collect()
+}
 
 /// Top level looper runner.
 pub struct Runner {
@@ -30,99 +107,346 @@ pub struct Runner {
 
     /// Keyboard I/O.
     keyboard: keyboard::Keyboard,
+
+    /// The blocks available to the project.
+    registry: registry::Registry,
+
+    /// The audio backend used for every audio device, if not selected by device name.
+    audio_backend: Option<backend::Backend>,
+
+    /// The project file.
+    filename: String,
+
+    /// When the project file and each file it includes were last modified, if known.
+    modified: Vec<Option<time::SystemTime>>,
+
+    /// Where to write the profiling report as JSON, if anywhere.
+    profile_output: Option<String>,
 }
 
 impl Runner {
     /// Create a new runner.
-    pub fn new(filename: &str) -> Result<Self, String> {
+    pub fn new(filename: &str) -> Result<Self, error::LooperError> {
         // Read in configuration
         let project = config::ProjectConfig::new(filename)?;
-        let tempo = log::unwrap_abort_str!(tempo::Tempo::new(&project));
-        let keyboard = log::unwrap_abort_str!(keyboard::Keyboard::new());
+        let tempo = tempo::Tempo::new(&project)?;
+        project.set_timeline(Rc::new(tempo.clone()));
+
+        // The keyboard is only attached to the terminal for realtime playback.
+        let keyboard = keyboard::Keyboard::detached();
 
         // Initialize the runner.
+        let modified = files_modified(&project.files);
         Ok(Runner {
             project: project,
             tempo: tempo,
             keyboard: keyboard,
+            registry: default_registry(),
+            audio_backend: None,
+            filename: filename.to_owned(),
+            modified: modified,
+            profile_output: None,
         })
     }
 
+    /// Get the block registry. Applications embedding the runner can use this to register their
+    /// own blocks before calling run().
+    pub fn registry(&mut self) -> &mut registry::Registry {
+        &mut self.registry
+    }
+
+    /// Use the same audio backend for every AudioSource and AudioSink in the project, regardless
+    /// of their device names.
+    pub fn set_audio_backend(&mut self, backend: backend::Backend) {
+        audio::register_backend(&mut self.registry, Some(backend));
+        self.audio_backend = Some(backend);
+    }
+
+    /// Repeat a range of measures, replacing the project's own practice_loop if it has one.
+    pub fn set_practice_loop(
+        &mut self,
+        practice_loop: config::PracticeLoop,
+    ) -> Result<(), error::LooperError> {
+        if let Err(e) = practice_loop.validate_stop(self.project.stop_measure) {
+            log::abort_msg!(e);
+        }
+        self.project.practice_loop = Some(practice_loop);
+        Ok(())
+    }
+
+    /// Count in some number of measures before playback starts, keeping the project's own count-in
+    /// sounds if it has them.
+    pub fn set_count_in(&mut self, measures: u32) {
+        match &mut self.project.count_in {
+            Some(count_in) => count_in.measures = measures,
+            None => self.project.count_in = Some(config::CountIn::new(measures)),
+        }
+    }
+
+    /// Write the profiling report to a JSON file when playback stops.
+    pub fn set_profile_output(&mut self, filename: &str) {
+        self.profile_output = Some(filename.to_owned());
+    }
+
     /// Run!
-    pub fn run(&mut self) -> Result<(), ()> {
-        // Initialize portaudio.
-        let pa = log::unwrap_abort!(audio::pa_get());
+    pub fn run(&mut self) -> Result<(), error::LooperError> {
+        // Keypresses are only available from a terminal, which CI machines may not have.
+        self.keyboard = match keyboard::Keyboard::new() {
+            Ok(v) => v,
+            Err(_) => {
+                println!("stdin is not a terminal, keypresses are disabled.");
+                keyboard::Keyboard::detached()
+            }
+        };
+        let result = self.run_blocks(true);
 
-        // Initialize streams.
-        let mut stream_catalog = stream::StreamCatalog::new();
+        // Hand the terminal back in the state we found it.
+        self.keyboard = keyboard::Keyboard::detached();
 
-        // Populate all of the sources/sinks/transformers.
-        let mut sources: Vec<Box<dyn block::Source>> = Vec::new();
-        let mut sinks: Vec<Box<dyn block::Sink>> = Vec::new();
-        let mut transformers: Vec<Box<dyn block::Transformer>> = Vec::new();
+        result
+    }
 
-        // Create all of the blocks.
-        for block_config in &self.project.blocks {
-            match block_config.block_type.as_str() {
-                // SOURCES
-                "AudioSource" => {
-                    let source = audio::AudioSource::new(block_config, &mut stream_catalog, &pa)?;
-                    sources.push(Box::new(source));
-                }
-                "VirtualInstrument" => {
-                    let source =
-                        instrument::VirtualInstrument::new(block_config, &mut stream_catalog)?;
-                    sources.push(Box::new(source));
-                }
-                "Metronome" => {
-                    let source = metronome::Metronome::new(block_config, &mut stream_catalog)?;
-                    sources.push(Box::new(source));
-                }
+    /// Render the project from start_measure to stop_measure as fast as possible and write the mix
+    /// of all AudioSinks to a wav file. No audio hardware is used.
+    pub fn render(&mut self, filename: &str) -> Result<(), error::LooperError> {
+        log::abort_if_msg!(
+            self.project.stop_measure < 0.0,
+            "Rendering requires a \"stop_measure\""
+        );
+        log::abort_if_msg!(
+            self.project
+                .practice_loop
+                .as_ref()
+                .is_some_and(|p| p.repeats.is_none()),
+            "Rendering a practice loop requires \"repeats\""
+        );
 
-                // TRANSFORMERS
-                "Loop" => {
-                    let tform = looper::Looper::new(block_config, &mut stream_catalog)?;
-                    transformers.push(Box::new(tform));
-                }
-                "Combiner" => {
-                    let tform = combiner::Combiner::new(block_config, &mut stream_catalog)?;
-                    transformers.push(Box::new(tform));
-                }
-                "Toggle" => {
-                    let tform = toggle::Toggle::new(block_config, &mut stream_catalog)?;
-                    transformers.push(Box::new(tform));
-                }
-                "LowPass" => {
-                    let tform = low_pass::LowPass::new(block_config, &mut stream_catalog)?;
-                    transformers.push(Box::new(tform));
-                }
+        // Swap the audio hardware blocks out for offline ones.
+        let mix = stream::empty_clip();
+        audio::register_render(&mut self.registry, &mix);
+        self.run_blocks(false)?;
 
-                // SINKS
-                // Sinks do not get mutable references to StreamCatalog because
-                // they should not be allowed to create streams.
-                "AudioSink" => {
-                    let sink = audio::AudioSink::new(block_config, &stream_catalog, &pa)?;
-                    sinks.push(Box::new(sink));
-                }
-                "Recorder" => {
-                    let sink = recorder::Recorder::new(block_config, &stream_catalog)?;
-                    sinks.push(Box::new(sink));
-                }
+        wav::write_wav_file(&mix, filename, self.project.sample_rate, self.project.num_channels)?;
+        println!("Rendered {} samples => {}", mix.borrow().len(), filename);
 
-                _ => {
-                    log::abort_msg!(format!("Unknown block: {}", block_config.block_type));
-                }
+        Ok(())
+    }
+
+    /// Measure the round-trip latency between the project's audio devices [samples]. Use the
+    /// result as the project's "latency".
+    pub fn calibrate(&mut self) -> Result<usize, error::LooperError> {
+        audio::calibrate(&self.project, self.audio_backend)
+    }
+
+    /// Check the project for problems without running it or touching any audio hardware.
+    pub fn check(&mut self) -> Vec<check::Problem> {
+        audio::register_backend(&mut self.registry, Some(backend::Backend::Null));
+        check::check(&self.project, &self.registry)
+    }
+
+    /// The wall clock duration of a single buffer.
+    fn buffer_duration(&self) -> time::Duration {
+        time::Duration::from_secs_f64(self.tempo.buffer_size as f64 / self.tempo.sample_rate as f64)
+    }
+
+    /// Move the playhead to a measure and let every block know.
+    fn seek(&mut self, measure: f32, blocks: &mut Blocks) {
+        println!("Jumping to measure {:.2}", measure);
+        self.tempo.seek(measure);
+
+        let state = block::PlaybackState {
+            tempo: &self.tempo,
+            keyboard: &self.keyboard,
+        };
+        blocks.seek(&state);
+    }
+
+    /// Jump back to the start of the practice loop if the playhead just reached the end of it and
+    /// there are passes left.
+    ///
+    /// # Arguments
+    ///
+    /// * previous_measure - Where the playhead was before the last step.
+    /// * passes - The number of times the practice loop has been started, counting this one.
+    fn wrap_practice_loop(&mut self, previous_measure: f32, passes: &mut u32, blocks: &mut Blocks) {
+        if let Some(practice) = self.project.practice_loop.clone() {
+            let current_measure = self.tempo.current_measure();
+            if previous_measure < practice.stop
+                && current_measure >= practice.stop
+                && practice.repeats.map_or(true, |r| *passes < r)
+            {
+                *passes += 1;
+                println!("Practice loop pass {}", passes);
+                self.seek(practice.start, blocks);
+            }
+        }
+    }
+
+    /// Play the count-in through every sink. The playhead stays at the start measure, so no blocks
+    /// run in the meantime.
+    fn count_in(
+        &self,
+        config: &config::CountIn,
+        blocks: &mut Blocks,
+    ) -> Result<(), error::LooperError> {
+        println!("Counting in {} measures", config.measures);
+        let mut count_in = count_in::CountIn::new(config, &self.tempo, self.project.num_channels)?;
+        while !count_in.is_done() && !shutdown::requested() {
+            let clicks = count_in.next();
+            for (_, source) in &mut blocks.sources {
+                source.count_in();
+            }
+            for (_, sink) in &mut blocks.sinks {
+                sink.count_in(clicks);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reload the project file, rebuilding only the blocks whose config changed. Every other block
+    /// keeps its state. If any part of the new project fails to load, nothing is changed.
+    fn reload(
+        &mut self,
+        blocks: &mut Blocks,
+        stream_catalog: &mut stream::StreamCatalog,
+    ) -> Result<(), error::LooperError> {
+        println!("Reloading {}", self.filename);
+        let project = match config::ProjectConfig::new(&self.filename) {
+            Ok(v) => v,
+            Err(e) => {
+                log::abort_msg!(e);
+            }
+        };
+
+        // The tempo doesn't change until the next run, so resolve the new segments with this one.
+        project.set_timeline(Rc::new(self.tempo.clone()));
+
+        // Blocks that changed are removed and created again from scratch.
+        let removed: Vec<String> = self
+            .project
+            .blocks
+            .iter()
+            .filter(|old| {
+                !project
+                    .blocks
+                    .iter()
+                    .any(|new| new.name == old.name && new.same_as(old))
+            })
+            .map(|old| old.name.to_owned())
+            .collect();
+        let created: Vec<&config::BlockConfig> = project
+            .blocks
+            .iter()
+            .filter(|new| {
+                !self
+                    .project
+                    .blocks
+                    .iter()
+                    .any(|old| old.name == new.name && old.same_as(new))
+            })
+            .collect();
+
+        // Build the new blocks alongside the old ones. The streams are shared with the running
+        // blocks, so they keep their size until the swap, and nothing changes if one fails.
+        let mut new_catalog = stream_catalog.clone();
+        new_catalog.defer_resizes();
+        for name in &removed {
+            new_catalog.remove_block(name);
+        }
+        let mut new_blocks = Blocks::new();
+        for block_config in &created {
+            new_blocks.create(&self.registry, block_config, &mut new_catalog)?;
+        }
+        new_catalog.validate()?;
+
+        // Make sure that the new blocks can still run in dependency order.
+        let names: Vec<String> = blocks
+            .transformers
+            .iter()
+            .filter(|(n, _)| !removed.contains(n))
+            .chain(&new_blocks.transformers)
+            .map(|(n, _)| n.clone())
+            .collect();
+        if let Err(e) = graph::Graph::new(&new_catalog).sort(&names) {
+            log::abort_msg!(e);
+        }
+
+        // Pick the new blocks up from the current playhead.
+        let state = block::PlaybackState {
+            tempo: &self.tempo,
+            keyboard: &self.keyboard,
+        };
+        new_blocks.seek(&state);
+
+        // Swap the changed blocks out.
+        for name in &removed {
+            blocks.remove(name);
+        }
+        blocks.append(new_blocks);
+        blocks.sort(&new_catalog)?;
+        new_catalog.apply_resizes();
+        new_catalog.resolve_latencies(self.project.latency);
+        *stream_catalog = new_catalog;
+
+        for name in &removed {
+            if !created.iter().any(|b| &b.name == name) {
+                println!("Removed block: {}", name);
+            }
+        }
+        for block_config in &created {
+            match removed.contains(&block_config.name) {
+                true => println!("Rebuilt block: {}", block_config.name),
+                false => println!("Added block: {}", block_config.name),
             }
         }
 
-        // Flush all of the input buffers.
-        for _ in 0..3 {
+        // Only the blocks are reloaded. Everything else takes effect on the next run.
+        self.project.blocks = project.blocks;
+        self.project.files = project.files;
+        Ok(())
+    }
+
+    /// Initialize all of the blocks and run them until the stop measure.
+    ///
+    /// # Arguments
+    ///
+    /// * realtime - Whether the blocks are connected to audio hardware. The project file is only
+    ///              reloaded when it changes during realtime playback.
+    fn run_blocks(&mut self, realtime: bool) -> Result<(), error::LooperError> {
+        // Initialize streams.
+        let mut stream_catalog = stream::StreamCatalog::with_format(
+            self.project.sample_rate,
+            self.project.buffer_size,
+            self.project.num_channels,
+        );
+
+        // Create all of the blocks.
+        let mut blocks = Blocks::new();
+        for block_config in &self.project.blocks {
+            blocks.create(&self.registry, block_config, &mut stream_catalog)?;
+        }
+
+        // Every stream that was bound to must have been created by some block.
+        stream_catalog.validate()?;
+
+        // Work out how late every stream is, now that we know where they all come from.
+        stream_catalog.resolve_latencies(self.project.latency);
+
+        // Run the transformers in dependency order.
+        blocks.sort(&stream_catalog)?;
+
+        // From here on, Ctrl+C stops playback gracefully so that every block gets cleaned up.
+        shutdown::install()?;
+
+        // Flush all of the input buffers. There's no hardware to flush when rendering.
+        let num_flushes = if realtime { 3 } else { 0 };
+        for _ in 0..num_flushes {
             let state = block::PlaybackState {
                 tempo: &self.tempo,
                 keyboard: &self.keyboard,
             };
 
-            for source in &mut sources {
+            for (_, source) in &mut blocks.sources {
                 source.read(&state);
             }
         }
@@ -130,53 +454,122 @@ impl Runner {
         // Skip the tempo forward to the start measure.
         self.tempo.skip(self.project.start_measure);
 
+        // Count in to the start measure. There's nobody to count in when rendering.
+        if let (true, Some(config)) = (realtime, &self.project.count_in) {
+            self.count_in(config, &mut blocks)?;
+        }
+
         // Run all of the blocks.
         let mut total_timer = timer::Timer::start();
         let mut compute_timer = timer::Timer::start();
+        let budget = if realtime {
+            Some(self.buffer_duration())
+        } else {
+            None
+        };
+        let mut profiler = profile::Profiler::new(budget);
+        let mut paused = false;
+        let mut practice_passes = 1;
         loop {
-            {
+            if paused {
+                // Nothing plays while paused, so just wait for the next keypress.
+                compute_timer.pause();
+                thread::sleep(self.buffer_duration());
+                compute_timer.resume();
+            } else {
                 let state = block::PlaybackState {
                     tempo: &self.tempo,
                     keyboard: &self.keyboard,
                 };
 
                 // Run all of the source blocks.
-                for source in &mut sources {
-                    if source.is_blocking_io() {
+                for (name, source) in &mut blocks.sources {
+                    let blocking_io = source.is_blocking_io();
+                    if blocking_io {
                         compute_timer.pause();
                     }
+                    let start = time::Instant::now();
                     source.read(&state);
-                    if source.is_blocking_io() {
+                    profiler.record(name, profile::Stage::Read, blocking_io, start.elapsed());
+                    if blocking_io {
                         compute_timer.resume();
                     }
                 }
 
                 // Run all of the transformer blocks.
-                for transformer in &mut transformers {
+                for (name, transformer) in &mut blocks.transformers {
+                    let start = time::Instant::now();
                     transformer.transform(&state);
+                    profiler.record(name, profile::Stage::Transform, false, start.elapsed());
                 }
 
                 // Run all of the sink blocks.
-                for sink in &mut sinks {
-                    if sink.is_blocking_io() {
+                for (name, sink) in &mut blocks.sinks {
+                    let blocking_io = sink.is_blocking_io();
+                    if blocking_io {
                         compute_timer.pause();
                     }
+                    let start = time::Instant::now();
                     sink.write(&state);
-                    if sink.is_blocking_io() {
+                    profiler.record(name, profile::Stage::Write, blocking_io, start.elapsed());
+                    if blocking_io {
                         compute_timer.resume();
                     }
                 }
+
+                profiler.end_cycle();
             }
 
-            self.tempo.step(1);
+            let previous_measure = self.tempo.current_measure();
+            if !paused {
+                self.tempo.step(1);
+            }
             self.keyboard.reset();
 
+            // Pick up changes to the project file, or any file it includes, at the start of each
+            // measure.
+            if realtime && self.tempo.on_measure() {
+                if files_modified(&self.project.files) != self.modified {
+                    if self.reload(&mut blocks, &mut stream_catalog).is_err() {
+                        println!("Failed to reload {}, keeping the old blocks.", self.filename);
+                    }
+                    self.modified = files_modified(&self.project.files);
+                }
+            }
+
+            // Go around the practice loop again when the playhead reaches the end of it.
+            self.wrap_practice_loop(previous_measure, &mut practice_passes, &mut blocks);
+
+            // Handle the transport keys.
+            for transport in self.keyboard.transport.clone() {
+                let current_measure = self.tempo.current_measure();
+                let measure = match transport {
+                    keyboard::Transport::PauseResume => {
+                        paused = !paused;
+                        match paused {
+                            true => println!("Paused at measure {:.2}", current_measure),
+                            false => println!("Resumed at measure {:.2}", current_measure),
+                        }
+                        continue;
+                    }
+                    keyboard::Transport::Back => current_measure - self.project.seek_measures,
+                    keyboard::Transport::Forward => current_measure + self.project.seek_measures,
+                    keyboard::Transport::Restart => self.project.start_measure,
+                };
+                self.seek(measure.max(0.0), &mut blocks);
+            }
+
             if self.project.stop_measure >= 0.0
                 && self.tempo.current_measure() >= self.project.stop_measure
             {
                 println!("Looper is done looping.");
                 break;
             }
+
+            if shutdown::requested() {
+                println!("Looper was interrupted, shutting down.");
+                break;
+            }
         }
 
         let total_duration = total_timer.stop();
@@ -185,17 +578,232 @@ impl Runner {
         println!("Compute duration: {}ms (excludes nonblocking I/O)", compute_duration);
         println!("I/O duration:     {}ms (only blocking I/O)", total_duration - compute_duration);
 
+        // Collect the device overflows and underflows before the devices are released.
+        for (name, source) in &blocks.sources {
+            profiler.set_xruns(name, profile::Stage::Read, source.overflows(), 0);
+        }
+        for (name, sink) in &blocks.sinks {
+            profiler.set_xruns(name, profile::Stage::Write, 0, sink.underflows());
+        }
+
         // Run the optional cleanup for all blocks.
-        for source in &mut sources {
+        blocks.cleanup();
+
+        profiler.print();
+        if let Some(filename) = &self.profile_output {
+            profiler.write_json(filename)?;
+            println!("Wrote profile => {}", filename);
+        }
+
+        Ok(())
+    }
+}
+
+/// Every block in the project, each paired with its name.
+struct Blocks {
+    /// The source blocks.
+    sources: Vec<(String, Box<dyn block::Source>)>,
+
+    /// The transformer blocks, in the order they run.
+    transformers: Vec<(String, Box<dyn block::Transformer>)>,
+
+    /// The sink blocks.
+    sinks: Vec<(String, Box<dyn block::Sink>)>,
+}
+
+impl Blocks {
+    /// Create an empty set of blocks.
+    fn new() -> Self {
+        Blocks {
+            sources: Vec::new(),
+            transformers: Vec::new(),
+            sinks: Vec::new(),
+        }
+    }
+
+    /// Create a block from its config.
+    fn create(
+        &mut self,
+        registry: &registry::Registry,
+        block_config: &config::BlockConfig,
+        stream_catalog: &mut stream::StreamCatalog,
+    ) -> Result<(), error::LooperError> {
+        stream_catalog.set_block(&block_config.name);
+        let constructor = log::opt_abort_msg!(
+            registry.get(&block_config.block_type),
+            format!("Unknown block: {}", block_config.block_type)
+        );
+        let name = block_config.name.to_owned();
+        let in_block = |e: error::LooperError| e.in_block(&block_config.name);
+        match constructor {
+            registry::Constructor::Source(new) => {
+                let block = new(block_config, stream_catalog).map_err(in_block)?;
+                self.sources.push((name, block));
+            }
+            registry::Constructor::Transformer(new) => {
+                let block = new(block_config, stream_catalog).map_err(in_block)?;
+                self.transformers.push((name, block));
+            }
+            registry::Constructor::Sink(new) => {
+                let block = new(block_config, stream_catalog).map_err(in_block)?;
+                self.sinks.push((name, block));
+            }
+        }
+        Ok(())
+    }
+
+    /// Clean up a block and remove it.
+    fn remove(&mut self, name: &str) {
+        if let Some(i) = self.sources.iter().position(|(n, _)| n == name) {
+            self.sources.remove(i).1.cleanup();
+        }
+        if let Some(i) = self.transformers.iter().position(|(n, _)| n == name) {
+            self.transformers.remove(i).1.cleanup();
+        }
+        if let Some(i) = self.sinks.iter().position(|(n, _)| n == name) {
+            self.sinks.remove(i).1.cleanup();
+        }
+    }
+
+    /// Move every block from another set of blocks into this one.
+    fn append(&mut self, mut other: Blocks) {
+        self.sources.append(&mut other.sources);
+        self.transformers.append(&mut other.transformers);
+        self.sinks.append(&mut other.sinks);
+    }
+
+    /// Sort the transformers into dependency order so that no transformer reads a stream before
+    /// the transformer that writes to it has run on the same cycle.
+    fn sort(&mut self, stream_catalog: &stream::StreamCatalog) -> Result<(), error::LooperError> {
+        let names: Vec<String> = self.transformers.iter().map(|(n, _)| n.clone()).collect();
+        let order = match graph::Graph::new(stream_catalog).sort(&names) {
+            Ok(v) => v,
+            Err(e) => {
+                log::abort_msg!(e);
+            }
+        };
+        let mut unordered: Vec<Option<(String, Box<dyn block::Transformer>)>> =
+            self.transformers.drain(..).map(Some).collect();
+        self.transformers = order
+            .iter()
+            .map(|&i| unordered[i].take().unwrap())
+            .collect();
+        Ok(())
+    }
+
+    /// Let every block know that the playhead moved.
+    fn seek(&mut self, state: &block::PlaybackState) {
+        for (_, source) in &mut self.sources {
+            source.seek(state);
+        }
+        for (_, transformer) in &mut self.transformers {
+            transformer.seek(state);
+        }
+        for (_, sink) in &mut self.sinks {
+            sink.seek(state);
+        }
+    }
+
+    /// Run the optional cleanup for all blocks.
+    fn cleanup(&mut self) {
+        for (_, source) in &mut self.sources {
             source.cleanup();
         }
-        for transformer in &mut transformers {
+        for (_, transformer) in &mut self.transformers {
             transformer.cleanup();
         }
-        for sink in &mut sinks {
+        for (_, sink) in &mut self.sinks {
             sink.cleanup();
         }
+    }
+}
 
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern crate test_blocks;
+
+    /// Create every block in the project, the same way run_blocks() does.
+    fn create_blocks(runner: &Runner) -> (Blocks, stream::StreamCatalog) {
+        let mut stream_catalog = stream::StreamCatalog::with_format(
+            runner.project.sample_rate,
+            runner.project.buffer_size,
+            runner.project.num_channels,
+        );
+        let mut blocks = Blocks::new();
+        for block_config in &runner.project.blocks {
+            blocks
+                .create(&runner.registry, block_config, &mut stream_catalog)
+                .unwrap();
+        }
+        stream_catalog.validate().unwrap();
+        blocks.sort(&stream_catalog).unwrap();
+        (blocks, stream_catalog)
+    }
+
+    fn block_names(blocks: &Blocks) -> Vec<&str> {
+        let sources = blocks.sources.iter().map(|(n, _)| n.as_str());
+        let transformers = blocks.transformers.iter().map(|(n, _)| n.as_str());
+        let sinks = blocks.sinks.iter().map(|(n, _)| n.as_str());
+        sources.chain(transformers).chain(sinks).collect()
+    }
+
+    #[test]
+    fn test_reload() {
+        let created = test_blocks::Created::default();
+        let mut runner = Runner::new("dat/runner/reload.yaml").unwrap();
+        runner.registry = test_blocks::registry(&created);
+        let (mut blocks, mut stream_catalog) = create_blocks(&runner);
+        let source = stream_catalog.bind_sink("source").unwrap();
+        assert_eq!(source.borrow().len(), 8);
+        created.borrow_mut().clear();
+
+        // The new source is created before the broken block fails, but the running blocks don't
+        // see any of it.
+        runner.filename = "dat/runner/reload_failed.yaml".to_string();
+        assert!(runner.reload(&mut blocks, &mut stream_catalog).is_err());
+        assert_eq!(source.borrow().len(), 8);
+        assert_eq!(block_names(&blocks), vec!["source", "transformer", "sink"]);
+        assert_eq!(runner.project.blocks.len(), 3);
+        assert!(stream_catalog.validate().is_ok());
+        created.borrow_mut().clear();
+
+        // Only the changed source and the added sink are created. The unchanged transformer keeps
+        // reading the same stream, which is now mono.
+        runner.filename = "dat/runner/reload_changed.yaml".to_string();
+        runner.reload(&mut blocks, &mut stream_catalog).unwrap();
+        assert_eq!(*created.borrow(), vec!["source", "extra"]);
+        assert_eq!(source.borrow().len(), 4);
+        assert_eq!(block_names(&blocks), vec!["source", "transformer", "sink", "extra"]);
+        assert_eq!(runner.project.blocks.len(), 4);
+    }
+
+    #[test]
+    fn test_practice_loop() {
+        let mut runner = Runner::new("dat/runner/practice_loop.yaml").unwrap();
+        let mut blocks = Blocks::new();
+
+        // Every time the playhead reaches measure 2 it jumps back to measure 1, until the range
+        // has been played 3 times. After that it plays on to the stop measure.
+        let mut passes = 1;
+        let mut wraps = 0;
+        while runner.tempo.current_measure() < runner.project.stop_measure {
+            let previous_measure = runner.tempo.current_measure();
+            runner.tempo.step(1);
+            runner.wrap_practice_loop(previous_measure, &mut passes, &mut blocks);
+            if runner.tempo.current_measure() < previous_measure {
+                assert_eq!(runner.tempo.current_measure(), 1.0);
+                wraps += 1;
+            }
+        }
+        assert_eq!(passes, 3);
+        assert_eq!(wraps, 2);
+
+        // The practice loop has to end before the stop measure.
+        let practice_loop = config::PracticeLoop::from_arg("1:5").unwrap();
+        assert!(runner.set_practice_loop(practice_loop).is_err());
+        let practice_loop = config::PracticeLoop::from_arg("1:4").unwrap();
+        assert!(runner.set_practice_loop(practice_loop).is_ok());
     }
 }
@@ -16,6 +16,11 @@
 //! }
 //! sampler.next(self.stream.borrow_mut());
 //! ```
+//!
+//! To start partway through the next buffer, e.g. exactly on a beat:
+//! ```
+//! sampler.play_at(clip, false, offset * num_channels);
+//! ```
 
 extern crate log;
 extern crate stream;
@@ -32,6 +37,9 @@ pub struct Sampler {
 
     /// Whether or not the Sampler is in loop mode.
     is_loop: bool,
+
+    /// The number of samples of the next buffer to skip before the clip starts.
+    delay: usize,
 }
 
 impl Sampler {
@@ -42,13 +50,27 @@ impl Sampler {
             clip_index: 0,
             is_playing: false,
             is_loop: false,
+            delay: 0,
         }
     }
 
     /// Play some samples.
     pub fn play(&mut self, clip: &stream::Clip, is_loop: bool) {
+        self.play_at(clip, is_loop, 0);
+    }
+
+    /// Play some samples, starting partway through the next buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * clip - The clip to play.
+    /// * is_loop - Whether to loop the clip.
+    /// * delay - The number of samples into the next buffer to start at. With interleaved
+    ///           channels, this is the number of frames times the number of channels.
+    pub fn play_at(&mut self, clip: &stream::Clip, is_loop: bool, delay: usize) {
         self.clip = Some(clip.clone());
         self.is_loop = is_loop;
+        self.delay = delay;
 
         self.is_playing = true;
         self.clip_index = 0;
@@ -77,6 +99,7 @@ impl Sampler {
         self.clip = None;
         self.is_playing = false;
         self.clip_index = 0;
+        self.delay = 0;
     }
 
     /// Get the next bunch of samples.
@@ -100,28 +123,81 @@ impl Sampler {
             };
             let clip = clip_rc.borrow();
 
-            // Read enough samples to fill the stream, unless we run out of clip.
-            let start_index = self.clip_index;
-            let stop_index = std::cmp::min(start_index + stream.len(), clip.len());
-
-            // Copy the clip slice to the output stream.
-            for i in start_index..stop_index {
-                stream[i - start_index] += clip[i];
+            // Wait out the delay before starting.
+            let delay = std::cmp::min(self.delay, stream.len());
+            self.delay -= delay;
+
+            // Fill the stream, going back to the start of the clip as many times as it takes if
+            // it's looping, unless we run out of clip.
+            let mut index = delay;
+            let mut finished = false;
+            while index < stream.len() && !clip.is_empty() {
+                let count = std::cmp::min(stream.len() - index, clip.len() - self.clip_index);
+                for i in 0..count {
+                    stream[index + i] += clip[self.clip_index + i];
+                }
+                index += count;
+                self.clip_index += count;
+
+                if self.clip_index == clip.len() {
+                    self.clip_index = 0;
+                    if !self.is_loop {
+                        finished = true;
+                        break;
+                    }
+                }
             }
-
-            self.clip_index = stop_index;
-            should_stop = stop_index == clip.len();
+            should_stop = finished || clip.is_empty();
         }
 
-        // Stop the clip or restart the loop if we're at the end.
-        if should_stop {
-            self.clip_index = 0;
-            if !self.is_loop {
-                self.stop();
-            }
+        // Stop the clip if we're at the end.
+        if should_stop && !self.is_loop {
+            self.stop();
         }
     }
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_play_at() {
+        let clip = stream::empty_clip();
+        clip.borrow_mut().extend_from_slice(&[1.0, 2.0, 3.0, 4.0]);
+        let mut sampler = Sampler::new();
+        let mut buffer = vec![stream::ZERO; 3];
+
+        // The clip starts partway through the first buffer and carries on into the next.
+        sampler.play_at(&clip, false, 2);
+        sampler.next(&mut buffer);
+        assert_eq!(buffer, vec![0.0, 0.0, 1.0]);
+
+        buffer.fill(stream::ZERO);
+        sampler.next(&mut buffer);
+        assert_eq!(buffer, vec![2.0, 3.0, 4.0]);
+        assert!(!sampler.is_playing());
+    }
+
+    #[test]
+    fn test_loop_wrap() {
+        let clip = stream::empty_clip();
+        clip.borrow_mut().extend_from_slice(&[1.0, 2.0, 3.0]);
+        let mut sampler = Sampler::new();
+        let mut buffer = vec![stream::ZERO; 4];
+
+        // The loop goes back to the start of the clip partway through a buffer.
+        sampler.play_at(&clip, true, 1);
+        sampler.next(&mut buffer);
+        assert_eq!(buffer, vec![0.0, 1.0, 2.0, 3.0]);
+
+        buffer.fill(stream::ZERO);
+        sampler.next(&mut buffer);
+        assert_eq!(buffer, vec![1.0, 2.0, 3.0, 1.0]);
+
+        buffer.fill(stream::ZERO);
+        sampler.next(&mut buffer);
+        assert_eq!(buffer, vec![2.0, 3.0, 1.0, 2.0]);
+        assert!(sampler.is_playing());
+    }
+}
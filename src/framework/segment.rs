@@ -1,7 +1,10 @@
 /// Segments are a window of time (in units of measures) that specify when a
 /// block should be operating or not operating.
 ///
-/// Segments are configured under the block under the "segments" key.
+/// Segments are configured under the block under the "segments" key. Times can also be written as
+/// "<measure>:<beat>[:<tick>]", "<beats>b" or "<seconds>s", which config resolves to measures
+/// through the project tempo. A segment can also cover one of the project's "sections" with
+/// "section", or several of them in a row with "repeat_sections".
 ///
 /// The example below instantiates a looper which listens from measures 10-14
 /// and then outputs from measures 14-20.
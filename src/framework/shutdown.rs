@@ -0,0 +1,65 @@
+//! Graceful shutdown on SIGINT/SIGTERM.
+//!
+//! Once installed, the first Ctrl+C (or SIGTERM) only sets a flag, which the runner polls every
+//! cycle so that it can stop playback and run every block's cleanup. A second signal terminates
+//! the process immediately.
+//!
+//! ```
+//! shutdown::install()?;
+//! while !shutdown::requested() {
+//!     // Play some audio.
+//! }
+//! ```
+
+extern crate error;
+
+use std::os::raw::c_int;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Interrupt from keyboard (Ctrl+C).
+const SIGINT: c_int = 2;
+
+/// Termination signal.
+const SIGTERM: c_int = 15;
+
+/// The default signal disposition.
+const SIG_DFL: usize = 0;
+
+/// The return value of signal() on error.
+const SIG_ERR: usize = !0;
+
+extern "C" {
+    fn signal(signum: c_int, handler: usize) -> usize;
+}
+
+/// Whether a shutdown has been requested.
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Signal handler. This may only do async-signal-safe things.
+extern "C" fn handle_signal(signum: c_int) {
+    REQUESTED.store(true, Ordering::SeqCst);
+
+    // Restore the default handler so that a second signal kills the process.
+    unsafe {
+        signal(signum, SIG_DFL);
+    }
+}
+
+/// Install the SIGINT/SIGTERM handlers.
+pub fn install() -> Result<(), error::LooperError> {
+    REQUESTED.store(false, Ordering::SeqCst);
+    for signum in [SIGINT, SIGTERM] {
+        let handler = handle_signal as extern "C" fn(c_int) as usize;
+        if unsafe { signal(signum, handler) } == SIG_ERR {
+            let msg = format!("Failed to install handler for signal {}", signum);
+            println!("{}", msg);
+            return Err(error::LooperError::Abort { cause: msg });
+        }
+    }
+    Ok(())
+}
+
+/// Whether a shutdown has been requested since install() was called.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
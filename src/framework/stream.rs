@@ -9,8 +9,27 @@
 //! All output/source streams are considered read/write and must be *created* using create_source()
 //! while input/sink streams should only use bind_sink(). This assumption is baked into the runtime
 //! control flow and if not followed will cause projects to fail to load.
+//!
+//! Blocks may bind to a stream before it has been created. The StreamCatalog records which block
+//! created and bound each stream (see set_block()) so that the framework can run blocks in
+//! dependency order and report streams that were never created (see validate()).
+//!
+//! The sample rate and buffer size are set per project. Blocks should read them from the
+//! StreamCatalog at initialization rather than assuming the defaults.
+//!
+//! Streams and clips may carry more than one audio channel (e.g. stereo), in which case their
+//! samples are interleaved. A stream with N audio channels holds N * buffer_size samples. Streams
+//! are created with the project's number of audio channels unless the block asks for a different
+//! number, so blocks reading from a stream should not assume its channel count and should use
+//! mix() to convert between channel counts.
+//!
+//! Streams captured from audio hardware arrive late by the round-trip latency of the hardware. The
+//! StreamCatalog tracks how late every stream is (see bind_latency()) so that blocks which record
+//! their inputs can shift the recording back into time.
+
+extern crate error;
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::string::String;
@@ -21,17 +40,23 @@ pub type Sample = f32;
 /// Zero constant of type Sample.
 pub const ZERO: Sample = 0 as Sample;
 
-/// The number of samples in a buffer. This is the number of samples sent to
+/// The default number of samples in a buffer. This is the number of samples sent to
 /// sink blocks, and also the number of samples expected from source blocks.
-pub const SAMPLES_PER_BUFFER: usize = 256;
+pub const DEFAULT_BUFFER_SIZE: usize = 256;
 
-/// The sample rate of the audio stream [samples/s].
-pub const SAMPLE_RATE: i32 = 44100;
+/// The default sample rate of the audio stream [samples/s].
+pub const DEFAULT_SAMPLE_RATE: i32 = 44100;
 
-/// Stream type shorthand.
-pub type RawStream = [Sample; SAMPLES_PER_BUFFER];
+/// The default number of audio channels in a stream.
+pub const DEFAULT_NUM_CHANNELS: usize = 1;
+
+/// Stream type shorthand. A stream holds buffer_size samples for each of its audio channels.
+pub type RawStream = Vec<Sample>;
 pub type Stream = Rc<RefCell<RawStream>>;
 
+/// How late a stream's audio is compared to the audio being played on the same cycle [samples].
+pub type Latency = Rc<Cell<usize>>;
+
 /// A variable-sized audio clip.
 pub type RawClip = Vec<Sample>;
 pub type Clip = Rc<RefCell<RawClip>>;
@@ -41,12 +66,94 @@ pub fn empty_clip() -> Clip {
     Clip::new(RawClip::new().into())
 }
 
+/// Add interleaved samples into another buffer, converting between audio channel counts. Mono is
+/// copied to every output channel, everything is averaged into a mono output, and otherwise input
+/// channels are mapped onto output channels in order, wrapping around if there are fewer inputs.
+///
+/// # Arguments
+///
+/// * src - The samples to add.
+/// * src_channels - The number of audio channels in src.
+/// * dst - The samples to add to.
+/// * dst_channels - The number of audio channels in dst.
+/// * gains - The gain applied to each output channel. Missing gains are 1.0.
+pub fn mix(
+    src: &[Sample],
+    src_channels: usize,
+    dst: &mut [Sample],
+    dst_channels: usize,
+    gains: &[f32],
+) {
+    let num_frames = std::cmp::min(src.len() / src_channels, dst.len() / dst_channels);
+    for frame in 0..num_frames {
+        let src_frame = &src[frame * src_channels..(frame + 1) * src_channels];
+        let dst_frame = &mut dst[frame * dst_channels..(frame + 1) * dst_channels];
+        for c in 0..dst_channels {
+            let sample = match (src_channels, dst_channels) {
+                (1, _) => src_frame[0],
+                (_, 1) => src_frame.iter().sum::<Sample>() / src_channels as Sample,
+                _ => src_frame[c % src_channels],
+            };
+            dst_frame[c] += sample * gains.get(c).copied().unwrap_or(1.0);
+        }
+    }
+}
+
+/// Convert interleaved samples to a different number of audio channels.
+pub fn convert_channels(src: &[Sample], src_channels: usize, dst_channels: usize) -> RawClip {
+    if src_channels == dst_channels {
+        return src.to_vec();
+    }
+
+    let mut dst = vec![ZERO; src.len() / src_channels * dst_channels];
+    mix(src, src_channels, &mut dst, dst_channels, &[]);
+    dst
+}
+
+/// Mix interleaved samples into a clip, starting at a frame offset into the clip. Frames which land
+/// before the start of the clip or at/after max_frames are dropped, and the clip grows as needed.
+///
+/// # Arguments
+///
+/// * clip - The clip to add to.
+/// * clip_channels - The number of audio channels in the clip.
+/// * offset - The frame in the clip where src starts. This may be negative.
+/// * src - The samples to add.
+/// * src_channels - The number of audio channels in src.
+/// * max_frames - The maximum length of the clip [frames].
+pub fn mix_into_clip(
+    clip: &mut RawClip,
+    clip_channels: usize,
+    offset: i64,
+    src: &[Sample],
+    src_channels: usize,
+    max_frames: usize,
+) {
+    let skip = (-offset).max(0) as usize;
+    let start = offset.max(0) as usize;
+    let end = (start + (src.len() / src_channels).saturating_sub(skip)).min(max_frames);
+    if end <= start {
+        return;
+    }
+
+    if clip.len() < end * clip_channels {
+        clip.resize(end * clip_channels, ZERO);
+    }
+    mix(
+        &src[skip * src_channels..],
+        src_channels,
+        &mut clip[start * clip_channels..end * clip_channels],
+        clip_channels,
+        &[],
+    );
+}
+
 pub trait Scalable {
     /// Scale the volume of an audio unit.
     fn scale(&mut self, volume: f32);
 }
 
-impl Scalable for RawStream {
+impl Scalable for RawClip {
     fn scale(&mut self, volume: f32) {
         for i in 0..self.len() {
             self[i] *= volume;
@@ -54,12 +161,17 @@ impl Scalable for RawStream {
     }
 }
 
-impl Scalable for RawClip {
-    fn scale(&mut self, volume: f32) {
-        for i in 0..self.len() {
-            self[i] *= volume;
-        }
-    }
+/// A link between two blocks through a stream.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Link {
+    /// The channel name of the stream.
+    pub channel: String,
+
+    /// The name of the block which created the stream.
+    pub producer: String,
+
+    /// The name of the block which bound to the stream.
+    pub consumer: String,
 }
 
 /// A catalog of streams. Blocks use this at creation to create or bind to output or input streams
@@ -67,38 +179,296 @@ impl Scalable for RawClip {
 /// relevant read()/write()/transform() is returned. Saving off a mutable reference at runtime is
 /// prohibited, as a mutable stream reference will cause other blocks which depend on the stream to
 /// panic.
+#[derive(Clone)]
 pub struct StreamCatalog {
-    streams: HashMap<String, Stream>,
+    /// All of the streams, keyed by channel name.
+    streams: RefCell<HashMap<String, Stream>>,
+
+    /// The name of the block which created each stream, keyed by channel name.
+    producers: HashMap<String, String>,
+
+    /// Every (channel, block) pair bound with bind_sink(), in the order they were bound.
+    consumers: RefCell<Vec<(String, String)>>,
+
+    /// The name of the block currently being initialized.
+    current_block: String,
+
+    /// The sample rate of every stream [samples/s].
+    sample_rate: i32,
+
+    /// The number of samples in every stream, per audio channel.
+    buffer_size: usize,
+
+    /// The number of audio channels in streams created with create_source().
+    num_channels: usize,
+
+    /// The latency of every stream bound with bind_latency(), keyed by channel name.
+    latencies: RefCell<HashMap<String, Latency>>,
+
+    /// The streams captured from audio hardware, with their own latency or None to use the
+    /// default latency.
+    captured: HashMap<String, Option<usize>>,
+
+    /// Whether the streams are shared with blocks which are already running, so that changing
+    /// the size of an existing stream has to wait until apply_resizes().
+    defer_resizes: bool,
+
+    /// The streams whose size changed while resizes were deferred, with their new sizes.
+    resizes: Vec<(Stream, usize)>,
 }
 
 impl StreamCatalog {
+    /// Create a catalog with the default sample rate, buffer size and number of audio channels.
     pub fn new() -> StreamCatalog {
+        StreamCatalog::with_format(DEFAULT_SAMPLE_RATE, DEFAULT_BUFFER_SIZE, DEFAULT_NUM_CHANNELS)
+    }
+
+    /// Create a catalog whose streams have the given sample rate, buffer size and number of audio
+    /// channels.
+    pub fn with_format(sample_rate: i32, buffer_size: usize, num_channels: usize) -> StreamCatalog {
         StreamCatalog {
-            streams: HashMap::new(),
+            streams: RefCell::new(HashMap::new()),
+            producers: HashMap::new(),
+            consumers: RefCell::new(Vec::new()),
+            current_block: String::new(),
+            sample_rate: sample_rate,
+            buffer_size: buffer_size,
+            num_channels: num_channels,
+            latencies: RefCell::new(HashMap::new()),
+            captured: HashMap::new(),
+            defer_resizes: false,
+            resizes: Vec::new(),
+        }
+    }
+
+    /// The sample rate of every stream [samples/s].
+    pub fn sample_rate(&self) -> i32 {
+        self.sample_rate
+    }
+
+    /// The number of samples in every stream, per audio channel.
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// The number of audio channels in streams created with create_source().
+    pub fn num_channels(&self) -> usize {
+        self.num_channels
+    }
+
+    /// Attribute all subsequent create_source()/bind_sink() calls to the named block. This should
+    /// be called by framework code before initializing each block.
+    pub fn set_block(&mut self, name: &str) {
+        self.current_block = name.to_owned();
+    }
+
+    /// Get a stream by name, creating it if it doesn't exist yet.
+    fn get_or_insert(&self, name: &str) -> Stream {
+        self.streams
+            .borrow_mut()
+            .entry(name.to_string())
+            .or_insert_with(|| Rc::new(RefCell::new(vec![ZERO; self.buffer_size])))
+            .clone()
+    }
+
+    /// Create a new stream source with the project's number of audio channels.
+    pub fn create_source(&mut self, name: &str) -> Result<Stream, error::LooperError> {
+        self.create_source_with_channels(name, self.num_channels)
+    }
+
+    /// Create a new stream source with a specific number of audio channels.
+    pub fn create_source_with_channels(
+        &mut self,
+        name: &str,
+        num_channels: usize,
+    ) -> Result<Stream, error::LooperError> {
+        if self.producers.contains_key(name) {
+            println!("Cannot create duplicate stream: {}", name);
+            return Err(error::LooperError::Stream {
+                block: self.current_block.clone(),
+                channel: name.to_string(),
+                cause: "Cannot create duplicate stream".to_string(),
+            });
+        }
+
+        self.producers
+            .insert(name.to_string(), self.current_block.clone());
+
+        // The stream may already have been bound with the default size.
+        let existing = self.streams.borrow().contains_key(name);
+        let stream = self.get_or_insert(name);
+        let size = self.buffer_size * num_channels;
+        if self.defer_resizes && existing {
+            if stream.borrow().len() != size {
+                self.resizes.push((stream.clone(), size));
+            }
+        } else {
+            stream.borrow_mut().resize(size, ZERO);
+        }
+        Ok(stream)
+    }
+
+    /// Leave the size of every existing stream alone until apply_resizes(). This should be called
+    /// on a clone of a catalog whose streams are in use, so that nothing changes for the running
+    /// blocks if creating the new ones fails.
+    pub fn defer_resizes(&mut self) {
+        self.defer_resizes = true;
+    }
+
+    /// Resize the streams whose number of audio channels changed since defer_resizes().
+    pub fn apply_resizes(&mut self) {
+        for (stream, size) in self.resizes.drain(..) {
+            stream.borrow_mut().resize(size, ZERO);
+        }
+        self.defer_resizes = false;
+    }
+
+    /// Forget every stream created or bound by a block, so that the block can be created again.
+    /// The streams themselves are kept, so the blocks connected to them keep working.
+    pub fn remove_block(&mut self, name: &str) {
+        let channels: Vec<String> = self
+            .producers
+            .iter()
+            .filter(|(_, producer)| *producer == name)
+            .map(|(channel, _)| channel.clone())
+            .collect();
+        for channel in &channels {
+            self.producers.remove(channel);
+            self.captured.remove(channel);
+        }
+        self.consumers
+            .borrow_mut()
+            .retain(|(_, consumer)| consumer != name);
+    }
+
+    /// Bind to a sink's outputs. The stream does not need to have been created yet, but it must be
+    /// created by some block before validate() is called.
+    pub fn bind_sink(&self, name: &str) -> Result<Stream, error::LooperError> {
+        self.consumers
+            .borrow_mut()
+            .push((name.to_string(), self.current_block.clone()));
+        Ok(self.get_or_insert(name))
+    }
+
+    /// Verify that every stream which was bound to was also created by some block. The error names
+    /// the first missing stream.
+    pub fn validate(&self) -> Result<(), error::LooperError> {
+        let missing = self.missing();
+        for (channel, consumer) in &missing {
+            println!("Could not find stream: {} (input to \"{}\")", channel, consumer);
+        }
+
+        match missing.into_iter().next() {
+            None => Ok(()),
+            Some((channel, consumer)) => Err(error::LooperError::Stream {
+                block: consumer,
+                channel: channel,
+                cause: "Could not find stream".to_string(),
+            }),
         }
     }
 
-    /// Create a new stream source.
-    pub fn create_source(&mut self, name: &str) -> Result<Stream, ()> {
-        let stream: Stream = Rc::new(RefCell::new([ZERO; SAMPLES_PER_BUFFER]));
-        match self.streams.insert(name.to_string(), stream.clone()) {
-            None => Ok(stream.clone()),
-            Some(_) => {
-                println!("Cannot create duplicate stream: {}", name);
-                Err(())
+    /// Get every (channel, block) pair where the block bound to a stream that was never created.
+    pub fn missing(&self) -> Vec<(String, String)> {
+        self.consumers
+            .borrow()
+            .iter()
+            .filter(|(channel, _)| !self.producers.contains_key(channel))
+            .cloned()
+            .collect()
+    }
+
+    /// Get every (channel, block) pair where the block created a stream that nothing binds to,
+    /// sorted by channel.
+    pub fn unused(&self) -> Vec<(String, String)> {
+        let consumers = self.consumers.borrow();
+        let mut unused: Vec<(String, String)> = self
+            .producers
+            .iter()
+            .filter(|(channel, _)| !consumers.iter().any(|(c, _)| c == *channel))
+            .map(|(channel, producer)| (channel.clone(), producer.clone()))
+            .collect();
+        unused.sort();
+        unused
+    }
+
+    /// Mark a stream as captured from audio hardware, so it arrives late by the hardware latency.
+    /// Blocks which have already lined their output up with the timeline, e.g. a Loop, mark it as
+    /// captured with a latency of 0, so that it isn't compensated again downstream.
+    ///
+    /// # Arguments
+    ///
+    /// * name - The name of the captured stream.
+    /// * latency - The latency of the stream [samples], or None to use the default latency.
+    pub fn set_captured(&mut self, name: &str, latency: Option<usize>) {
+        self.captured.insert(name.to_string(), latency);
+    }
+
+    /// Get the latency of a stream. The latency is 0 until resolve_latencies() is called, so
+    /// blocks should only read it at runtime.
+    pub fn bind_latency(&self, name: &str) -> Latency {
+        self.latencies
+            .borrow_mut()
+            .entry(name.to_string())
+            .or_insert_with(|| Rc::new(Cell::new(0)))
+            .clone()
+    }
+
+    /// Work out the latency of every stream bound with bind_latency(). Streams captured from audio
+    /// hardware have their own latency, and every other stream is as late as the latest stream
+    /// that its producer binds to. This should be called by framework code once every block has
+    /// been created.
+    ///
+    /// # Arguments
+    ///
+    /// * default_latency - The latency of captured streams which don't have their own [samples].
+    pub fn resolve_latencies(&self, default_latency: usize) {
+        for (channel, latency) in self.latencies.borrow().iter() {
+            latency.set(self.latency_of(channel, default_latency, &mut Vec::new()));
+        }
+    }
+
+    /// Get the latency of a stream by walking upstream to the captured streams.
+    fn latency_of(
+        &self,
+        channel: &str,
+        default_latency: usize,
+        visited: &mut Vec<String>,
+    ) -> usize {
+        if let Some(latency) = self.captured.get(channel) {
+            return latency.unwrap_or(default_latency);
+        }
+
+        // Guard against cycles, which are reported elsewhere.
+        let producer = match self.producers.get(channel) {
+            Some(v) if !visited.iter().any(|c| c == channel) => v,
+            _ => return 0,
+        };
+        visited.push(channel.to_string());
+
+        let mut latency = 0;
+        for (input, consumer) in self.consumers.borrow().iter() {
+            if consumer == producer {
+                latency = latency.max(self.latency_of(input, default_latency, visited));
             }
         }
+        latency
     }
 
-    /// Bind to a sink's outputs.
-    pub fn bind_sink(&self, name: &str) -> Result<Stream, ()> {
-        match self.streams.get(name) {
-            Some(stream) => Ok(stream.clone()),
-            None => {
-                println!("Could not find stream: {}", &name);
-                Err(())
+    /// Get all of the links between blocks, in the order the streams were bound. Streams which
+    /// were bound but never created are skipped.
+    pub fn links(&self) -> Vec<Link> {
+        let mut links = Vec::<Link>::new();
+        for (channel, consumer) in self.consumers.borrow().iter() {
+            if let Some(producer) = self.producers.get(channel) {
+                links.push(Link {
+                    channel: channel.clone(),
+                    producer: producer.clone(),
+                    consumer: consumer.clone(),
+                });
             }
         }
+        links
     }
 }
 
@@ -119,20 +489,151 @@ mod tests {
         assert_eq!(stream_bind.borrow()[7], 12 as Sample);
     }
 
+    #[test]
+    fn test_format() {
+        let mut sc = StreamCatalog::with_format(48000, 64, 2);
+        assert_eq!(sc.sample_rate(), 48000);
+        assert_eq!(sc.buffer_size(), 64);
+        assert_eq!(sc.num_channels(), 2);
+
+        // Streams are sized to the buffer size and channel count whether they're created or bound
+        // first.
+        let bound = sc.bind_sink("stream").unwrap();
+        sc.create_source("stream").unwrap();
+        assert_eq!(bound.borrow().len(), 128);
+
+        let bound = sc.bind_sink("mono").unwrap();
+        sc.create_source_with_channels("mono", 1).unwrap();
+        assert_eq!(bound.borrow().len(), 64);
+    }
+
+    #[test]
+    fn test_mix() {
+        // Mono is copied to every channel.
+        let mut stereo = vec![ZERO; 4];
+        mix(&[1.0, 2.0], 1, &mut stereo, 2, &[0.5, 1.0]);
+        assert_eq!(stereo, vec![0.5, 1.0, 1.0, 2.0]);
+
+        // Stereo is averaged down to mono.
+        let mut mono = vec![1.0; 2];
+        mix(&[1.0, 3.0, -1.0, 1.0], 2, &mut mono, 1, &[]);
+        assert_eq!(mono, vec![3.0, 1.0]);
+
+        // Matching channel counts are added as-is.
+        assert_eq!(convert_channels(&[1.0, 2.0], 2, 2), vec![1.0, 2.0]);
+        assert_eq!(convert_channels(&[1.0, 2.0], 1, 2), vec![1.0, 1.0, 2.0, 2.0]);
+    }
+
+    #[test]
+    fn test_mix_into_clip() {
+        let mut clip = RawClip::new();
+
+        // Frames before the start of the clip are dropped.
+        mix_into_clip(&mut clip, 1, -1, &[1.0, 2.0, 3.0], 1, 4);
+        assert_eq!(clip, vec![2.0, 3.0]);
+
+        // Frames past the maximum length are dropped.
+        mix_into_clip(&mut clip, 1, 1, &[1.0, 1.0, 1.0, 1.0], 1, 4);
+        assert_eq!(clip, vec![2.0, 4.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_latency() {
+        let mut sc = StreamCatalog::new();
+        sc.set_block("mic");
+        sc.create_source("mic").unwrap();
+        sc.set_captured("mic", None);
+        sc.set_block("guitar");
+        sc.create_source("guitar").unwrap();
+        sc.set_captured("guitar", Some(300));
+        sc.set_block("metronome");
+        sc.create_source("click").unwrap();
+
+        // The mix is as late as its latest input.
+        sc.set_block("combiner");
+        sc.bind_sink("mic").unwrap();
+        sc.bind_sink("click").unwrap();
+        sc.create_source("mix").unwrap();
+
+        // A loop of the guitar has already been shifted back in time, so a loop of that loop isn't
+        // shifted again.
+        sc.set_block("guitarloop");
+        sc.bind_sink("guitar").unwrap();
+        sc.create_source("guitarloop").unwrap();
+        sc.set_captured("guitarloop", Some(0));
+        sc.set_block("remix");
+        sc.bind_sink("guitarloop").unwrap();
+        sc.create_source("remix").unwrap();
+
+        let mix = sc.bind_latency("mix");
+        let mic = sc.bind_latency("mic");
+        let guitar = sc.bind_latency("guitar");
+        let click = sc.bind_latency("click");
+        let guitarloop = sc.bind_latency("guitarloop");
+        let remix = sc.bind_latency("remix");
+        assert_eq!(mix.get(), 0);
+
+        sc.resolve_latencies(100);
+        assert_eq!(mix.get(), 100);
+        assert_eq!(mic.get(), 100);
+        assert_eq!(guitar.get(), 300);
+        assert_eq!(click.get(), 0);
+        assert_eq!(guitarloop.get(), 0);
+        assert_eq!(remix.get(), 0);
+    }
+
     #[test]
     fn test_bind_without_create() {
         let sc = StreamCatalog::new();
 
-        // Verify that binding to a stream that doesn't exist raises an Err
+        // Verify that binding to a stream that is never created fails validation.
         let name = "stream";
-        match sc.bind_sink(name) {
+        sc.bind_sink(name).unwrap();
+        assert_eq!(sc.missing(), vec![(name.to_owned(), "".to_owned())]);
+        match sc.validate() {
             Ok(_) => {
-                panic!("Stream bind should have failed.")
+                panic!("Stream validation should have failed.")
+            }
+            Err(e) => {
+                assert_eq!(
+                    e,
+                    error::LooperError::Stream {
+                        block: "".to_owned(),
+                        channel: name.to_owned(),
+                        cause: "Could not find stream".to_owned(),
+                    }
+                );
             }
-            Err(_) => {}
         };
     }
 
+    #[test]
+    fn test_bind_before_create() {
+        let mut sc = StreamCatalog::new();
+
+        // Bind to a stream before it is created and verify that both point to the same thing.
+        let name = "stream";
+        sc.set_block("consumer");
+        let stream_bind = sc.bind_sink(name).unwrap();
+        sc.set_block("producer");
+        let stream_create = sc.create_source(name).unwrap();
+        stream_create.borrow_mut()[3] = 5 as Sample;
+        assert_eq!(stream_bind.borrow()[3], 5 as Sample);
+
+        // The link between the two blocks is recorded.
+        sc.validate().unwrap();
+        assert!(sc.missing().is_empty());
+        assert!(sc.unused().is_empty());
+        assert_eq!(
+            sc.links(),
+            vec![Link {
+                channel: name.to_owned(),
+                producer: "producer".to_owned(),
+                consumer: "consumer".to_owned(),
+            }]
+        );
+    }
+
     #[test]
     fn test_double_create() {
         let mut sc = StreamCatalog::new();
@@ -148,16 +649,40 @@ mod tests {
         };
     }
 
+    #[test]
+    fn test_remove_block() {
+        let mut sc = StreamCatalog::new();
+        sc.set_block("producer");
+        let created = sc.create_source("stream").unwrap();
+        sc.set_block("consumer");
+        let bound = sc.bind_sink("stream").unwrap();
+
+        // Once the producer is removed, its stream needs to be created again.
+        sc.remove_block("producer");
+        assert!(sc.validate().is_err());
+
+        // Creating it again hands back the same stream that the consumer is bound to.
+        sc.set_block("producer");
+        let recreated = sc.create_source("stream").unwrap();
+        assert!(Rc::ptr_eq(&created, &recreated));
+        assert!(Rc::ptr_eq(&bound, &recreated));
+        assert!(sc.validate().is_ok());
+
+        // Removing the consumer removes its link.
+        sc.remove_block("consumer");
+        assert!(sc.links().is_empty());
+    }
+
     #[test]
     fn test_scale_stream() {
-        let mut stream: RawStream = [ZERO; SAMPLES_PER_BUFFER];
-        for i in 0..SAMPLES_PER_BUFFER {
+        let mut stream: RawStream = vec![ZERO; DEFAULT_BUFFER_SIZE];
+        for i in 0..DEFAULT_BUFFER_SIZE {
             stream[i] = i as Sample;
         }
         stream.scale(0.5);
 
         // Assert all the values are twice as big now.
-        for i in 0..SAMPLES_PER_BUFFER {
+        for i in 0..DEFAULT_BUFFER_SIZE {
             assert_eq!(stream[i], i as Sample * 0.5);
         }
     }
@@ -6,36 +6,104 @@
 //!     // Play metronome sound.
 //! }
 //! ```
+//!
+//! Time is tracked in samples, and beats don't have to be a whole number of buffers long, so a beat
+//! usually starts somewhere in the middle of a buffer. Blocks which need to play something exactly
+//! on the beat can find out where with beat_offset():
+//! ```
+//! if let Some(offset) = tempo.beat_offset(0) {
+//!     sampler.play_at(&click, false, offset * num_channels);
+//! }
+//! ```
+//!
+//! The bpm counts a configurable note value, the beat_unit, which defaults to a quarter note. Each
+//! beat of the time signature is a 1/beat_duration note, so in 6/8 with a dotted quarter beat_unit,
+//! there are three beats to every tick of the bpm. Blocks which follow the bpm rather than the time
+//! signature, like the metronome, use pulse_offset() instead of beat_offset().
+//!
+//! The tempo can change partway through the song (see config::TempoChange), so the length of a
+//! beat isn't fixed. Every beat up to the end of the last tempo change is looked up in a table of
+//! the samples where they start, and every beat after that has the final tempo. Positions are still
+//! given in measures either way.
+//!
+//! The project, or a single block, can have a groove (see config::Groove), which shifts the
+//! subdivisions of every pulse, e.g. to swing the eighth notes. Beats which fall between pulses,
+//! like the eighth notes of 6/8, follow the project groove too. Pulses and measures never move.
+//!
+//! Tempo also implements config::Timeline, so that segments can be written in beats or seconds:
+//! ```
+//! project.set_timeline(Rc::new(tempo.clone()));
+//! ```
+//!
+//! The time signature can change too (see config::MeterChange). Measures are numbered straight
+//! through every change, so a segment from measure 8 to 10 covers one 4/4 measure and one 7/8
+//! measure if the meter changes at measure 9.
 
 extern crate config;
 extern crate log;
-extern crate stream;
+
+/// A stretch of the song in a single time signature.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Meter {
+    /// The first measure in this time signature.
+    pub measure: i32,
+
+    /// The beat where the first measure starts.
+    pub beat: i32,
+
+    /// The number of beats in each of these measures (top of the time signature).
+    pub beats_per_measure: i32,
+
+    /// The duration of the beat in these measures (bottom of the time signature).
+    pub beat_duration: i32,
+}
 
 /// Struct for managing the tempo of the project and the current state.
+#[derive(Clone)]
 pub struct Tempo {
     // Configuration parameters
-    /// Beats per minute.
+    /// Beats per minute at the start of the song.
     pub bpm: i32,
 
-    /// The number of beats per measure (top of the time signature).
+    /// The number of beats per measure (top of the time signature) at the start of the song.
     pub beats_per_measure: i32,
 
-    /// The duration of the beat (bottom of the time signature).
+    /// The duration of the beat (bottom of the time signature) at the start of the song.
     pub beat_duration: i32,
 
+    /// The note value that the bpm counts, as a fraction of a whole note, e.g. 0.25 for a quarter
+    /// note or 0.375 for a dotted quarter note.
+    pub beat_unit: f64,
+
+    /// The sample rate of the project [samples/s].
+    pub sample_rate: i32,
+
+    /// The number of samples in a single step.
+    pub buffer_size: usize,
+
     // Precomputed constants
-    /// The number of steps in a single beat.
-    pub steps_per_beat: i32,
+    /// The number of samples in a single beat at the start of the song. This usually isn't a
+    /// whole number.
+    pub samples_per_beat: f64,
+
+    /// The number of samples in a measure at the start of the song.
+    pub samples_per_measure: f64,
+
+    /// Every time signature in the song, in order, starting at measure 0.
+    meters: Vec<Meter>,
 
-    /// The number of steps in a single measure.
-    pub steps_per_measure: i32,
+    /// The sample where each beat starts, up to the end of the last tempo or time signature change.
+    beat_samples: Vec<f64>,
 
-    /// The number of samples in a measure.
-    pub samples_per_measure: i32,
+    /// The number of samples in a single beat after the last tempo or time signature change.
+    final_samples_per_beat: f64,
+
+    /// The groove of the whole song, if any.
+    pub groove: Option<config::Groove>,
 
     // Current state
-    /// The current step.
-    pub current_step: i32,
+    /// The current position [samples].
+    pub current_sample: i64,
 
     /// The current beat.
     pub current_beat_i32: i32,
@@ -46,70 +114,224 @@ impl Tempo {
     /// Initialize a new tempo and reset the state to 0.
     pub fn new(project: &config::ProjectConfig) -> Result<Self, String> {
         let tempo = &project.tempo_config;
-        let bpm = match tempo["bpm"].as_i64() {
-            Some(v) => v as i32,
+        let bpm = match project.get_tempo_i32_opt("bpm")? {
+            Some(v) => v,
             None => {
                 println!("\"bpm\" not specified, using default '120'");
                 120
             }
         };
-        let beats_per_measure = match tempo["beats_per_measure"].as_i64() {
-            Some(v) => v as i32,
+        let beats_per_measure = match project.get_tempo_i32_opt("beats_per_measure")? {
+            Some(v) => v,
             None => {
                 println!("\"beats_per_measure\" not specified, using default '4'");
                 4
             }
         };
-        let beat_duration = match tempo["beat_duration"].as_i64() {
-            Some(v) => v as i32,
+        let beat_duration = match project.get_tempo_i32_opt("beat_duration")? {
+            Some(v) => v,
             None => {
                 println!("\"beat_duration\" not specified, using default '4'");
                 4
             }
         };
+        log::abort_if_msg_str!(bpm <= 0, "\"bpm\" must be positive");
+        log::abort_if_msg_str!(beats_per_measure <= 0, "\"beats_per_measure\" must be positive");
+        log::abort_if_msg_str!(beat_duration <= 0, "\"beat_duration\" must be positive");
+
+        let beat_unit = match &tempo["beat_unit"] {
+            v if v.is_badvalue() => 0.25,
+            v => {
+                let unit = match v.as_i64() {
+                    Some(i) => i.to_string(),
+                    None => v.as_str().unwrap_or("").to_owned(),
+                };
+                log::opt_abort_str!(
+                    parse_note_value(&unit),
+                    format!("Invalid \"beat_unit\": \"{}\"", unit)
+                )
+            }
+        };
+
+        let sample_rate = project.sample_rate;
+        let buffer_size = project.buffer_size;
+
+        // Calculate beat and measure widths. A beat is a 1/beat_duration note, and the bpm counts
+        // beat_units.
+        let beat_samples = |bpm: f32, beat_duration: i32| -> f64 {
+            60.0 / bpm as f64 * sample_rate as f64 / (beat_duration as f64 * beat_unit)
+        };
+        let samples_per_beat = beat_samples(bpm as f32, beat_duration);
+        let samples_per_measure = samples_per_beat * beats_per_measure as f64;
 
-        let seconds_per_step = stream::SAMPLES_PER_BUFFER as f32 / stream::SAMPLE_RATE as f32;
-        let seconds_per_beat = 60.0 / bpm as f32;
+        // Work out where every time signature starts.
+        let mut meters = vec![Meter {
+            measure: 0,
+            beat: 0,
+            beats_per_measure: beats_per_measure,
+            beat_duration: beat_duration,
+        }];
+        for change in &project.meter_changes {
+            let previous = meters.last().unwrap();
+            let meter = Meter {
+                measure: change.measure,
+                beat: previous.beat
+                    + (change.measure - previous.measure) * previous.beats_per_measure,
+                beats_per_measure: change.beats_per_measure,
+                beat_duration: change.beat_duration.unwrap_or(previous.beat_duration),
+            };
+            if meter.measure == previous.measure {
+                meters.pop();
+            }
+            meters.push(meter);
+        }
 
-        // Calculate beat and measure widths.
-        let steps_per_beat = (seconds_per_beat / seconds_per_step) as i32;
-        let steps_per_measure = steps_per_beat * beats_per_measure;
-        let samples_per_measure = steps_per_measure * stream::SAMPLES_PER_BUFFER as i32;
+        // Lay out every beat until the tempo and time signature stop changing.
+        let changes = &project.tempo_changes;
+        let last_meter = meters.last().unwrap();
+        let num_beats = match changes.last() {
+            Some(last) => beat_at_measure(&meters, last.measure + last.ramp).ceil() as usize,
+            None => 0,
+        }
+        .max(last_meter.beat as usize);
+        let mut table: Vec<f64> = Vec::with_capacity(num_beats + 1);
+        let mut sample = 0.0;
+        for beat in 0..num_beats {
+            table.push(sample);
+            // Ramps are sampled half way through each beat.
+            let middle = beat as f32 + 0.5;
+            let measure = measure_at_beat(&meters, middle);
+            let beat_duration = meter_at_beat(&meters, middle).beat_duration;
+            sample += beat_samples(bpm_at(bpm as f32, changes, measure), beat_duration);
+        }
+        table.push(sample);
+        let final_bpm = changes.last().map_or(bpm as f32, |last| last.bpm);
+        let final_samples_per_beat = beat_samples(final_bpm, last_meter.beat_duration);
 
         Ok(Tempo {
             bpm: bpm,
             beats_per_measure: beats_per_measure,
             beat_duration: beat_duration,
+            beat_unit: beat_unit,
+            sample_rate: sample_rate,
+            buffer_size: buffer_size,
 
-            steps_per_beat: steps_per_beat,
-            steps_per_measure: steps_per_measure,
+            samples_per_beat: samples_per_beat,
             samples_per_measure: samples_per_measure,
+            meters: meters,
+            beat_samples: table,
+            final_samples_per_beat: final_samples_per_beat,
+            groove: project.groove.clone(),
 
-            current_step: 0,
+            current_sample: 0,
             current_beat_i32: 0,
             current_beat_f32: 0.0,
         })
     }
 
-    /// Step the tempo forward.
+    /// Step the tempo forward by a number of buffers.
     ///
     /// DANGER: This should only be called by framework code, not by any blocks.
     /// Blocks should not have a mutable reference to tempo, so this shouldn't be possible.
     pub fn step(&mut self, count: i32) {
-        self.current_step += count as i32;
-        self.current_beat_i32 = self.current_step / self.steps_per_beat;
-        self.current_beat_f32 = self.current_step as f32 / self.steps_per_beat as f32;
+        self.current_sample += count as i64 * self.buffer_size as i64;
+        self.update_beat();
     }
 
     /// Skip a number of measures forward.
     pub fn skip(&mut self, num_measures: f32) {
-        let num_chunks = (self.steps_per_measure as f32 * num_measures) as i32;
-        self.step(num_chunks);
+        self.seek(self.current_measure() + num_measures);
+    }
+
+    /// Move the playhead to a measure. The playhead lands on the nearest sample, which doesn't have
+    /// to be the start of a buffer.
+    ///
+    /// DANGER: This should only be called by framework code, not by any blocks. Blocks are told
+    /// about the jump through their seek() hook.
+    pub fn seek(&mut self, measure: f32) {
+        self.current_sample = self.sample_at_measure(measure).round() as i64;
+        self.update_beat();
+    }
+
+    /// Work out the current beat from the current sample.
+    fn update_beat(&mut self) {
+        let sample = self.current_sample as f64;
+        self.current_beat_i32 = self.beat_at_sample(sample);
+        self.current_beat_f32 = self.beat_position(sample) as f32;
+    }
+
+    /// The sample where a beat starts. Beats before the start of the song have the initial tempo.
+    fn beat_start(&self, beat: i32) -> f64 {
+        let last = self.beat_samples.len() as i32 - 1;
+        if beat < 0 {
+            return beat as f64 * self.samples_per_beat;
+        }
+        match beat <= last {
+            true => self.beat_samples[beat as usize],
+            false => {
+                self.beat_samples[last as usize]
+                    + (beat - last) as f64 * self.final_samples_per_beat
+            }
+        }
+    }
+
+    /// The beat containing a sample.
+    fn beat_at_sample(&self, sample: f64) -> i32 {
+        let last = self.beat_samples.len() as i32 - 1;
+        let last_sample = self.beat_samples[last as usize];
+        if sample < 0.0 {
+            (sample / self.samples_per_beat).floor() as i32
+        } else if sample >= last_sample {
+            last + ((sample - last_sample) / self.final_samples_per_beat).floor() as i32
+        } else {
+            self.beat_samples.partition_point(|&s| s <= sample) as i32 - 1
+        }
+    }
+
+    /// The position of a sample in the song [beats].
+    fn beat_position(&self, sample: f64) -> f64 {
+        let beat = self.beat_at_sample(sample);
+        let start = self.beat_start(beat);
+        let length = self.beat_start(beat + 1) - start;
+        beat as f64 + (sample - start) / length
+    }
+
+    /// The sample at a position in the song [beats].
+    fn sample_at_beat(&self, beats: f64) -> f64 {
+        let beat = beats.floor() as i32;
+        let start = self.beat_start(beat);
+        let length = self.beat_start(beat + 1) - start;
+        start + (beats - beat as f64) * length
+    }
+
+    /// The sample at a position in the song [measures].
+    fn sample_at_measure(&self, measure: f32) -> f64 {
+        self.sample_at_beat(beat_at_measure(&self.meters, measure) as f64)
+    }
+
+    /// The sample where a position in the song lands [measures], rounded to the nearest sample.
+    pub fn measure_sample(&self, measure: f32) -> i64 {
+        self.sample_at_measure(measure).round() as i64
+    }
+
+    /// The number of samples between two positions in the song [measures].
+    pub fn samples_between(&self, m1: f32, m2: f32) -> usize {
+        (self.measure_sample(m2) - self.measure_sample(m1)).max(0) as usize
     }
 
     /// The current measure as a float since the beginning of the song.
     pub fn current_measure(&self) -> f32 {
-        return self.current_beat_f32 / self.beats_per_measure as f32;
+        return measure_at_beat(&self.meters, self.current_beat_f32);
+    }
+
+    /// The time signature at the current position.
+    pub fn current_meter(&self) -> &Meter {
+        meter_at_beat(&self.meters, self.current_beat_f32)
+    }
+
+    /// The number of samples in the current beat.
+    pub fn current_samples_per_beat(&self) -> f64 {
+        self.beat_start(self.current_beat_i32 + 1) - self.beat_start(self.current_beat_i32)
     }
 
     /// Whether the current time is in the provided measure. This method uses floating point
@@ -127,15 +349,239 @@ impl Tempo {
         return m1 <= m && m < m2;
     }
 
-    /// Returns true if this chunk lands on the start of a beat.
+    /// The next tick of a grid which starts in this chunk, and how far into the chunk it starts
+    /// [frames]. The grid starts over at the start of every measure, and a tick starts on the first
+    /// sample at or after its exact (fractional) position once the groove has shifted it.
+    ///
+    /// # Arguments
+    ///
+    /// * step_offset - A +/- offset to apply when calculating the window [buffers].
+    /// * spacing - The distance between ticks in a time signature [beats].
+    /// * groove - The groove to shift the ticks by, if any.
+    fn next_tick(
+        &self,
+        step_offset: i32,
+        spacing: impl Fn(&Meter) -> f64,
+        groove: Option<&config::Groove>,
+    ) -> Option<(f64, usize)> {
+        let start = self.current_sample + step_offset as i64 * self.buffer_size as i64;
+        if start < 0 {
+            return None;
+        }
+
+        // A groove never moves a tick out of its measure, so start looking from the start of the
+        // measure containing the last sample of the previous chunk. The ticks stay in order, so
+        // the first one at or after the start of the chunk is the next one.
+        let beat = self.beat_position(start as f64 - 1.0);
+        let meter = meter_at_beat(&self.meters, beat as f32);
+        let length = meter.beats_per_measure as f64;
+        let mut measure_start =
+            meter.beat as f64 + ((beat - meter.beat as f64) / length).floor() * length;
+        loop {
+            let meter = meter_at_beat(&self.meters, measure_start as f32);
+            let length = meter.beats_per_measure as f64;
+            let pulse = self.beat_unit * meter.beat_duration as f64;
+            let spacing = spacing(meter);
+            let num_ticks = (length / spacing - 1e-9).ceil() as usize;
+            for i in 0..num_ticks {
+                let tick = i as f64 * spacing;
+                let shifted = match groove {
+                    Some(groove) => groove_position(tick, pulse, length, groove),
+                    None => tick,
+                };
+                let offset = self.sample_at_beat(measure_start + shifted).ceil() as i64 - start;
+                if offset >= self.buffer_size as i64 {
+                    return None;
+                }
+                if offset >= 0 {
+                    return Some((measure_start + tick, offset as usize));
+                }
+            }
+            measure_start += length;
+        }
+    }
+
+    /// The next beat which starts in this chunk, and how far into the chunk it starts [frames].
+    ///
+    /// # Arguments
+    ///
+    /// * step_offset - A +/- offset to apply when calculating the window [buffers].
+    fn next_beat(&self, step_offset: i32) -> Option<(i32, usize)> {
+        self.next_tick(step_offset, |_| 1.0, self.groove.as_ref())
+            .map(|(beat, offset)| (beat.round() as i32, offset))
+    }
+
+    /// If a beat starts in this chunk, how far into the chunk it starts [frames].
+    ///
+    /// # Arguments
+    ///
+    /// * step_offset - A +/- offset to apply when calculating the window. This
+    ///                 is typically used to compensate for latency.
+    pub fn beat_offset(&self, step_offset: i32) -> Option<usize> {
+        self.next_beat(step_offset).map(|(_, offset)| offset)
+    }
+
+    /// If a measure starts in this chunk, how far into the chunk it starts [frames].
+    ///
+    /// # Arguments
+    ///
+    /// * step_offset - A +/- offset to apply when calculating the window.
+    pub fn measure_offset(&self, step_offset: i32) -> Option<usize> {
+        let (beat, offset) = self.next_beat(step_offset)?;
+        let meter = meter_at_beat(&self.meters, beat as f32);
+        match (beat - meter.beat) % meter.beats_per_measure == 0 {
+            true => Some(offset),
+            false => None,
+        }
+    }
+
+    /// If a pulse of the bpm, or a subdivision of one, starts in this chunk, how far into the chunk
+    /// it starts [frames]. The pulses start over at the start of every measure, so a measure which
+    /// isn't a whole number of pulses long, e.g. 7/8 with a quarter note beat_unit, ends with a
+    /// short pulse. The subdivisions follow the project groove.
+    ///
+    /// # Arguments
+    ///
+    /// * step_offset - A +/- offset to apply when calculating the window.
+    /// * subdivisions - The number of evenly spaced ticks in each pulse.
+    pub fn pulse_offset(&self, step_offset: i32, subdivisions: u32) -> Option<usize> {
+        self.next_pulse(step_offset, subdivisions, self.groove.as_ref())
+    }
+
+    /// Same as pulse_offset(), but the subdivisions follow a groove of their own instead of the
+    /// project groove.
+    ///
+    /// # Arguments
+    ///
+    /// * step_offset - A +/- offset to apply when calculating the window.
+    /// * subdivisions - The number of evenly spaced ticks in each pulse.
+    /// * groove - The groove to shift the subdivisions by.
+    pub fn grooved_pulse_offset(
+        &self,
+        step_offset: i32,
+        subdivisions: u32,
+        groove: &config::Groove,
+    ) -> Option<usize> {
+        self.next_pulse(step_offset, subdivisions, Some(groove))
+    }
+
+    fn next_pulse(
+        &self,
+        step_offset: i32,
+        subdivisions: u32,
+        groove: Option<&config::Groove>,
+    ) -> Option<usize> {
+        let spacing = |meter: &Meter| {
+            self.beat_unit * meter.beat_duration as f64 / subdivisions.max(1) as f64
+        };
+        self.next_tick(step_offset, spacing, groove)
+            .map(|(_, offset)| offset)
+    }
+
+    /// Returns true if a measure starts in this chunk.
+    pub fn on_measure(&self) -> bool {
+        return self.measure_offset(0).is_some();
+    }
+
+    /// Returns true if a beat starts in this chunk.
     ///
     /// # Arguments
     ///
     /// * step_offset - A +/- offset to apply when calculating the window. This
     ///                 is typically used to compensate for latency.
     pub fn on_beat(&self, step_offset: i32) -> bool {
-        return (self.current_step + step_offset) % self.steps_per_beat == 0;
+        return self.beat_offset(step_offset).is_some();
+    }
+}
+
+impl config::Timeline for Tempo {
+    fn measure_at_beat(&self, beat: f64) -> f32 {
+        measure_at_beat(&self.meters, beat as f32)
+    }
+
+    fn beat_at_measure(&self, measure: f32) -> f64 {
+        beat_at_measure(&self.meters, measure) as f64
+    }
+
+    fn measure_at_seconds(&self, seconds: f64) -> f32 {
+        let beat = self.beat_position(seconds * self.sample_rate as f64);
+        measure_at_beat(&self.meters, beat as f32)
+    }
+}
+
+/// Parse a note value as a fraction of a whole note, e.g. "4" for a quarter note, or "4." for a
+/// dotted quarter note.
+pub fn parse_note_value(s: &str) -> Option<f64> {
+    let (value, dotted) = match s.strip_suffix('.') {
+        Some(v) => (v, true),
+        None => (s, false),
+    };
+    let value = value.parse::<u32>().ok().filter(|v| *v > 0)?;
+    let fraction = 1.0 / value as f64;
+    Some(if dotted { fraction * 1.5 } else { fraction })
+}
+
+/// Where a groove shifts a tick to, relative to the start of its measure [beats]. Each pulse is
+/// split into as many subdivisions as the groove has offsets, and everything in between is
+/// stretched to fit, so ticks never pass each other.
+///
+/// # Arguments
+///
+/// * tick - The position of the tick in the measure [beats].
+/// * pulse - The length of a pulse [beats].
+/// * length - The length of the measure [beats].
+/// * groove - The groove.
+fn groove_position(tick: f64, pulse: f64, length: f64, groove: &config::Groove) -> f64 {
+    let n = groove.offsets.len();
+    let pulses = tick / pulse;
+    let whole = pulses.floor();
+    let slot = (pulses - whole) * n as f64;
+    let j = (slot.floor() as usize).min(n - 1);
+    let from = j as f64 + groove.offsets[j] as f64;
+    let to = (j + 1) as f64 + groove.offsets.get(j + 1).map_or(0.0, |o| *o as f64);
+    let position = from + (slot - j as f64) * (to - from);
+    ((whole + position / n as f64) * pulse).min(length)
+}
+
+/// The time signature in effect at a beat.
+fn meter_at_beat(meters: &[Meter], beat: f32) -> &Meter {
+    let i = meters.partition_point(|m| m.beat as f32 <= beat);
+    &meters[i.max(1) - 1]
+}
+
+/// The position of a beat in the song [measures].
+fn measure_at_beat(meters: &[Meter], beat: f32) -> f32 {
+    let meter = meter_at_beat(meters, beat);
+    meter.measure as f32 + (beat - meter.beat as f32) / meter.beats_per_measure as f32
+}
+
+/// The beat at a position in the song [measures].
+fn beat_at_measure(meters: &[Meter], measure: f32) -> f32 {
+    let i = meters.partition_point(|m| m.measure as f32 <= measure);
+    let meter = &meters[i.max(1) - 1];
+    meter.beat as f32 + (measure - meter.measure as f32) * meter.beats_per_measure as f32
+}
+
+/// The tempo at a position in the song [beats/min].
+///
+/// # Arguments
+///
+/// * bpm - The tempo at the start of the song.
+/// * changes - The tempo changes, in order.
+/// * measure - The position in the song.
+pub fn bpm_at(bpm: f32, changes: &[config::TempoChange], measure: f32) -> f32 {
+    let mut current = bpm;
+    for change in changes {
+        if measure < change.measure {
+            break;
+        }
+        if measure < change.measure + change.ramp {
+            let progress = (measure - change.measure) / change.ramp;
+            return current + (change.bpm - current) * progress;
+        }
+        current = change.bpm;
     }
+    current
 }
 
 #[cfg(test)]
@@ -152,28 +598,68 @@ mod tests {
         assert!(tempo.beats_per_measure == 3);
         assert!(tempo.beat_duration == 4);
 
-        // This will break if sample rate or samples per buffer ever change.
-        let steps_per_beat: i32 = 103;
-        let steps_per_measure: i32 = 103 * 3;
-        assert!(tempo.steps_per_beat == steps_per_beat);
-        assert!(tempo.steps_per_measure == steps_per_measure);
+        // 100bpm at 44.1kHz => 26460 samples per beat, which is 103.36 buffers of 256 samples.
+        assert_eq!(tempo.samples_per_beat, 26460.0);
+        assert_eq!(tempo.samples_per_measure, 26460.0 * 3.0);
         assert!(tempo.current_beat_i32 == 0);
         assert!(tempo.current_beat_f32 == 0.0);
 
-        // Step forward one beat.
-        tempo.step(steps_per_beat);
+        // The first beat ends partway through the 104th buffer.
+        tempo.step(103);
+        assert!(tempo.current_beat_i32 == 0);
+        tempo.step(1);
         assert!(tempo.current_beat_i32 == 1);
-        log::assert_approx_eq!(tempo.current_measure(), 0.3333333, 0.00001);
+        log::assert_approx_eq!(tempo.current_measure(), (104.0 * 256.0 / 26460.0) / 3.0, 0.00001);
+
+        // The fractions don't add up to any drift, however long the song is.
+        tempo.seek(0.0);
+        tempo.step(103359);
+        assert_eq!(tempo.current_beat_i32, 999);
+        tempo.step(1);
+        assert_eq!(tempo.current_beat_i32, 1000);
+        log::assert_approx_eq!(tempo.current_measure(), (103360.0 * 256.0 / 26460.0) / 3.0, 0.0001);
+    }
 
-        // Step forward one beat.
-        tempo.step(steps_per_beat);
-        assert!(tempo.current_beat_i32 == 2);
-        log::assert_approx_eq!(tempo.current_measure(), 0.6666666, 0.00001);
+    #[test]
+    fn test_format() {
+        let project = config::ProjectConfig::new("dat/tempo/format.yaml").unwrap();
+        let tempo = Tempo::new(&project).unwrap();
+
+        // 100bpm at 48kHz => 0.6s * 48000 = 28800 samples per beat.
+        assert_eq!(tempo.sample_rate, 48000);
+        assert_eq!(tempo.buffer_size, 64);
+        assert_eq!(tempo.samples_per_beat, 28800.0);
+        assert_eq!(tempo.samples_per_measure, 28800.0 * 3.0);
+    }
 
-        // Step forward one beat.
-        tempo.step(steps_per_beat);
-        assert!(tempo.current_beat_i32 == 3);
-        log::assert_approx_eq!(tempo.current_measure(), 1.0, 0.0000001);
+    #[test]
+    fn test_expressions() {
+        let project = config::ProjectConfig::new("dat/tempo/expressions.yaml").unwrap();
+        let tempo = Tempo::new(&project).unwrap();
+        assert_eq!(tempo.bpm, 100);
+        assert_eq!(tempo.beats_per_measure, 6);
+        assert_eq!(tempo.beat_duration, 8);
+
+        // A measure with no beats would never end.
+        let project = config::ProjectConfig::new("dat/tempo/zero_beats.yaml").unwrap();
+        assert!(Tempo::new(&project).is_err());
+    }
+
+    #[test]
+    fn test_seek() {
+        let project = config::ProjectConfig::new("dat/tempo/tempo.yaml").unwrap();
+        let mut tempo = Tempo::new(&project).unwrap();
+
+        // Seeking is absolute, whether it jumps forward or back.
+        tempo.step(10);
+        tempo.seek(2.0);
+        assert_eq!(tempo.current_sample, 26460 * 3 * 2);
+        assert_eq!(tempo.current_beat_i32, 6);
+        assert_eq!(tempo.beat_offset(0), Some(0));
+
+        tempo.seek(1.0);
+        assert_eq!(tempo.current_sample, 26460 * 3);
+        assert_eq!(tempo.current_beat_i32, 3);
     }
 
     #[test]
@@ -185,15 +671,18 @@ mod tests {
         assert!(tempo.current_beat_f32 == 0.0);
         assert!(!tempo.in_measure(1.0, 2.0));
 
-        let steps_per_measure: i32 = 103 * 3;
+        // Measure 1 starts 20 samples into the 311th buffer.
+        tempo.step(310);
+        assert!(!tempo.in_measure(1.0, 2.0));
+        assert_eq!(tempo.measure_offset(0), Some(20));
 
         // Now we're in the measure.
-        tempo.step(steps_per_measure);
+        tempo.step(1);
         assert!(tempo.current_beat_i32 == 3);
         assert!(tempo.in_measure(1.0, 2.0));
 
         // And now we're not.
-        tempo.step(steps_per_measure);
+        tempo.step(310);
         assert!(tempo.current_beat_i32 == 6);
         assert!(!tempo.in_measure(1.0, 2.0));
     }
@@ -205,18 +694,231 @@ mod tests {
 
         // on_beat should fire on the first beat.
         assert!(tempo.on_beat(0));
+        assert_eq!(tempo.beat_offset(0), Some(0));
 
-        let mut beat_count: i32 = 0;
-        while beat_count < 3 {
-            // on_beat should return true whenever current_beat increments by 1.
-            if tempo.current_beat_i32 == beat_count {
+        // Every beat after that starts partway through a buffer.
+        let mut offsets = Vec::new();
+        for step in 0..310 {
+            if let Some(offset) = tempo.beat_offset(0) {
+                offsets.push((step, offset));
                 assert!(tempo.on_beat(0));
-                beat_count += 1;
             } else {
                 assert!(!tempo.on_beat(0));
             }
+            tempo.step(1);
+        }
+        assert_eq!(offsets, vec![(0, 0), (103, 26460 - 103 * 256), (206, 52920 - 206 * 256)]);
+
+        // The offset applies to an earlier or later buffer too.
+        tempo.seek(0.0);
+        tempo.step(102);
+        assert!(!tempo.on_beat(0));
+        assert_eq!(tempo.beat_offset(1), Some(92));
+        assert_eq!(tempo.measure_offset(1), None);
+    }
+
+    #[test]
+    fn test_tempo_changes() {
+        let project = config::ProjectConfig::new("dat/tempo/tempo_changes.yaml").unwrap();
+        let mut tempo = Tempo::new(&project).unwrap();
+
+        // 120bpm at 48kHz => 24000 samples per beat, and 48000 at 60bpm.
+        assert_eq!(tempo.samples_per_beat, 24000.0);
+        tempo.seek(2.0);
+        assert_eq!(tempo.current_sample, 24000 * 4);
+        assert!(tempo.on_measure());
+        tempo.seek(4.0);
+        assert_eq!(tempo.current_sample, 24000 * 4 + 48000 * 4);
+        assert!(tempo.on_measure());
+
+        // Half way through a half time beat.
+        tempo.seek(2.25);
+        assert_eq!(tempo.current_sample, 24000 * 4 + 24000);
+        assert_eq!(tempo.current_measure(), 2.25);
+        assert!(!tempo.on_beat(0));
+        assert!(tempo.on_beat(24000 / 64));
+
+        // The ramp speeds up every beat, until it settles at 140bpm.
+        let mut previous = f64::MAX;
+        for beat in 8..16 {
+            let start = tempo.beat_start(beat);
+            let length = tempo.beat_start(beat + 1) - start;
+            assert!(length <= previous + 0.001);
+            previous = length;
+        }
+        assert!(tempo.beat_start(9) - tempo.beat_start(8) < 48000.0);
+        log::assert_approx_eq!(previous, 60.0 / 140.0 * 48000.0, 0.001);
+
+        // Measures stay in step when stepping through the changes one buffer at a time, up to the
+        // last buffer before the end.
+        tempo.seek(0.0);
+        let mut measures = 0;
+        let mut beats = 0;
+        for _ in 0..tempo.samples_between(0.0, 8.0) / tempo.buffer_size {
+            if tempo.on_beat(0) {
+                beats += 1;
+            }
+            if tempo.on_measure() {
+                log::assert_approx_eq!(tempo.current_measure(), measures as f32, 0.01);
+                measures += 1;
+            }
+            tempo.step(1);
+        }
+        assert_eq!(measures, 8);
+        assert_eq!(beats, 16);
+
+        // Samples between two positions account for the change.
+        assert_eq!(tempo.samples_between(1.0, 3.0), 24000 * 2 + 48000 * 2);
+        assert_eq!(tempo.samples_between(3.0, 1.0), 0);
+    }
+
+    #[test]
+    fn test_bpm_at() {
+        let project = config::ProjectConfig::new("dat/tempo/tempo_changes.yaml").unwrap();
+        let changes = &project.tempo_changes;
+        assert_eq!(bpm_at(120.0, changes, 1.9), 120.0);
+        assert_eq!(bpm_at(120.0, changes, 2.0), 60.0);
+        assert_eq!(bpm_at(120.0, changes, 4.0), 60.0);
+        assert_eq!(bpm_at(120.0, changes, 5.0), 100.0);
+        assert_eq!(bpm_at(120.0, changes, 6.0), 140.0);
+        assert_eq!(bpm_at(120.0, changes, 100.0), 140.0);
+    }
+
+    #[test]
+    fn test_meter_changes() {
+        let project = config::ProjectConfig::new("dat/tempo/meter_changes.yaml").unwrap();
+        let mut tempo = Tempo::new(&project).unwrap();
+
+        // Two measures of 4/4, one of 7/8, then back to 4/4. Eighth notes are half as long.
+        tempo.seek(3.0);
+        assert_eq!(tempo.current_beat_i32, 4 + 4 + 7);
+        assert_eq!(tempo.current_sample, 24000 * 8 + 12000 * 7);
+        assert_eq!(tempo.current_meter().beats_per_measure, 4);
+
+        tempo.seek(2.5);
+        assert_eq!(tempo.current_beat_f32, 4.0 + 4.0 + 3.5);
+        assert_eq!(tempo.current_meter().beats_per_measure, 7);
+        assert_eq!(tempo.current_meter().beat_duration, 8);
+
+        // Measure numbering carries straight on through the changes.
+        tempo.seek(0.0);
+        let mut measures = 0;
+        let mut beats = 0;
+        for _ in 0..tempo.samples_between(0.0, 4.0) / tempo.buffer_size {
+            if tempo.on_beat(0) {
+                beats += 1;
+            }
+            if tempo.on_measure() {
+                log::assert_approx_eq!(tempo.current_measure(), measures as f32, 0.01);
+                measures += 1;
+            }
+            tempo.step(1);
+        }
+        assert_eq!(measures, 4);
+        assert_eq!(beats, 4 + 4 + 7 + 4);
+        assert_eq!(tempo.samples_between(2.0, 3.0), 12000 * 7);
+
+        // Quarter note pulses start over at every measure, so the 7/8 measure ends with a short one.
+        tempo.seek(2.0);
+        let mut pulses = Vec::new();
+        for step in 0..tempo.samples_between(2.0, 3.0) / tempo.buffer_size {
+            if let Some(offset) = tempo.pulse_offset(0, 1) {
+                pulses.push(step * tempo.buffer_size + offset);
+            }
+            tempo.step(1);
+        }
+        assert_eq!(pulses, vec![0, 24000, 48000, 72000]);
+    }
+
+    #[test]
+    fn test_beat_unit() {
+        let project = config::ProjectConfig::new("dat/tempo/compound.yaml").unwrap();
+        let mut tempo = Tempo::new(&project).unwrap();
 
+        // 6/8 at 120 dotted quarter notes per minute => 0.5s per dotted quarter, 3 eighths each.
+        assert_eq!(tempo.beat_unit, 0.375);
+        assert_eq!(tempo.samples_per_beat, 8000.0);
+        assert_eq!(tempo.samples_per_measure, 48000.0);
+
+        // The metronome can click on the pulse or on every eighth note.
+        let mut pulses = 0;
+        let mut subdivisions = 0;
+        let mut beats = 0;
+        for _ in 0..(48000 * 2) / tempo.buffer_size {
+            pulses += tempo.pulse_offset(0, 1).is_some() as i32;
+            subdivisions += tempo.pulse_offset(0, 3).is_some() as i32;
+            beats += tempo.on_beat(0) as i32;
             tempo.step(1);
         }
+        assert_eq!(pulses, 4);
+        assert_eq!(subdivisions, 12);
+        assert_eq!(beats, 12);
+
+        assert_eq!(parse_note_value("4"), Some(0.25));
+        assert_eq!(parse_note_value("2."), Some(0.75));
+        assert_eq!(parse_note_value("0"), None);
+        assert_eq!(parse_note_value("quarter"), None);
+    }
+
+    #[test]
+    fn test_swing() {
+        let project = config::ProjectConfig::new("dat/tempo/swing.yaml").unwrap();
+        let mut tempo = Tempo::new(&project).unwrap();
+        let shuffle = config::Groove {
+            offsets: vec![0.0, 0.5, 0.25],
+        };
+
+        // Where every tick of a measure lands [samples].
+        let mut beats = vec![];
+        let mut eighths = vec![];
+        let mut sixteenths = vec![];
+        let mut shuffled = vec![];
+        for _ in 0..96000 / tempo.buffer_size {
+            let sample = tempo.current_sample as usize;
+            beats.extend(tempo.beat_offset(0).map(|offset| sample + offset));
+            eighths.extend(tempo.pulse_offset(0, 2).map(|offset| sample + offset));
+            sixteenths.extend(tempo.pulse_offset(0, 4).map(|offset| sample + offset));
+            shuffled.extend(
+                tempo
+                    .grooved_pulse_offset(0, 3, &shuffle)
+                    .map(|offset| sample + offset),
+            );
+            tempo.step(1);
+        }
+
+        // 120bpm at 48kHz => 24000 samples per beat. The beats stay put, but every second eighth
+        // note is half an eighth late, and the sixteenths in between are stretched to fit.
+        assert_eq!(beats, vec![0, 24000, 48000, 72000]);
+        assert_eq!(eighths, vec![0, 18000, 24000, 42000, 48000, 66000, 72000, 90000]);
+        assert_eq!(&sixteenths[..4], &[0, 9000, 18000, 21000]);
+        assert_eq!(&shuffled[..4], &[0, 12000, 18000, 24000]);
+
+        // A short pulse at the end of a measure can't swing past the end of it.
+        let swing = config::Groove::swing(0.5);
+        assert_eq!(groove_position(3.0, 2.0, 3.5, &swing), 3.5);
+        assert_eq!(groove_position(6.0, 2.0, 7.0, &swing), 6.0);
+    }
+
+    #[test]
+    fn test_segment_times() {
+        let project = config::ProjectConfig::new("dat/tempo/segment_times.yaml").unwrap();
+        let blocks = &project.blocks;
+
+        // Musical times can't be resolved until the tempo is known.
+        assert!(blocks[0].get_segments().is_err());
+        let tempo = Tempo::new(&project).unwrap();
+        project.set_timeline(std::rc::Rc::new(tempo));
+
+        // 4/4 until measure 2, then 3/4, at 120bpm => 0.5s per beat.
+        let segments = blocks[0].get_segments().unwrap();
+        let times: Vec<(f32, f32)> = segments.iter().map(|s| (s.start, s.stop)).collect();
+        assert_eq!(times[0], (1.5, 2.5));
+        assert_eq!(times[1], (3.0, 4.0));
+        assert_eq!(times[2].0, 1.5);
+        log::assert_approx_eq!(times[2].1, 2.0 + 2.0 / 3.0, 0.00001);
+
+        assert!(blocks[1].get_segments().is_err());
+        assert!(blocks[2].get_segments().is_err());
+        assert!(blocks[3].get_segments().is_err());
     }
 }
@@ -0,0 +1,89 @@
+//! Blocks for testing framework code.
+//!
+//! These blocks create and bind the channels named in their config and do nothing else, so tests
+//! can wire up projects without any audio. Every block logs its name when it's created, so tests
+//! can tell which blocks were built.
+//!
+//! ```
+//! let created = test_blocks::Created::default();
+//! let registry = test_blocks::registry(&created);
+//! ```
+//!
+//! TestSource \[Source\]:
+//!     Required parameters:
+//!         output_channel: The channel to create.
+//!     Optional parameters:
+//!         num_channels: The number of audio channels in the output. Defaults to the project's.
+//!         segments: Parsed, but otherwise ignored.
+//!
+//! TestTransformer \[Transformer\]:
+//!     Required parameters:
+//!         input_channel: The channel to bind.
+//!         output_channel: The channel to create.
+//!
+//! TestSink \[Sink\]:
+//!     Required parameters:
+//!         input_channel: The channel to bind.
+
+extern crate block;
+extern crate config;
+extern crate registry;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// The names of every block created, in order.
+pub type Created = Rc<RefCell<Vec<String>>>;
+
+pub struct TestSource {}
+
+impl block::Source for TestSource {
+    fn read(&mut self, _: &block::PlaybackState) {}
+}
+
+pub struct TestTransformer {}
+
+impl block::Transformer for TestTransformer {
+    fn transform(&mut self, _: &block::PlaybackState) {}
+}
+
+pub struct TestSink {}
+
+impl block::Sink for TestSink {
+    fn write(&mut self, _: &block::PlaybackState) {}
+}
+
+/// Create a registry of the test blocks.
+///
+/// # Arguments
+///
+/// * created - Where to log the name of every block created.
+pub fn registry(created: &Created) -> registry::Registry {
+    let mut registry = registry::Registry::new();
+    let log = created.clone();
+    registry.register_source("TestSource", move |config, stream_catalog| {
+        let num_channels =
+            config.get_i32_opt("num_channels", &(stream_catalog.num_channels() as i32))?;
+        stream_catalog.create_source_with_channels(
+            config.get_str("output_channel")?,
+            num_channels as usize,
+        )?;
+        config.get_segments()?;
+        log.borrow_mut().push(config.name.clone());
+        Ok(TestSource {})
+    });
+    let log = created.clone();
+    registry.register_transformer("TestTransformer", move |config, stream_catalog| {
+        stream_catalog.bind_sink(config.get_str("input_channel")?)?;
+        stream_catalog.create_source(config.get_str("output_channel")?)?;
+        log.borrow_mut().push(config.name.clone());
+        Ok(TestTransformer {})
+    });
+    let log = created.clone();
+    registry.register_sink("TestSink", move |config, stream_catalog| {
+        stream_catalog.bind_sink(config.get_str("input_channel")?)?;
+        log.borrow_mut().push(config.name.clone());
+        Ok(TestSink {})
+    });
+    registry
+}
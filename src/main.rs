@@ -1,25 +1,256 @@
+extern crate backend;
+extern crate check;
+extern crate config;
+extern crate dot;
+extern crate error;
 extern crate runner;
+extern crate tempo;
 
 use std::env;
+use std::process;
+use std::rc::Rc;
 
-fn run(filename: &str) -> Result<(), String> {
+fn help() -> process::ExitCode {
+    println!("usage:");
+    println!("$ looper <file.yaml>");
+    println!("$ looper --render <out.wav> <file.yaml>");
+    println!("$ looper --audio-backend <null|null:paced|file> <file.yaml>");
+    println!("$ looper --practice-loop <start>:<stop>[:<repeats>] <file.yaml>");
+    println!("$ looper --profile <out.json> <file.yaml>");
+    println!("$ looper --count-in <measures> <file.yaml>");
+    println!("$ looper check <file.yaml>");
+    println!("$ looper calibrate <file.yaml>");
+    println!("$ looper graph <file.yaml> <out.dot> [<arrangement.csv>]");
+    println!("$ looper --list-blocks");
+    println!();
+    println!("commands:");
+    println!("    check                Report every problem with the project without playing it");
+    println!("    calibrate            Measure the audio latency between the first AudioSink and");
+    println!("                         the first AudioSource by playing clicks");
+    println!("    graph                Export the block graph in DOT format, and optionally the");
+    println!("                         segment timing of every block in CSV format");
+    println!();
+    println!("options:");
+    println!("    --render <out.wav>   Render the project offline to a wav file");
+    println!("    --audio-backend <b>  Use the given audio backend for every audio device");
+    println!("    --practice-loop <l>  Repeat measures <start> up to <stop>, <repeats> times in");
+    println!("                         total or forever if omitted");
+    println!("    --profile <out.json> Write the per-block profiling report to a JSON file");
+    println!("    --count-in <n>       Count in <n> measures of clicks before playback starts");
+    println!("    --list-blocks        List all of the available block types");
+
+    return process::ExitCode::FAILURE;
+}
+
+fn list_blocks() -> process::ExitCode {
+    let registry = runner::default_registry();
+    for (block_type, kind) in registry.list() {
+        println!("{:<20} {}", block_type, kind);
+    }
+
+    return process::ExitCode::SUCCESS;
+}
+
+fn check(filename: &str) -> process::ExitCode {
+    let mut looper = match runner::Runner::new(filename) {
+        Ok(v) => v,
+        Err(e) => {
+            println!("{}", e);
+            return process::ExitCode::FAILURE;
+        }
+    };
+
+    // Report every problem at once.
+    let problems = looper.check();
+    println!();
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    let num_errors = problems
+        .iter()
+        .filter(|p| p.severity == check::Severity::Error)
+        .count();
+    println!("{} errors, {} warnings", num_errors, problems.len() - num_errors);
+
+    match num_errors {
+        0 => process::ExitCode::SUCCESS,
+        _ => process::ExitCode::FAILURE,
+    }
+}
+
+fn calibrate(filename: &str) -> process::ExitCode {
+    let mut looper = match runner::Runner::new(filename) {
+        Ok(v) => v,
+        Err(e) => {
+            println!("{}", e);
+            return process::ExitCode::FAILURE;
+        }
+    };
+
+    match looper.calibrate() {
+        Ok(latency) => {
+            println!("Add \"latency: {}\" to the project config to compensate for it.", latency);
+            process::ExitCode::SUCCESS
+        }
+        Err(e) => {
+            println!("Calibration failed: {}", e);
+            process::ExitCode::FAILURE
+        }
+    }
+}
+
+fn graph(filename: &str, dot_filename: &str, csv_filename: Option<&String>) -> process::ExitCode {
+    let project = match config::ProjectConfig::new(filename) {
+        Ok(v) => v,
+        Err(e) => {
+            println!("{}", e);
+            return process::ExitCode::FAILURE;
+        }
+    };
+
+    match tempo::Tempo::new(&project) {
+        Ok(tempo) => project.set_timeline(Rc::new(tempo)),
+        Err(e) => {
+            println!("{}", e);
+            return process::ExitCode::FAILURE;
+        }
+    }
+
+    let mut outputs = vec![(dot_filename, dot::graph(&project))];
+    if let Some(csv_filename) = csv_filename {
+        outputs.push((csv_filename, dot::arrangement(&project)));
+    }
+    for (out, contents) in outputs {
+        if let Err(e) = std::fs::write(out, contents) {
+            println!("Failed to write {}: {}", out, e.to_string());
+            return process::ExitCode::FAILURE;
+        }
+        println!("Wrote {}", out);
+    }
+
+    return process::ExitCode::SUCCESS;
+}
+
+fn run(
+    filename: &str,
+    render: Option<&str>,
+    audio_backend: Option<backend::Backend>,
+    practice_loop: Option<config::PracticeLoop>,
+    profile: Option<&str>,
+    count_in: Option<u32>,
+) -> Result<(), error::LooperError> {
     let mut looper = runner::Runner::new(filename)?;
-    match looper.run() {
-        Ok(v) => Ok(v),
-        Err(_) => Err("Looper failed to initialize".to_string()),
+    if let Some(audio_backend) = audio_backend {
+        looper.set_audio_backend(audio_backend);
+    }
+    if let Some(practice_loop) = practice_loop {
+        looper.set_practice_loop(practice_loop)?;
+    }
+    if let Some(profile) = profile {
+        looper.set_profile_output(profile);
+    }
+    if let Some(count_in) = count_in {
+        looper.set_count_in(count_in);
+    }
+    match render {
+        Some(out) => looper.render(out),
+        None => looper.run(),
     }
 }
 
-fn main() {
+fn main() -> process::ExitCode {
     let args: Vec<String> = env::args().collect();
-    let filename = &args[1];
+    if args.len() == 3 && args[1] == "check" {
+        return check(&args[2]);
+    }
+    if args.len() == 3 && args[1] == "calibrate" {
+        return calibrate(&args[2]);
+    }
+    if (args.len() == 4 || args.len() == 5) && args[1] == "graph" {
+        return graph(&args[2], &args[3], args.get(4));
+    }
 
-    match run(&filename) {
+    // Parse the command line.
+    let mut filename: Option<&str> = None;
+    let mut render: Option<&str> = None;
+    let mut audio_backend: Option<backend::Backend> = None;
+    let mut practice_loop: Option<config::PracticeLoop> = None;
+    let mut profile: Option<&str> = None;
+    let mut count_in: Option<u32> = None;
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--list-blocks" => {
+                return list_blocks();
+            }
+            "--render" => {
+                i += 1;
+                match args.get(i) {
+                    Some(v) => render = Some(v),
+                    None => return help(),
+                }
+            }
+            "--audio-backend" => {
+                i += 1;
+                match args.get(i).and_then(|v| backend::Backend::from_name(v)) {
+                    Some(v) => audio_backend = Some(v),
+                    None => return help(),
+                }
+            }
+            "--practice-loop" => {
+                i += 1;
+                let arg = match args.get(i) {
+                    Some(v) => v,
+                    None => return help(),
+                };
+                match config::PracticeLoop::from_arg(arg) {
+                    Ok(v) => practice_loop = Some(v),
+                    Err(e) => {
+                        println!("{}", e);
+                        return help();
+                    }
+                }
+            }
+            "--profile" => {
+                i += 1;
+                match args.get(i) {
+                    Some(v) => profile = Some(v),
+                    None => return help(),
+                }
+            }
+            "--count-in" => {
+                i += 1;
+                match args
+                    .get(i)
+                    .and_then(|v| v.parse::<u32>().ok())
+                    .filter(|v| *v > 0)
+                {
+                    Some(v) => count_in = Some(v),
+                    None => return help(),
+                }
+            }
+            arg => {
+                if filename.is_some() {
+                    return help();
+                }
+                filename = Some(arg);
+            }
+        }
+        i += 1;
+    }
+    let filename = match filename {
+        Some(v) => v,
+        None => return help(),
+    };
+
+    match run(filename, render, audio_backend, practice_loop, profile, count_in) {
         Ok(_) => {
             println!("Looper success");
+            process::ExitCode::SUCCESS
         }
         Err(e) => {
-            println!("{}", e.to_string());
+            println!("Looper failed: {}", e);
+            process::ExitCode::FAILURE
         }
-    };
+    }
 }
@@ -10,9 +10,16 @@
 //!         type: "Combiner"
 //!         input_channels: The input channel names
 //!         output_channel: The output channel name
+//!     Optional parameters:
+//!         pans: The stereo position of each input, from -1.0 (left) to 1.0 (right), in the same
+//!               order as input_channels. Defaults to 0.0 (center) for every input. Panning only
+//!               applies when the project is stereo.
 
 extern crate block;
 extern crate config;
+extern crate error;
+extern crate log;
+extern crate registry;
 extern crate stream;
 
 /// The Combiner Block.
@@ -22,6 +29,23 @@ pub struct Combiner {
 
     /// The output stream.
     output_stream: stream::Stream,
+
+    /// The number of audio channels in the output stream.
+    num_channels: usize,
+
+    /// The gain applied to each output channel, for each input stream.
+    gains: Vec<Vec<f32>>,
+}
+
+/// Get the left/right gains for a stereo position. The center leaves both channels at full volume
+/// and each side fades out the opposite channel.
+fn pan_gains(pan: f32) -> Vec<f32> {
+    vec![(1.0 - pan).min(1.0), (1.0 + pan).min(1.0)]
+}
+
+/// Register the Combiner block.
+pub fn register(registry: &mut registry::Registry) {
+    registry.register_transformer("Combiner", Combiner::new);
 }
 
 impl Combiner {
@@ -29,10 +53,19 @@ impl Combiner {
     pub fn new(
         config: &config::BlockConfig,
         stream_catalog: &mut stream::StreamCatalog,
-    ) -> Result<Self, ()> {
+    ) -> Result<Self, error::LooperError> {
         // Read in parameters.
         let input_channels = config.get_str_list("input_channels")?;
         let output_channel = config.get_str("output_channel")?;
+        let pans = config.get_f32_list_opt("pans")?;
+        log::abort_if_msg!(
+            pans.len() > input_channels.len(),
+            "Combiner has more \"pans\" than \"input_channels\""
+        );
+        log::abort_if_msg!(
+            pans.iter().any(|pan| pan.abs() > 1.0),
+            "Combiner \"pans\" must be between -1.0 and 1.0"
+        );
 
         // Load streams.
         let mut input_streams = Vec::<stream::Stream>::with_capacity(input_channels.len());
@@ -41,23 +74,74 @@ impl Combiner {
         }
         let output_stream = stream_catalog.create_source(output_channel)?;
 
+        // Panning only makes sense for stereo outputs.
+        let num_channels = stream_catalog.num_channels();
+        let gains = (0..input_streams.len())
+            .map(|i| match num_channels {
+                2 => pan_gains(pans.get(i).copied().unwrap_or(0.0)),
+                _ => Vec::new(),
+            })
+            .collect();
+
         Ok(Combiner {
             input_streams: input_streams,
             output_stream: output_stream,
+            num_channels: num_channels,
+            gains: gains,
         })
     }
-}
 
-impl block::Transformer for Combiner {
-    fn transform(&mut self, _: &block::PlaybackState) {
+    /// Sum all of the input streams into the output stream.
+    fn combine(&mut self) {
         let mut output_stream = self.output_stream.borrow_mut();
         output_stream.fill(stream::ZERO);
+        let buffer_size = output_stream.len() / self.num_channels;
 
-        for stream in &self.input_streams {
+        for (stream, gains) in self.input_streams.iter().zip(&self.gains) {
             let input_stream = stream.borrow();
-            for i in 0..stream::SAMPLES_PER_BUFFER {
-                output_stream[i] += input_stream[i];
-            }
+            let input_channels = input_stream.len() / buffer_size;
+            stream::mix(
+                &input_stream,
+                input_channels,
+                &mut output_stream,
+                self.num_channels,
+                gains,
+            );
         }
     }
 }
+
+impl block::Transformer for Combiner {
+    fn transform(&mut self, _: &block::PlaybackState) {
+        self.combine();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pan() {
+        let project = config::ProjectConfig::new("dat/combiner/pan.yaml").unwrap();
+        let mut stream_catalog = stream::StreamCatalog::with_format(44100, 2, 2);
+        let left = stream_catalog
+            .create_source_with_channels("left", 1)
+            .unwrap();
+        let center = stream_catalog
+            .create_source_with_channels("center", 1)
+            .unwrap();
+        let stereo = stream_catalog.create_source("stereo").unwrap();
+
+        let mut combiner = Combiner::new(&project.blocks[0], &mut stream_catalog).unwrap();
+        let output_stream = stream_catalog.bind_sink("mix").unwrap();
+
+        left.borrow_mut().copy_from_slice(&[1.0, 2.0]);
+        center.borrow_mut().copy_from_slice(&[0.5, 0.5]);
+        stereo.borrow_mut().copy_from_slice(&[0.25, 0.5, 0.25, 0.5]);
+        combiner.combine();
+
+        // Fully left, centered, and half right with both stereo channels passing through.
+        assert_eq!(*output_stream.borrow(), vec![1.625, 0.5 + 0.5, 2.625, 0.5 + 0.5]);
+    }
+}
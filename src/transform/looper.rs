@@ -4,6 +4,15 @@
 //! subsequent output segments. If multiple input streams are provided, this block implicitly
 //! combines them into the recording.
 //!
+//! Inputs captured from audio hardware arrive late by the hardware latency, so they're shifted
+//! earlier in the recording to line up with the rest of the project. The end of the recording is
+//! filled in as the late audio arrives, while the loop is already playing. The loop itself plays
+//! on time, so blocks which record it again don't shift it a second time.
+//!
+//! If the playhead jumps back to before the end of the input segment, the loop is recorded again.
+//! If it jumps into an output segment, the loop picks up partway through as if it had been playing
+//! all along.
+//!
 //! Loop \[Transformer\]:
 //!     Required parameters:
 //!         name: Anything
@@ -18,7 +27,9 @@
 
 extern crate block;
 extern crate config;
+extern crate error;
 extern crate log;
+extern crate registry;
 extern crate sampler;
 extern crate segment;
 extern crate stream;
@@ -32,9 +43,15 @@ pub struct Looper {
     /// The input streams.
     input_streams: Vec<stream::Stream>,
 
+    /// The latency of each input stream.
+    latencies: Vec<stream::Latency>,
+
     /// The output streams.
     output_stream: stream::Stream,
 
+    /// The number of audio channels in the recording.
+    num_channels: usize,
+
     /// The clip used for recording and playing back the stream.
     recording: stream::Clip,
 
@@ -50,11 +67,28 @@ pub struct Looper {
     /// Whether or not the recording is complete.
     recording_complete: bool,
 
+    /// Whether the recording has started.
+    is_recording: bool,
+
+    /// The length of the complete recording [frames].
+    recording_length: usize,
+
+    /// The number of frames of late input still to be added to the end of the recording.
+    tail_frames: usize,
+
     /// The current interval index.
     cur_interval: usize,
 
     /// Whether we are currently playing something.
     is_playing: bool,
+
+    /// Whether the recording came from clip_override instead of the inputs.
+    is_override: bool,
+}
+
+/// Register the Loop block.
+pub fn register(registry: &mut registry::Registry) {
+    registry.register_transformer("Loop", Looper::new);
 }
 
 impl Looper {
@@ -62,7 +96,7 @@ impl Looper {
     pub fn new(
         config: &config::BlockConfig,
         stream_catalog: &mut stream::StreamCatalog,
-    ) -> Result<Self, ()> {
+    ) -> Result<Self, error::LooperError> {
         // Read in parameters.
         let input_channels = config.get_str_list("input_channels")?;
         let output_channel = config.get_str("output_channel")?;
@@ -71,12 +105,17 @@ impl Looper {
 
         // Load streams. Load inputs first so that we don't accidentally bind to ourself.
         let mut input_streams = Vec::<stream::Stream>::with_capacity(input_channels.len());
+        let mut latencies = Vec::<stream::Latency>::with_capacity(input_channels.len());
         for channel in &input_channels {
             input_streams.push(stream_catalog.bind_sink(channel)?);
+            latencies.push(stream_catalog.bind_latency(channel));
         }
         let output_stream = stream_catalog.create_source(output_channel)?;
         output_stream.borrow_mut().fill(stream::ZERO);
 
+        // The recording is shifted back onto the timeline, so the loop plays on time.
+        stream_catalog.set_captured(output_channel, Some(0));
+
         // Load segments and sort into their respective buckets.
         let mut recording_segment_opt: Option<segment::Segment> = None;
         let mut playback_segments = Vec::<segment::Segment>::with_capacity(segments.len() - 1);
@@ -106,9 +145,11 @@ impl Looper {
         }
 
         // Create the clip/sampler.
+        let num_channels = stream_catalog.num_channels();
         let recording: stream::Clip;
         if clip_override != "" {
-            recording = wav::read_wav_file(clip_override)?;
+            recording =
+                wav::read_wav_file(clip_override, stream_catalog.sample_rate(), num_channels)?;
         } else {
             recording = stream::empty_clip();
         }
@@ -125,51 +166,89 @@ impl Looper {
         Ok(Looper {
             name: config.name.to_owned(),
             input_streams: input_streams,
+            latencies: latencies,
             output_stream: output_stream,
+            num_channels: num_channels,
             recording: recording,
             sampler: sampler,
             recording_segment: recording_segment,
             playback_segments: playback_segments,
             recording_complete: clip_override != "",
+            is_recording: false,
+            recording_length: 0,
+            tail_frames: 0,
             cur_interval: 0,
             is_playing: false,
+            is_override: clip_override != "",
         })
     }
+
+    /// Add the next buffer of every input to the recording, shifted earlier by its latency.
+    ///
+    /// # Arguments
+    ///
+    /// * position - Where the start of the buffer lands in the recording [frames]. This is
+    ///              negative if the recording starts partway through the buffer.
+    /// * buffer_size - The number of frames in each input.
+    /// * max_frames - The maximum length of the recording [frames].
+    fn record(&mut self, position: i64, buffer_size: usize, max_frames: usize) {
+        let mut recording = self.recording.borrow_mut();
+        for (input_stream, latency) in self.input_streams.iter().zip(&self.latencies) {
+            let stream = input_stream.borrow();
+            stream::mix_into_clip(
+                &mut recording,
+                self.num_channels,
+                position - latency.get() as i64,
+                &stream,
+                stream.len() / buffer_size,
+                max_frames,
+            );
+        }
+    }
 }
 
 impl block::Transformer for Looper {
     fn transform(&mut self, state: &block::PlaybackState) {
         let tempo = state.tempo;
-        let cur_measure = tempo.current_measure();
+        let buffer_start = tempo.current_sample;
+        let buffer_end = buffer_start + tempo.buffer_size as i64;
+
+        // Where the start of this buffer lands in the recording [frames].
+        let recording_start = tempo.measure_sample(self.recording_segment.start);
+        let position = buffer_start - recording_start;
+
+        // Keep adding the late inputs to the end of the recording until they catch up.
+        if self.tail_frames > 0 {
+            self.record(position, tempo.buffer_size, self.recording_length);
+            self.tail_frames = self.tail_frames.saturating_sub(tempo.buffer_size);
+        }
 
         //  -- RECORDING PHASE -- //
         if !self.recording_complete {
-            // Determine whether we're in the recording interval.
-            if self.recording_segment.start <= cur_measure
-                && cur_measure < self.recording_segment.stop
-            {
-                let mut recording = self.recording.borrow_mut();
-                if recording.is_empty() {
-                    println!("Loop recording started: {}", self.name);
-                }
-
-                // Resize the recording clip.
-                let start_index = recording.len();
-                recording.resize(start_index + stream::SAMPLES_PER_BUFFER, stream::ZERO);
+            let recording_stop = tempo.measure_sample(self.recording_segment.stop);
 
-                // Add the input streams to the new extended portion of the recording.
-                for input_stream in &self.input_streams {
-                    let stream = input_stream.borrow();
-                    for i in 0..stream::SAMPLES_PER_BUFFER {
-                        recording[start_index + i] += stream[i];
-                    }
+            // Record every buffer which overlaps the recording interval. The first one usually
+            // starts before the interval does.
+            if recording_start < buffer_end && buffer_start < recording_stop {
+                if !self.is_recording {
+                    println!("Loop recording started: {}", self.name);
+                    self.is_recording = true;
                 }
+                self.record(position, tempo.buffer_size, usize::MAX);
             }
 
-            // Mark the recording complete if we're past the recording interval.
-            if cur_measure >= self.recording_segment.stop {
+            // Mark the recording complete once the recording interval ends, so that the loop can
+            // start playing in the same buffer. The late inputs haven't reached the end of the
+            // recording yet, so leave room for them.
+            if buffer_end >= recording_stop {
                 println!("Loop recording complete: {}", self.name);
                 self.recording_complete = true;
+                self.is_recording = false;
+                self.recording_length = (recording_stop - recording_start).max(0) as usize;
+                self.tail_frames = self.latencies.iter().map(|l| l.get()).max().unwrap_or(0);
+                self.recording
+                    .borrow_mut()
+                    .resize(self.recording_length * self.num_channels, stream::ZERO);
             }
         }
 
@@ -181,12 +260,12 @@ impl block::Transformer for Looper {
                 let segment = &self.playback_segments[next_interval];
 
                 // We're waiting for the next segment. Break without playing.
-                if cur_measure < segment.start {
+                if buffer_end <= tempo.measure_sample(segment.start) {
                     break;
                 }
 
-                // We're in the current segment. Break and play.
-                if cur_measure < segment.stop {
+                // The current segment overlaps this buffer. Break and play.
+                if buffer_start < tempo.measure_sample(segment.stop) {
                     should_play = true;
                     break;
                 }
@@ -195,10 +274,24 @@ impl block::Transformer for Looper {
                 next_interval += 1;
             }
 
-            // We have entered a new interval. Start the sample from the beginning.
+            // We have entered a new interval. Start the loop on the exact sample where the interval
+            // starts, which is usually partway through this buffer, or pick it up from wherever it
+            // would have been if the playhead just jumped into the middle of the interval.
             if should_play && (next_interval != self.cur_interval || !self.is_playing) {
                 println!("Playing loop: {}", self.name);
-                self.sampler.play(&self.recording, true);
+                let start = tempo.measure_sample(self.playback_segments[next_interval].start);
+                if start >= buffer_start {
+                    let offset = (start - buffer_start) as usize;
+                    self.sampler
+                        .play_at(&self.recording, true, offset * self.num_channels);
+                } else {
+                    self.sampler.play(&self.recording, true);
+                    let len = self.recording.borrow().len();
+                    if len > 0 {
+                        let frames = (buffer_start - start) as usize;
+                        self.sampler.skip(frames * self.num_channels % len);
+                    }
+                }
             }
             // If we shouldn't play at all, stop the sampler.
             else if !should_play {
@@ -218,4 +311,140 @@ impl block::Transformer for Looper {
             self.is_playing = should_play;
         }
     }
+
+    fn seek(&mut self, state: &block::PlaybackState) {
+        let tempo = state.tempo;
+        let cur_measure = tempo.current_measure();
+
+        // Record the loop again if the playhead jumped back before the end of the recording. Any
+        // part of the interval which was skipped is left silent.
+        if !self.is_override && cur_measure < self.recording_segment.stop {
+            self.recording.borrow_mut().clear();
+            self.recording_complete = false;
+            self.is_recording = false;
+            self.recording_length = 0;
+        }
+        self.tail_frames = 0;
+
+        // Work out what to play from scratch on the next cycle.
+        self.sampler.stop();
+        self.cur_interval = 0;
+        self.is_playing = false;
+        self.output_stream.borrow_mut().fill(stream::ZERO);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use block::Transformer;
+
+    /// Run a Looper from measure 0 until a measure, and collect everything it plays on each pass
+    /// of the practice loop, if there is one. The input counts up by one every sample and by a
+    /// million every pass, so every sample of the loop says where and when it was recorded.
+    fn run_looper(
+        filename: &str,
+        until: f32,
+        practice_loop: Option<config::PracticeLoop>,
+    ) -> (tempo::Tempo, Vec<Vec<stream::Sample>>) {
+        let project = config::ProjectConfig::new(filename).unwrap();
+        let mut tempo = tempo::Tempo::new(&project).unwrap();
+        let keyboard = keyboard::Keyboard::detached();
+        let mut stream_catalog = stream::StreamCatalog::with_format(44100, tempo.buffer_size, 1);
+        let input = stream_catalog.create_source("input").unwrap();
+        let mut looper = Looper::new(&project.blocks[0], &mut stream_catalog).unwrap();
+        let output = stream_catalog.bind_sink("output").unwrap();
+
+        let mut passes = vec![vec![]];
+        while tempo.current_measure() < until {
+            let pass = passes.len() - 1;
+            for (i, sample) in input.borrow_mut().iter_mut().enumerate() {
+                *sample =
+                    (tempo.current_sample + i as i64 + pass as i64 * 1000000) as stream::Sample;
+            }
+            let state = block::PlaybackState {
+                tempo: &tempo,
+                keyboard: &keyboard,
+            };
+            looper.transform(&state);
+            passes[pass].extend_from_slice(&output.borrow());
+
+            let previous_measure = tempo.current_measure();
+            tempo.step(1);
+            if let Some(practice) = &practice_loop {
+                if previous_measure < practice.stop
+                    && tempo.current_measure() >= practice.stop
+                    && practice.repeats.map_or(true, |r| passes.len() < r as usize)
+                {
+                    tempo.seek(practice.start);
+                    looper.seek(&block::PlaybackState {
+                        tempo: &tempo,
+                        keyboard: &keyboard,
+                    });
+                    passes.push(vec![]);
+                }
+            }
+        }
+        (tempo, passes)
+    }
+
+    #[test]
+    fn test_loop_timing() {
+        let (tempo, passes) = run_looper("dat/looper/timing.yaml", 13.0, None);
+        let played = &passes[0];
+
+        // 4/4 at 100bpm is 105840 samples per measure, which isn't a whole number of buffers, so
+        // the recording and every repetition start partway through a buffer.
+        let length = tempo.samples_between(1.0, 2.0);
+        assert_eq!(length, 105840);
+        let recording_start = tempo.measure_sample(1.0) as usize;
+        let start = tempo.measure_sample(2.0) as usize;
+        let stop = tempo.measure_sample(12.0) as usize;
+
+        // Silence until the loop starts, then every repetition exactly one loop after the last,
+        // with no gaps.
+        assert!(played[..start].iter().all(|s| *s == 0.0));
+        for i in start..stop {
+            let expected = recording_start + (i - start) % length;
+            assert_eq!(played[i], expected as stream::Sample, "sample {}", i);
+        }
+    }
+
+    #[test]
+    fn test_seek() {
+        let practice_loop = config::PracticeLoop::from_arg("3:5:2").unwrap();
+
+        // A loop recorded before the practice loop keeps playing the same recording on every pass.
+        let (tempo, passes) =
+            run_looper("dat/looper/seek_before.yaml", 5.0, Some(practice_loop.clone()));
+        assert_eq!(passes.len(), 2);
+        let length = tempo.samples_between(1.0, 2.0) as usize;
+        let recording_start = tempo.measure_sample(1.0) as usize;
+        let loop_start = tempo.measure_sample(2.0) as usize;
+        let start = tempo.measure_sample(3.0) as usize;
+        let stop = tempo.measure_sample(5.0) as usize;
+        for i in start..stop {
+            let expected = recording_start + (i - loop_start) % length;
+            assert_eq!(passes[0][i], expected as stream::Sample, "sample {}", i);
+            assert_eq!(passes[1][i - start], expected as stream::Sample, "sample {}", i);
+        }
+
+        // A loop recorded inside the practice loop is recorded again on every pass.
+        let (tempo, passes) = run_looper("dat/looper/seek_inside.yaml", 5.0, Some(practice_loop));
+        assert_eq!(passes.len(), 2);
+        let start = tempo.measure_sample(3.0) as usize;
+        let loop_start = tempo.measure_sample(4.0) as usize;
+        let stop = tempo.measure_sample(5.0) as usize;
+        assert!(passes[1][..loop_start - start].iter().all(|s| *s == 0.0));
+        for i in loop_start..stop {
+            let expected = start + (i - loop_start);
+            assert_eq!(passes[0][i], expected as stream::Sample, "sample {}", i);
+            assert_eq!(
+                passes[1][i - start],
+                (expected + 1000000) as stream::Sample,
+                "sample {}",
+                i
+            );
+        }
+    }
 }
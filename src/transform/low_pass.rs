@@ -1,6 +1,6 @@
 //! Low Pass Filter Block.
 //!
-//! Filters out signals above a cutoff frequency.
+//! Filters out signals above a cutoff frequency. Each audio channel is filtered separately.
 //!
 //! LowPass \[Transformer\]:
 //!     Required parameters:
@@ -12,7 +12,9 @@
 
 extern crate block;
 extern crate config;
+extern crate error;
 extern crate log;
+extern crate registry;
 extern crate stream;
 
 use std::str::FromStr;
@@ -33,20 +35,49 @@ pub struct LowPass {
     /// The denominator filter coefficients.
     denominator: Vec<f32>,
 
-    /// The last N input history, where N is the filter order.
-    in_history: Vec<Sample>,
+    /// The number of audio channels in the output stream.
+    num_channels: usize,
 
-    /// The last N output history, where N is the filter order.
-    out_history: Vec<Sample>,
+    /// The input converted to the number of audio channels in the output stream.
+    input_buffer: stream::RawStream,
+
+    /// The last N input history for each channel, where N is the filter order.
+    in_history: Vec<Vec<Sample>>,
+
+    /// The last N output history for each channel, where N is the filter order.
+    out_history: Vec<Vec<Sample>>,
 
     /// The ring buffer index into the history vector.
     ring_index: i32,
 }
 
+/// The sample rate the filter lookup table was generated at [samples/s].
+const TABLE_SAMPLE_RATE: i32 = 44100;
+
 /// Consult a lookup table to get the filter coefficients.
-fn get_filter_coefficients(freq: f32) -> Result<(Vec<f32>, Vec<f32>), ()> {
+///
+/// # Arguments
+///
+/// * freq - The cutoff frequency.
+/// * sample_rate - The sample rate of the filtered stream.
+fn get_filter_coefficients(
+    freq: f32,
+    sample_rate: i32,
+) -> Result<(Vec<f32>, Vec<f32>), error::LooperError> {
     let table_path = "assets/filters/low_pass.txt";
-    let contents = log::unwrap_abort!(std::fs::read_to_string(table_path));
+
+    // The coefficients only depend on the cutoff relative to the sample rate, so look up the
+    // cutoff that is equivalent at the table's sample rate.
+    let freq = freq * TABLE_SAMPLE_RATE as f32 / sample_rate as f32;
+    let contents = match std::fs::read_to_string(table_path) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(error::LooperError::File {
+                path: table_path.to_owned(),
+                cause: e.to_string(),
+            });
+        }
+    };
 
     let mut numerator: Vec<f32> = Vec::<f32>::new();
     let mut state: i32 = 0;
@@ -73,7 +104,12 @@ fn get_filter_coefficients(freq: f32) -> Result<(Vec<f32>, Vec<f32>), ()> {
         }
     }
 
-    Err(())
+    log::abort_msg!(format!("No filter coefficients for a cutoff of {}Hz", freq));
+}
+
+/// Register the LowPass block.
+pub fn register(registry: &mut registry::Registry) {
+    registry.register_transformer("LowPass", LowPass::new);
 }
 
 impl LowPass {
@@ -81,7 +117,7 @@ impl LowPass {
     pub fn new(
         config: &config::BlockConfig,
         stream_catalog: &mut stream::StreamCatalog,
-    ) -> Result<Self, ()> {
+    ) -> Result<Self, error::LooperError> {
         // Read in parameters.
         let input_channel = config.get_str("input_channel")?;
         let output_channel = config.get_str("output_channel")?;
@@ -92,43 +128,63 @@ impl LowPass {
         let output_stream = stream_catalog.create_source(output_channel)?;
 
         // Load the filter.
-        let (numerator, denominator) = log::unwrap_abort_msg!(
-            get_filter_coefficients(freq),
-            format!("No filter found for freq={}", freq)
-        );
+        let (numerator, denominator) = get_filter_coefficients(freq, stream_catalog.sample_rate())?;
         let order = numerator.len();
+        let num_channels = stream_catalog.num_channels();
 
         Ok(LowPass {
             input_stream: input_stream,
             output_stream: output_stream,
             numerator: numerator,
             denominator: denominator,
-            in_history: vec![0f32; order],
-            out_history: vec![0f32; order],
+            num_channels: num_channels,
+            input_buffer: stream::RawStream::new(),
+            in_history: vec![vec![0f32; order]; num_channels],
+            out_history: vec![vec![0f32; order]; num_channels],
             ring_index: 0i32,
         })
     }
 
     /// Apply the filter.
     fn filter(&mut self) {
-        let input_stream = self.input_stream.borrow();
         let mut output_stream = self.output_stream.borrow_mut();
         let order = self.numerator.len() as i32;
+        let num_frames = output_stream.len() / self.num_channels;
 
-        for m in 0..stream::SAMPLES_PER_BUFFER {
-            // First term
-            output_stream[m] = self.numerator[0] * input_stream[m];
+        // Match the input to the output channels so that each channel can be filtered in place.
+        {
+            let input_stream = self.input_stream.borrow();
+            self.input_buffer.clear();
+            self.input_buffer.resize(output_stream.len(), stream::ZERO);
+            stream::mix(
+                &input_stream,
+                input_stream.len() / num_frames,
+                &mut self.input_buffer,
+                self.num_channels,
+                &[],
+            );
+        }
 
-            // Next N-1 terms
-            for i in 1..order as usize {
-                let prev_index = (self.ring_index - i as i32).rem_euclid(order) as usize;
-                output_stream[m] += self.denominator[i] * self.out_history[prev_index]
-                    + self.numerator[i] * self.in_history[prev_index];
-            }
+        for frame in 0..num_frames {
+            for c in 0..self.num_channels {
+                let m = frame * self.num_channels + c;
+                let in_history = &mut self.in_history[c];
+                let out_history = &mut self.out_history[c];
+
+                // First term
+                output_stream[m] = self.numerator[0] * self.input_buffer[m];
 
-            // Update the historical ring buffer.
-            self.in_history[self.ring_index as usize] = input_stream[m];
-            self.out_history[self.ring_index as usize] = output_stream[m];
+                // Next N-1 terms
+                for i in 1..order as usize {
+                    let prev_index = (self.ring_index - i as i32).rem_euclid(order) as usize;
+                    output_stream[m] += self.denominator[i] * out_history[prev_index]
+                        + self.numerator[i] * in_history[prev_index];
+                }
+
+                // Update the historical ring buffer.
+                in_history[self.ring_index as usize] = self.input_buffer[m];
+                out_history[self.ring_index as usize] = output_stream[m];
+            }
             self.ring_index = (self.ring_index + 1) % order;
         }
     }
@@ -142,7 +198,7 @@ impl block::Transformer for LowPass {
 
 #[cfg(test)]
 mod tests {
-    use stream::SAMPLES_PER_BUFFER;
+    use stream::DEFAULT_BUFFER_SIZE;
 
     use super::*;
 
@@ -190,7 +246,7 @@ mod tests {
         // Validate the output.
         {
             let s = output_stream.borrow();
-            for i in 0..SAMPLES_PER_BUFFER {
+            for i in 0..DEFAULT_BUFFER_SIZE {
                 // This tolerance is pretty high but an iterative algorithm in a different
                 // language is bound to diverge by a bit over 256 iterations.
                 log::assert_approx_eq!(s[i], output[i], 0.1);
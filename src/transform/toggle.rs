@@ -12,7 +12,9 @@
 
 extern crate block;
 extern crate config;
+extern crate error;
 extern crate log;
+extern crate registry;
 extern crate segment;
 extern crate stream;
 
@@ -28,12 +30,17 @@ pub struct Toggle {
     segments: Vec<segment::Segment>,
 }
 
+/// Register the Toggle block.
+pub fn register(registry: &mut registry::Registry) {
+    registry.register_transformer("Toggle", Toggle::new);
+}
+
 impl Toggle {
     /// Create a new Toggle.
     pub fn new(
         config: &config::BlockConfig,
         stream_catalog: &mut stream::StreamCatalog,
-    ) -> Result<Self, ()> {
+    ) -> Result<Self, error::LooperError> {
         // Read in parameters.
         let input_channel = config.get_str("input_channel")?;
         let output_channel = config.get_str("output_channel")?;
@@ -64,14 +71,20 @@ impl block::Transformer for Toggle {
         let tempo = state.tempo;
 
         let mut output_stream = self.output_stream.borrow_mut();
+        output_stream.fill(stream::ZERO);
         for segment in &self.segments {
             if tempo.in_measure(segment.start, segment.stop) {
                 let input_stream = self.input_stream.borrow();
-                output_stream.clone_from_slice(&input_stream[..]);
+                let output_channels = output_stream.len() / tempo.buffer_size;
+                stream::mix(
+                    &input_stream,
+                    input_stream.len() / tempo.buffer_size,
+                    &mut output_stream,
+                    output_channels,
+                    &[],
+                );
                 return;
             }
         }
-
-        output_stream.fill(stream::ZERO);
     }
 }
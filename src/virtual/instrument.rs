@@ -15,7 +15,8 @@
 //! Instrument Configuration
 //!
 //! Instruments are configured a list of sounds. Each sound has:
-//!     - Key: the key to press on the keyboard.
+//!     - Key: the key to press on the keyboard. Transport keys (space, "[", "]" and "0") are
+//!            reserved for controlling playback and never reach the instrument.
 //!     - File: The wav file sound to play when the key is pressed.
 //!     - Group: The sound group. Sounds in different groups can be played independently.
 //!              Two sounds in the same group will interrupt one another when played.
@@ -33,7 +34,9 @@
 
 extern crate block;
 extern crate config;
+extern crate error;
 extern crate log;
+extern crate registry;
 extern crate sampler;
 extern crate stream;
 extern crate tempo;
@@ -70,20 +73,33 @@ pub struct VirtualInstrument {
 fn load_instrument_from_file(
     instrument_type: &str,
     volume: f32,
-) -> Result<HashMap<char, Sound>, ()> {
+    sample_rate: i32,
+    num_channels: usize,
+) -> Result<HashMap<char, Sound>, error::LooperError> {
     let filename = config::instrument_path(instrument_type);
-    let config = &log::unwrap_abort_msg!(
-        config::read_yaml_file(filename.as_str()),
-        format!("Invalid instrument \"{}\" (tried to load from: {})", instrument_type, filename)
-    )[0];
+    let config = &match config::read_yaml_file(filename.as_str()) {
+        Ok(v) => v,
+        Err(e) => {
+            println!("Invalid instrument \"{}\": {}", instrument_type, e);
+            return Err(error::LooperError::File {
+                path: filename,
+                cause: e,
+            });
+        }
+    }[0];
     log::abort_if!(config.is_badvalue());
 
     let sounds = &config["sounds"];
-    load_instrument(sounds, volume)
+    load_instrument(sounds, volume, sample_rate, num_channels)
 }
 
 /// Load an instrument from Yaml as a map of clips.
-fn load_instrument(sounds: &Yaml, volume: f32) -> Result<HashMap<char, Sound>, ()> {
+fn load_instrument(
+    sounds: &Yaml,
+    volume: f32,
+    sample_rate: i32,
+    num_channels: usize,
+) -> Result<HashMap<char, Sound>, error::LooperError> {
     log::abort_if!(!sounds.is_array());
 
     // Load the audio clips into memory.
@@ -100,7 +116,7 @@ fn load_instrument(sounds: &Yaml, volume: f32) -> Result<HashMap<char, Sound>, (
 
         // Load the clip and the sampler.
         let clip_path = config::clip_path(clip_name);
-        let clip = log::unwrap_abort!(wav::read_wav_file(clip_path.as_str()));
+        let clip = wav::read_wav_file(clip_path.as_str(), sample_rate, num_channels)?;
 
         // Scale the volume of the clip.
         clip.borrow_mut().scale(volume);
@@ -117,12 +133,17 @@ fn load_instrument(sounds: &Yaml, volume: f32) -> Result<HashMap<char, Sound>, (
     Ok(clips)
 }
 
+/// Register the VirtualInstrument block.
+pub fn register(registry: &mut registry::Registry) {
+    registry.register_source("VirtualInstrument", VirtualInstrument::new);
+}
+
 impl VirtualInstrument {
     /// Construct a new VirtualInstrument block.
     pub fn new(
         config: &config::BlockConfig,
         stream_catalog: &mut stream::StreamCatalog,
-    ) -> Result<Self, ()> {
+    ) -> Result<Self, error::LooperError> {
         // Read in config parameters
         let output_channel = config.get_str("output_channel")?;
         let instrument_type = config.get_str_opt("instrument", "")?;
@@ -132,15 +153,17 @@ impl VirtualInstrument {
         let stream = stream_catalog.create_source(output_channel)?;
 
         // Load the instrument configuration.
+        let sample_rate = stream_catalog.sample_rate();
+        let num_channels = stream_catalog.num_channels();
         let clips = match instrument_type {
             "" => {
                 let sounds = log::unwrap_abort_msg!(
                     config.get_value("sounds"),
                     "Must specify either \"instrument\" or \"sounds\""
                 );
-                load_instrument(sounds, volume)?
+                load_instrument(sounds, volume, sample_rate, num_channels)?
             }
-            name => load_instrument_from_file(name, volume)?,
+            name => load_instrument_from_file(name, volume, sample_rate, num_channels)?,
         };
 
         // Load the sampler groups.
@@ -182,6 +205,13 @@ impl block::Source for VirtualInstrument {
             sampler.next(&mut stream);
         }
     }
+
+    fn seek(&mut self, _state: &block::PlaybackState) {
+        // Cut off any notes that were still ringing.
+        for sampler in self.samplers.values_mut() {
+            sampler.stop();
+        }
+    }
 }
 
 #[cfg(test)]
@@ -191,7 +221,8 @@ mod tests {
     #[test]
     fn test_load_instrument() {
         // This should unwrap.
-        let clips = load_instrument_from_file("drums1", 1.0).unwrap();
+        let clips =
+            load_instrument_from_file("drums1", 1.0, stream::DEFAULT_SAMPLE_RATE, 1).unwrap();
 
         // Grab all of the keys/clips.
         for key in ['a', 's', 'd', 'f', 'g'] {
@@ -205,7 +236,7 @@ mod tests {
     #[test]
     fn test_load_instrument_fail() {
         // This should not unwrap.
-        match load_instrument_from_file("invalid", 1.0) {
+        match load_instrument_from_file("invalid", 1.0, stream::DEFAULT_SAMPLE_RATE, 1) {
             Ok(_) => {
                 panic!("Instrument should be invalid");
             }
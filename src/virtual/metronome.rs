@@ -1,6 +1,8 @@
 //! Metronome Block.
 //!
-//! The metronome provides a steady tick on every beat. The 'tick' sound is configurable.
+//! The metronome provides a steady tick on every pulse of the bpm (see tempo::Tempo::beat_unit), or
+//! on every subdivision of the pulse. The 'tick' sound is configurable, and the first tick of every
+//! measure can be accented with a different sound, following any changes of time signature.
 //!
 //! Metronome \[Source\]:
 //!     Required parameters:
@@ -11,13 +13,21 @@
 //!         output_channel: The output channel name
 //!     Optional parameters:
 //!         sound: The sound to play on each beat. Defaults to "hihat-closed1".
+//!         accent_sound: The sound to play on the first beat of each measure. Defaults to `sound`.
+//!         subdivisions: The number of ticks in each pulse, e.g. 3 to tick every eighth note in
+//!                       6/8 with a dotted quarter note beat_unit. Defaults to 1.
+//!         groove: The name of a groove template to shift the subdivisions by.
+//!         swing: How far to delay every second subdivision, as a fraction of a subdivision.
+//!                Only one of groove and swing can be given. Defaults to the project groove.
 //!         volume: The volume of the metronome tick as a floating point multiplier.
 //!         segments: A list of "output" segments for which the metronome is active. If this
 //!                   parameter is not supplied, the metronome will default to always-on.
 
 extern crate block;
 extern crate config;
+extern crate error;
 extern crate log;
+extern crate registry;
 extern crate sampler;
 extern crate segment;
 extern crate stream;
@@ -34,11 +44,28 @@ pub struct Metronome {
     // The clip to play.
     clip: stream::Clip,
 
+    /// The clip to play on the first beat of each measure.
+    accent_clip: stream::Clip,
+
     /// The sampler.
     sampler: sampler::Sampler,
 
     /// The active segments.
     segments: Vec<segment::Segment>,
+
+    /// The number of ticks in each pulse.
+    subdivisions: u32,
+
+    /// The groove to follow instead of the project groove, if any.
+    groove: Option<config::Groove>,
+
+    /// The number of audio channels in the output stream.
+    num_channels: usize,
+}
+
+/// Register the Metronome block.
+pub fn register(registry: &mut registry::Registry) {
+    registry.register_source("Metronome", Metronome::new);
 }
 
 impl Metronome {
@@ -46,27 +73,40 @@ impl Metronome {
     pub fn new(
         config: &config::BlockConfig,
         stream_catalog: &mut stream::StreamCatalog,
-    ) -> Result<Self, ()> {
+    ) -> Result<Self, error::LooperError> {
         // Read in parameters.
         let output_stream = config.get_str("output_channel")?;
         let sound = config.get_str_opt("sound", "hihat-closed1")?;
+        let accent_sound = config.get_str_opt("accent_sound", sound)?;
         let volume = config.get_f32_opt("volume", &1.0)?;
+        let subdivisions = config.get_i32_opt("subdivisions", &1)?;
+        let groove = config.get_groove_opt()?;
         let segments = config.get_segments()?;
 
         // Load streams.
         let stream = stream_catalog.create_source(output_stream)?;
 
-        // Load in the clip to play.
-        let filename = config::clip_path(sound);
-        let clip = log::unwrap_abort_msg!(
-            wav::read_wav_file(&filename),
-            format!("Failed to find clip {} at {}", sound, filename)
-        );
-        clip.borrow_mut().scale(volume);
+        // Load in the clips to play.
+        let load_clip = |sound: &str| -> Result<stream::Clip, error::LooperError> {
+            let clip = wav::read_wav_file(
+                &config::clip_path(sound),
+                stream_catalog.sample_rate(),
+                stream_catalog.num_channels(),
+            )?;
+            clip.borrow_mut().scale(volume);
+            Ok(clip)
+        };
+        let clip = load_clip(sound)?;
+        let accent_clip = match accent_sound == sound {
+            true => clip.clone(),
+            false => load_clip(accent_sound)?,
+        };
 
         // Load the sampler.
         let sampler = sampler::Sampler::new();
 
+        log::abort_if_msg!(subdivisions <= 0, "Metronome \"subdivisions\" must be positive");
+
         // Validate the segments.
         for segment in &segments {
             log::abort_if_msg!(
@@ -78,8 +118,12 @@ impl Metronome {
         Ok(Metronome {
             stream: stream,
             clip: clip,
+            accent_clip: accent_clip,
             sampler: sampler,
             segments: segments,
+            subdivisions: subdivisions as u32,
+            groove: groove,
+            num_channels: stream_catalog.num_channels(),
         })
     }
 }
@@ -88,25 +132,34 @@ impl block::Source for Metronome {
     fn read(&mut self, state: &block::PlaybackState) {
         let tempo = state.tempo;
 
-        if self.segments.is_empty() {
-            // If no segments are present, assume the metronome is always on.
-            if tempo.on_beat(0) {
-                self.sampler.play(&self.clip, false);
-            }
-        } else {
-            // Otherwise, play the metronome only in an active segment.
-            for segment in &self.segments {
-                if tempo.in_measure(segment.start, segment.stop) {
-                    if tempo.on_beat(0) {
-                        self.sampler.play(&self.clip, false);
-                        break;
-                    }
-                }
-            }
+        // If no segments are present, assume the metronome is always on. Otherwise, play the
+        // metronome only in an active segment.
+        let active = self.segments.is_empty()
+            || self
+                .segments
+                .iter()
+                .any(|segment| tempo.in_measure(segment.start, segment.stop));
+        let pulse_offset = match &self.groove {
+            Some(groove) => tempo.grooved_pulse_offset(0, self.subdivisions, groove),
+            None => tempo.pulse_offset(0, self.subdivisions),
+        };
+        if let (true, Some(offset)) = (active, pulse_offset) {
+            // The tick usually starts partway through the buffer.
+            let clip = match tempo.measure_offset(0).is_some() {
+                true => &self.accent_clip,
+                false => &self.clip,
+            };
+            self.sampler
+                .play_at(clip, false, offset * self.num_channels);
         }
 
         let mut stream = self.stream.borrow_mut();
         stream.fill(stream::ZERO);
         self.sampler.next(&mut stream);
     }
+
+    fn seek(&mut self, _state: &block::PlaybackState) {
+        // Don't let a click ring on across the jump.
+        self.sampler.stop();
+    }
 }